@@ -1,14 +1,19 @@
 use anyhow::Result;
 
 use rustsynth::{
+    constants::{BUFFER_LEN, SAMPLE_RATE},
     host::Host,
     midi::MidiEvents,
     midi::MidiInput,
     midi::MidiPoly,
     midi::MidiSlider,
     midi::MidiSliderSettings,
+    midi::SliderCurve,
+    midi::SliderSource,
+    midi::VoiceStealMode,
     modules::Envelope,
     modules::EnvelopeSettings,
+    modules::RetriggerMode,
     modules::{Op, OpType, Oscillator, OscillatorSettings},
 };
 
@@ -21,74 +26,90 @@ fn main() {
 fn run() -> Result<()> {
     let start = std::time::Instant::now();
 
-    let mut host = Host::new()?;
+    let mut host = Host::new(SAMPLE_RATE, BUFFER_LEN)?;
 
-    let midi = host.create_module::<MidiInput>("midi", 0)?;
+    let midi = host.create_module::<MidiInput>("midi", 0.into())?;
 
     let fmod_pitch_slider = host.create_module::<MidiSlider>(
         "fmod_pitch_slider",
         MidiSliderSettings {
-            controller: 41,
+            source: SliderSource::Controller(41),
+            curve: SliderCurve::Linear,
             default: 1.0,
             min: 0.0,
             max: 8.0,
+            smoothing_ms: 0.0,
+            high_resolution: false,
         },
     )?;
-    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(fmod_pitch_slider, "in")?);
+    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(fmod_pitch_slider, "in")?)?;
 
     let fmod_vol_slider = host.create_module::<MidiSlider>(
         "fmod_vol_slider",
         MidiSliderSettings {
-            controller: 42,
-            default: 64.0,
+            source: SliderSource::Controller(42),
+            curve: SliderCurve::Linear,
+            default: 0.0625,
             min: 0.0,
-            max: 128.0,
+            max: 0.125,
+            smoothing_ms: 0.0,
+            high_resolution: false,
         },
     )?;
-    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(fmod_vol_slider, "in")?);
+    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(fmod_vol_slider, "in")?)?;
 
     let carrier_atk_slider = host.create_module::<MidiSlider>(
         "carrier_atk_slider",
         MidiSliderSettings {
-            controller: 43,
+            source: SliderSource::Controller(43),
+            curve: SliderCurve::Linear,
             default: 0.0,
             min: 0.0,
             max: 1.0,
+            smoothing_ms: 0.0,
+            high_resolution: false,
         },
     )?;
-    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(carrier_atk_slider, "in")?);
+    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(carrier_atk_slider, "in")?)?;
 
     let carrier_rel_slider = host.create_module::<MidiSlider>(
         "carrier_rel_slider",
         MidiSliderSettings {
-            controller: 44,
+            source: SliderSource::Controller(44),
+            curve: SliderCurve::Linear,
             default: 0.0,
             min: 0.0,
             max: 1.7,
+            smoothing_ms: 0.0,
+            high_resolution: false,
         },
     )?;
-    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(carrier_rel_slider, "in")?);
+    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(carrier_rel_slider, "in")?)?;
 
     let carrier_vol_slider = host.create_module::<MidiSlider>(
         "carrier_vol_slider",
         MidiSliderSettings {
-            controller: 7,
+            source: SliderSource::Controller(7),
+            curve: SliderCurve::Linear,
             default: 0.5,
             min: 0.0,
             max: 1.0,
+            smoothing_ms: 0.0,
+            high_resolution: false,
         },
     )?;
-    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(carrier_vol_slider, "in")?);
+    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(carrier_vol_slider, "in")?)?;
 
     let group = host.create_group("group", 16, None)?;
 
-    let voices = host.create_group_joining_module::<MidiPoly>(group, "voices", ())?;
-    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(voices.ungrouped(), "in")?);
+    let voices =
+        host.create_group_joining_module::<MidiPoly>(group, "voices", VoiceStealMode::Oldest)?;
+    host.link::<MidiEvents>(host.buf(midi, "out")?, host.buf(voices.ungrouped(), "in")?)?;
 
     let fmod_osc = host.create_group_instance_module::<Oscillator>(
         group,
         "fmod_osc",
-        &OscillatorSettings::Square,
+        &OscillatorSettings::Square.into(),
     )?;
     host.link_group::<MidiEvents>(
         &host.group_joining_buf(voices, "out")?,
@@ -97,7 +118,7 @@ fn run() -> Result<()> {
     host.link_group_ext::<f32>(
         host.buf(fmod_pitch_slider, "out")?,
         &host.group_instance_buf(&fmod_osc, "pitch_shift")?,
-    );
+    )?;
 
     let fmod_envelope = host.create_group_instance_module::<Envelope>(
         group,
@@ -107,6 +128,8 @@ fn run() -> Result<()> {
             decay: 5.0,
             sustain: 0.6,
             release: 0.2,
+            retrigger_mode: RetriggerMode::FromCurrent,
+            ..Default::default()
         },
     )?;
     host.link_group::<MidiEvents>(
@@ -116,11 +139,11 @@ fn run() -> Result<()> {
     host.link_group_ext::<f32>(
         host.buf(carrier_atk_slider, "out")?,
         &host.group_instance_buf(&fmod_envelope, "attack")?,
-    );
+    )?;
     host.link_group_ext::<f32>(
         host.buf(carrier_rel_slider, "out")?,
         &host.group_instance_buf(&fmod_envelope, "release")?,
-    );
+    )?;
     host.link_group::<f32>(
         &host.group_instance_buf(&fmod_osc, "out")?,
         &host.group_instance_buf(&fmod_envelope, "in")?,
@@ -135,21 +158,21 @@ fn run() -> Result<()> {
     host.link_group_ext::<f32>(
         host.buf(fmod_vol_slider, "out")?,
         &host.group_instance_variadic_buf(&fmod_amp, "in")?.at(1)?,
-    );
+    )?;
 
     let carrier_osc = host.create_group_instance_module::<Oscillator>(
         group,
         "carrier_osc",
-        &OscillatorSettings::Sine(1024),
+        &OscillatorSettings::Sine(1024).into(),
     )?;
     host.link_group::<MidiEvents>(
         &host.group_joining_buf(voices, "out")?,
         &host.group_instance_buf(&carrier_osc, "in")?,
     )?;
-    host.link_group_value::<f32>(0.2, &host.group_instance_buf(&carrier_osc, "vel_amt")?);
+    host.link_group_value::<f32>(0.2, &host.group_instance_buf(&carrier_osc, "vel_amt")?)?;
     host.link_group::<f32>(
         &host.group_instance_buf(&fmod_amp, "out")?,
-        &host.group_instance_buf(&carrier_osc, "freq_mod")?,
+        &host.group_instance_buf(&carrier_osc, "phase_mod")?,
     )?;
 
     let carrier_envelope = host.create_group_instance_module::<Envelope>(
@@ -160,6 +183,8 @@ fn run() -> Result<()> {
             decay: 1.0,
             sustain: 0.6,
             release: 0.6,
+            retrigger_mode: RetriggerMode::FromCurrent,
+            ..Default::default()
         },
     )?;
     host.link_group::<MidiEvents>(
@@ -169,11 +194,11 @@ fn run() -> Result<()> {
     host.link_group_ext::<f32>(
         host.buf(carrier_atk_slider, "out")?,
         &host.group_instance_buf(&carrier_envelope, "attack")?,
-    );
+    )?;
     host.link_group_ext::<f32>(
         host.buf(carrier_rel_slider, "out")?,
         &host.group_instance_buf(&carrier_envelope, "release")?,
-    );
+    )?;
     host.link_group::<f32>(
         &host.group_instance_buf(&carrier_osc, "out")?,
         &host.group_instance_buf(&carrier_envelope, "in")?,
@@ -189,19 +214,20 @@ fn run() -> Result<()> {
     host.link::<f32>(
         host.buf(mixer.ungrouped(), "out")?,
         host.variadic_buf(carrier_amp, "in")?.at(0)?,
-    );
+    )?;
     host.link::<f32>(
         host.buf(carrier_vol_slider, "out")?,
         host.variadic_buf(carrier_amp, "in")?.at(1)?,
-    );
+    )?;
 
     host.link::<f32>(
         host.buf(carrier_amp, "out")?,
         host.buf(host.get_output_module(), "in")?,
-    );
+    )?;
 
     let dur = std::time::Instant::now().duration_since(start);
     println!("Initialized in {}s", dur.as_secs_f64());
 
+    host.start();
     host.process();
 }
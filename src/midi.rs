@@ -1,4 +1,12 @@
-use std::{convert::Infallible, sync::mpsc, time::Instant};
+use std::{
+    convert::Infallible,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::Instant,
+};
 
 use midir::{Ignore, MidiInput as MidirInput, MidiInputConnection};
 
@@ -8,12 +16,9 @@ use midly::num::*;
 
 use thiserror::Error;
 
-use crate::{
-    constants::*,
-    host::{
-        BufferHandle, BuiltModuleDescriptor, In, Module, ModuleBuffersIn, ModuleBuffersOut,
-        ModuleDescriptor, ModuleSettings, Out, VariadicBufferHandle,
-    },
+use crate::host::{
+    BufferHandle, BuiltModuleDescriptor, In, Module, ModuleBuffersIn, ModuleBuffersOut,
+    ModuleDescriptor, ModuleSettings, ModuleState, Out, StateSnapshot, VariadicBufferHandle,
 };
 
 #[derive(Debug, Clone)]
@@ -79,6 +84,130 @@ pub struct MidiInput {
     start_time: Instant,
     event_receiver: mpsc::Receiver<RawEvent>,
     event_queue: Vec<RawEvent>,
+    running_status: Option<u8>,
+    channels: Option<Vec<u4>>,
+    dropped_events: DroppedEvents,
+    sample_rate: f32,
+}
+
+// counts malformed messages skipped by a MidiInput, shared so it can be read from outside the host
+#[derive(Clone, Default)]
+pub struct DroppedEvents(Arc<AtomicUsize>);
+
+impl DroppedEvents {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MidiInputSettings {
+    pub port: usize,
+    // None accepts every channel; system and realtime events always pass through
+    pub channels: Option<Vec<u4>>,
+    pub dropped_events: DroppedEvents,
+}
+
+impl From<usize> for MidiInputSettings {
+    fn from(port: usize) -> Self {
+        Self {
+            port,
+            channels: None,
+            dropped_events: Default::default(),
+        }
+    }
+}
+
+fn split_raw_messages(bytes: &[u8], running_status: &mut Option<u8>) -> Vec<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if byte >= 0xF8 {
+            out.push(vec![byte]);
+            i += 1;
+            continue;
+        }
+        if byte == 0xF0 {
+            let end = bytes[i..]
+                .iter()
+                .position(|&b| b == 0xF7)
+                .map_or(bytes.len(), |p| i + p + 1);
+            out.push(bytes[i..end].to_vec());
+            *running_status = None;
+            i = end;
+            continue;
+        }
+
+        let (status, data_start) = if byte >= 0x80 {
+            *running_status = if byte < 0xF0 { Some(byte) } else { None };
+            (byte, i + 1)
+        } else if let Some(status) = *running_status {
+            (status, i)
+        } else {
+            i += 1;
+            continue;
+        };
+        let data_len = match status {
+            0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+            0x80..=0xEF | 0xF2 => 2,
+            _ => 0,
+        };
+        let mut message = vec![status];
+        i = data_start;
+        while message.len() <= data_len && i < bytes.len() {
+            let byte = bytes[i];
+            if byte >= 0xF8 {
+                // realtime bytes can land in the middle of another message
+                out.push(vec![byte]);
+            } else if byte >= 0x80 {
+                // a new status cuts the message short
+                break;
+            } else {
+                message.push(byte);
+            }
+            i += 1;
+        }
+        out.push(message);
+    }
+    out
+}
+
+// messages that fail to parse are counted in `dropped_events` and skipped, so one bad message
+// doesn't lose the rest of the packet
+fn parse_raw_messages(
+    bytes: &[u8],
+    running_status: &mut Option<u8>,
+    channels: Option<&[u4]>,
+    dropped_events: &DroppedEvents,
+) -> Vec<MidiEvent> {
+    let mut events = Vec::new();
+    for message in split_raw_messages(bytes, running_status) {
+        let event: MidiEvent = match message[..] {
+            // midly's live parser only reads channel and system common messages
+            [status] if status >= 0xF8 => {
+                MidiEvent::Realtime(midly::live::SystemRealtime::new(status))
+            }
+            _ => match MLiveEvent::parse(&message) {
+                Ok(event) => event.into(),
+                Err(_) => {
+                    dropped_events.0.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            },
+        };
+        if let (Some(channels), MidiEvent::Midi { channel, .. }) = (channels, &event) {
+            if !channels.contains(channel) {
+                continue;
+            }
+        }
+        events.push(event);
+    }
+    events
 }
 
 #[derive(Error, Debug)]
@@ -87,29 +216,31 @@ pub enum MidiInputError {
     InitError(#[from] midir::InitError),
     #[error(transparent)]
     ConnectError(#[from] midir::ConnectError<MidirInput>),
-    #[error("no MIDI device available on port {0}")]
-    PortError(usize),
+    #[error("MIDI port {port} out of range ({available} ports available)")]
+    PortOutOfRange { port: usize, available: usize },
 }
 
 impl ModuleSettings for MidiInput {
-    type Settings = usize;
+    type Settings = MidiInputSettings;
     type Error = MidiInputError;
 }
 
 impl Module for MidiInput {
     fn init(
         mut desc: ModuleDescriptor,
-        port_idx: usize,
+        settings: MidiInputSettings,
         _: usize,
     ) -> Result<BuiltModuleDescriptor<Self>, MidiInputError> {
+        let port_idx = settings.port;
         let mut midi_in = MidirInput::new("midir reading input")?;
         midi_in.ignore(Ignore::None);
 
         // Get an input port
         let ports = midi_in.ports();
-        let in_port = ports
-            .get(port_idx)
-            .ok_or(MidiInputError::PortError(port_idx))?;
+        let in_port = ports.get(port_idx).ok_or(MidiInputError::PortOutOfRange {
+            port: port_idx,
+            available: ports.len(),
+        })?;
 
         let (tx, rx) = mpsc::channel();
         // conn_in needs to be a named parameter, because it needs to be kept alive until the end of the scope
@@ -117,11 +248,11 @@ impl Module for MidiInput {
             in_port,
             "midir-read-input",
             move |_timestamp, message, _| {
-                tx.send(RawEvent {
+                // the receiver only goes away when the module is dropped, so there's no one to tell
+                let _ = tx.send(RawEvent {
                     time_received: Instant::now(),
                     message: message.into(),
-                })
-                .unwrap();
+                });
             },
             (),
         )?;
@@ -132,6 +263,10 @@ impl Module for MidiInput {
             start_time: Instant::now(),
             event_receiver: rx,
             event_queue: Vec::new(),
+            running_status: None,
+            channels: settings.channels,
+            dropped_events: settings.dropped_events,
+            sample_rate: desc.sample_rate() as f32,
         };
         Ok(desc.build(module))
     }
@@ -154,14 +289,18 @@ impl Module for MidiInput {
                     0.0
                 };
 
-            let event = MLiveEvent::parse(&raw.message).unwrap().into();
-
-            let idx = usize::max(0, (elapsed * SAMPLE_RATE as f32) as usize);
-            if idx >= BUFFER_LEN {
+            let idx = usize::max(0, (elapsed * self.sample_rate) as usize);
+            if idx >= buffer.len() {
                 cutoff = Some(i);
                 break;
             }
-            buffer[idx].push(event);
+
+            buffer[idx].extend(parse_raw_messages(
+                &raw.message,
+                &mut self.running_status,
+                self.channels.as_deref(),
+                &self.dropped_events,
+            ));
         }
 
         if let Some(i) = cutoff {
@@ -172,6 +311,170 @@ impl Module for MidiInput {
 
         self.start_time = start_time_new;
     }
+
+    fn reset(&mut self) {
+        self.event_receiver.try_iter().for_each(drop);
+        self.event_queue.clear();
+        self.running_status = None;
+        self.start_time = Instant::now();
+    }
+}
+
+pub struct MidiFilePlayer {
+    buf_out: BufferHandle<Out<MidiEvents>>,
+    events: Vec<(u64, MidiEvent)>,
+    length: u64,
+    looping: bool,
+    position: u64,
+    next_event: usize,
+}
+
+#[derive(Clone)]
+pub struct MidiFileSettings {
+    pub path: PathBuf,
+    pub tempo_scale: f32,
+    pub looping: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum MidiFileError {
+    #[error("failed to read MIDI file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse MIDI file: {0}")]
+    Parse(#[from] midly::Error),
+}
+
+impl ModuleSettings for MidiFilePlayer {
+    type Settings = MidiFileSettings;
+    type Error = MidiFileError;
+}
+
+const DEFAULT_TEMPO: u32 = 500_000; // microseconds per beat
+
+// returns the events with their sample offsets, plus the length of the file in samples
+fn load_midi_file(
+    settings: &MidiFileSettings,
+    sample_rate: u32,
+) -> Result<(Vec<(u64, MidiEvent)>, u64), MidiFileError> {
+    let data = std::fs::read(&settings.path)?;
+    let smf = midly::Smf::parse(&data)?;
+
+    let mut timed = Vec::new();
+    for track in smf.tracks.iter() {
+        let mut tick = 0u64;
+        for event in track.iter() {
+            tick += event.delta.as_int() as u64;
+            timed.push((tick, event.kind));
+        }
+    }
+    // stable sort keeps the original track order for simultaneous events
+    timed.sort_by_key(|(tick, _)| *tick);
+
+    let tempo_scale = if settings.tempo_scale > 0.0 {
+        settings.tempo_scale as f64
+    } else {
+        1.0
+    };
+    let mut secs_per_tick = match smf.header.timing {
+        midly::Timing::Metrical(tpb) => {
+            DEFAULT_TEMPO as f64 / 1_000_000.0 / tpb.as_int().max(1) as f64
+        }
+        midly::Timing::Timecode(fps, subframes) => {
+            1.0 / (fps.as_f32() as f64 * subframes.max(1) as f64)
+        }
+    };
+
+    let mut events = Vec::new();
+    let mut length = 0;
+    let mut last_tick = 0u64;
+    let mut secs = 0.0f64;
+    for (tick, kind) in timed {
+        secs += (tick - last_tick) as f64 * secs_per_tick;
+        last_tick = tick;
+        let sample = (secs / tempo_scale * sample_rate as f64).round() as u64;
+        length = sample + 1;
+        match kind {
+            midly::TrackEventKind::Midi { channel, message } => {
+                events.push((sample, MidiEvent::Midi { channel, message }));
+            }
+            midly::TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) => {
+                if let midly::Timing::Metrical(tpb) = smf.header.timing {
+                    secs_per_tick =
+                        tempo.as_int() as f64 / 1_000_000.0 / tpb.as_int().max(1) as f64;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((events, length))
+}
+
+impl Module for MidiFilePlayer {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: MidiFileSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, MidiFileError> {
+        let (events, length) = load_midi_file(&settings, desc.sample_rate())?;
+        let module = Self {
+            buf_out: desc.with_buf_out::<MidiEvents>("out"),
+            events,
+            length,
+            looping: settings.looping,
+            position: 0,
+            next_event: 0,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, _buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let buffer = buffers_out.get(self.buf_out);
+        for events in buffer.iter_mut() {
+            events.clear();
+            while let Some((sample, event)) = self.events.get(self.next_event) {
+                if *sample > self.position {
+                    break;
+                }
+                events.push(event.clone());
+                self.next_event += 1;
+            }
+
+            if self.position < self.length {
+                self.position += 1;
+                if self.position == self.length && self.looping {
+                    self.position = 0;
+                    self.next_event = 0;
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+        self.next_event = 0;
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+}
+
+impl StateSnapshot for MidiFilePlayer {
+    type State = (u64, usize);
+
+    fn snapshot(&self) -> (u64, usize) {
+        (self.position, self.next_event)
+    }
+
+    fn restore(&mut self, state: &(u64, usize)) {
+        let (position, next_event) = *state;
+        self.position = position;
+        self.next_event = next_event;
+    }
 }
 
 pub struct MidiSlider {
@@ -180,14 +483,54 @@ pub struct MidiSlider {
     settings: MidiSliderSettings,
     range: f32,
     current_val: f32,
+    target_val: f32,
+    smoothing_coeff: f32,
+    msb: u8,
+}
+
+#[derive(Clone, Copy)]
+pub enum SliderSource {
+    Controller(u8),
+    PitchBend,
+    ChannelPressure,
+    PolyPressure(u8),
+}
+
+impl Default for SliderSource {
+    fn default() -> Self {
+        Self::Controller(0)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum SliderCurve {
+    #[default]
+    Linear,
+    Exponential,
+    Logarithmic,
+}
+
+const SLIDER_CURVATURE: f32 = 4.0;
+
+impl SliderCurve {
+    fn apply(self, x: f32) -> f32 {
+        match self {
+            Self::Linear => x,
+            Self::Exponential => (x * SLIDER_CURVATURE).exp_m1() / SLIDER_CURVATURE.exp_m1(),
+            Self::Logarithmic => (x * SLIDER_CURVATURE.exp_m1()).ln_1p() / SLIDER_CURVATURE,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct MidiSliderSettings {
-    pub controller: u8,
+    pub source: SliderSource,
+    pub curve: SliderCurve,
     pub default: f32,
     pub min: f32,
     pub max: f32,
+    pub smoothing_ms: f32,
+    pub high_resolution: bool,
 }
 
 impl ModuleSettings for MidiSlider {
@@ -205,7 +548,14 @@ impl Module for MidiSlider {
             midi_in: desc.with_buf_in::<MidiEvents>("in"),
             signal_out: desc.with_buf_out::<f32>("out"),
             current_val: settings.default,
+            target_val: settings.default,
             range: settings.max - settings.min,
+            smoothing_coeff: if settings.smoothing_ms > 0.0 {
+                1.0 - (-1.0 / (desc.sample_rate() as f32 * settings.smoothing_ms / 1000.0)).exp()
+            } else {
+                1.0
+            },
+            msb: 0,
             settings,
         };
         Ok(desc.build(module))
@@ -217,118 +567,2206 @@ impl Module for MidiSlider {
             .iter()
             .zip(buffers_out.get(self.signal_out).iter_mut())
         {
-            let mut new_value: Option<u8> = None;
+            let mut new_value: Option<f32> = None;
             for event in midi.iter() {
-                if let MidiEvent::Midi {
-                    message: midly::MidiMessage::Controller { controller, value },
-                    ..
-                } = event
-                {
-                    if self.settings.controller == controller.as_int() {
-                        new_value = Some(value.as_int());
+                if let MidiEvent::Midi { message, .. } = event {
+                    match (self.settings.source, message) {
+                        (
+                            SliderSource::Controller(source),
+                            midly::MidiMessage::Controller { controller, value },
+                        ) if source == controller.as_int() => {
+                            if self.settings.high_resolution {
+                                // an MSB on its own resets the LSB to zero
+                                self.msb = value.as_int();
+                                new_value = Some((self.msb as u16 * 128) as f32 / 16383.0);
+                            } else {
+                                new_value = Some(value.as_int() as f32 / 127.0);
+                            }
+                        }
+                        (
+                            SliderSource::Controller(source),
+                            midly::MidiMessage::Controller { controller, value },
+                        ) if self.settings.high_resolution
+                            && source < 32
+                            && source + 32 == controller.as_int() =>
+                        {
+                            new_value = Some(
+                                (self.msb as u16 * 128 + value.as_int() as u16) as f32 / 16383.0,
+                            );
+                        }
+                        (SliderSource::PitchBend, midly::MidiMessage::PitchBend { bend }) => {
+                            new_value = Some(bend.0.as_int() as f32 / 16383.0);
+                        }
+                        (
+                            SliderSource::ChannelPressure,
+                            midly::MidiMessage::ChannelAftertouch { vel },
+                        ) => {
+                            new_value = Some(vel.as_int() as f32 / 127.0);
+                        }
+                        (
+                            SliderSource::PolyPressure(source),
+                            midly::MidiMessage::Aftertouch { key, vel },
+                        ) if source == key.as_int() => {
+                            new_value = Some(vel.as_int() as f32 / 127.0);
+                        }
+                        _ => {}
                     }
                 }
             }
 
             if let Some(new_value) = new_value {
-                self.current_val = ((new_value as f32) / 128.0) * self.range + self.settings.min;
+                let new_value = self.settings.curve.apply(new_value);
+                let (lo, hi) = if self.settings.min <= self.settings.max {
+                    (self.settings.min, self.settings.max)
+                } else {
+                    (self.settings.max, self.settings.min)
+                };
+                self.target_val = (new_value * self.range + self.settings.min).max(lo).min(hi);
             }
 
+            if self.smoothing_coeff < 1.0 {
+                self.current_val += (self.target_val - self.current_val) * self.smoothing_coeff;
+            } else {
+                self.current_val = self.target_val;
+            }
             *out = self.current_val;
         }
     }
+
+    fn reset(&mut self) {
+        self.current_val = self.settings.default;
+        self.target_val = self.settings.default;
+        self.msb = 0;
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
 }
 
-pub struct MidiPoly {
-    num_ports: usize,
-    notes: Vec<(u8, MidiEvent)>,
+impl StateSnapshot for MidiSlider {
+    type State = (f32, f32, u8);
+
+    fn snapshot(&self) -> (f32, f32, u8) {
+        (self.current_val, self.target_val, self.msb)
+    }
+
+    fn restore(&mut self, state: &(f32, f32, u8)) {
+        let (current_val, target_val, msb) = *state;
+        self.current_val = current_val;
+        self.target_val = target_val;
+        self.msb = msb;
+    }
+}
+
+pub struct Aftertouch {
     midi_in: BufferHandle<In<MidiEvents>>,
-    midi_out: Vec<BufferHandle<Out<MidiEvents>>>,
-    midi_out_variadic: VariadicBufferHandle<Out<MidiEvents>>,
+    signal_out: BufferHandle<Out<f32>>,
+    key: Option<u8>,
+    value: f32,
 }
 
-#[derive(Error, Debug)]
-#[error("MidiPoly must have at least one input buffer")]
-pub struct MidiPolyError;
+#[derive(Clone, Default)]
+pub struct AftertouchSettings {
+    // None follows channel pressure, Some(key) follows poly aftertouch for that key
+    pub key: Option<u8>,
+}
 
-impl ModuleSettings for MidiPoly {
-    type Settings = ();
-    type Error = MidiPolyError;
+impl ModuleSettings for Aftertouch {
+    type Settings = AftertouchSettings;
+    type Error = Infallible;
 }
 
-impl Module for MidiPoly {
+impl Module for Aftertouch {
     fn init(
         mut desc: ModuleDescriptor,
-        _settings: (),
-        num_ports: usize,
-    ) -> Result<BuiltModuleDescriptor<Self>, MidiPolyError> {
-        if num_ports == 0 {
-            return Err(MidiPolyError);
-        }
-
-        let midi_out = desc.with_variadic_buf_out::<MidiEvents>("out");
+        settings: AftertouchSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
         let module = Self {
-            num_ports,
-            notes: Default::default(),
             midi_in: desc.with_buf_in::<MidiEvents>("in"),
-            midi_out: midi_out.all().collect(),
-            midi_out_variadic: midi_out,
+            signal_out: desc.with_buf_out::<f32>("out"),
+            key: settings.key,
+            value: 0.0,
         };
         Ok(desc.build(module))
     }
 
     fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
-        for buffer in buffers_out.get_iter(self.midi_out_variadic) {
-            for events in buffer {
-                events.clear();
+        for (midi, out) in buffers_in
+            .get(self.midi_in)
+            .iter()
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            for event in midi.iter() {
+                if let MidiEvent::Midi { message, .. } = event {
+                    match (self.key, message) {
+                        (None, midly::MidiMessage::ChannelAftertouch { vel }) => {
+                            self.value = vel.as_int() as f32 / 127.0;
+                        }
+                        (Some(source), midly::MidiMessage::Aftertouch { key, vel })
+                            if source == key.as_int() =>
+                        {
+                            self.value = vel.as_int() as f32 / 127.0;
+                        }
+                        _ => {}
+                    }
+                }
             }
+            *out = self.value;
         }
+    }
 
-        for (i, events) in buffers_in.get(self.midi_in).iter().enumerate() {
-            for event in events {
+    fn reset(&mut self) {
+        self.value = 0.0;
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+}
+
+impl StateSnapshot for Aftertouch {
+    type State = f32;
+
+    fn snapshot(&self) -> f32 {
+        self.value
+    }
+
+    fn restore(&mut self, state: &f32) {
+        self.value = *state;
+    }
+}
+
+// largest usable pitch-bend deadzone; anything wider would leave no range to bend through
+const MAX_BEND_DEADZONE: f32 = 0.999;
+
+// Maps a pitch-bend message to [-1, 1] semitones. Bends within `deadzone` of center read as
+// zero and the rest is rescaled so a full bend still reaches the ends of the range.
+pub(crate) fn bend_semitones(bend: midly::PitchBend, deadzone: f32) -> f32 {
+    let deadzone = if deadzone.is_nan() {
+        0.0
+    } else {
+        deadzone.clamp(0.0, MAX_BEND_DEADZONE)
+    };
+    let bend = (bend.0.as_int() as i32 - 0x2000) as f32 / (0x2000 as f32);
+    if bend.abs() <= deadzone {
+        0.0
+    } else {
+        bend.signum() * (bend.abs() - deadzone) / (1.0 - deadzone)
+    }
+}
+
+// one-pole glide towards the latest bend, `smoothing` being the time constant in seconds
+pub(crate) fn smooth_bend(bend: f32, target: f32, smoothing: f32, sample_time: f32) -> f32 {
+    if smoothing > 0.0 {
+        bend + (target - bend) * (1.0 - (-sample_time / smoothing).exp())
+    } else {
+        target
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct NoteToFreqState {
+    held: Vec<u8>,
+    semitone: f32,
+    target: f32,
+    bend: f32,
+    bend_target: f32,
+    global_tuning: f32,
+}
+
+pub struct NoteToFreq {
+    midi_in: BufferHandle<In<MidiEvents>>,
+    glide: BufferHandle<In<f32>>,
+    semitone_offset: BufferHandle<In<f32>>,
+    bend_deadzone: BufferHandle<In<f32>>,
+    bend_smoothing: BufferHandle<In<f32>>,
+    freq_out: BufferHandle<Out<f32>>,
+    gate_out: BufferHandle<Out<f32>>,
+    state: NoteToFreqState,
+    sample_rate: f32,
+}
+
+impl ModuleSettings for NoteToFreq {
+    type Settings = ();
+    type Error = Infallible;
+}
+
+impl Module for NoteToFreq {
+    fn init(
+        mut desc: ModuleDescriptor,
+        _settings: (),
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            midi_in: desc.with_buf_in::<MidiEvents>("in"),
+            glide: desc.with_buf_in_default::<f32>("glide", 0.0),
+            semitone_offset: desc.with_buf_in_default::<f32>("semitone_offset", 0.0),
+            bend_deadzone: desc.with_buf_in_default::<f32>("bend_deadzone", 0.0),
+            bend_smoothing: desc.with_buf_in_default::<f32>("bend_smoothing", 0.0),
+            freq_out: desc.with_buf_out::<f32>("freq"),
+            gate_out: desc.with_buf_out::<f32>("gate"),
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let midi_in = buffers_in.get(self.midi_in);
+        let glide = buffers_in.get(self.glide);
+        let semitone_offset = buffers_in.get(self.semitone_offset);
+        let bend_deadzone = buffers_in.get(self.bend_deadzone);
+        let bend_smoothing = buffers_in.get(self.bend_smoothing);
+        let [freq_out, gate_out] = buffers_out.get_many([self.freq_out, self.gate_out]);
+
+        for i in 0..midi_in.len() {
+            for event in midi_in[i].iter() {
                 if let MidiEvent::Midi { message, .. } = event {
-                    match message {
-                        midly::MidiMessage::NoteOn { key, .. } => {
+                    match *message {
+                        midly::MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
                             let key = key.as_int();
-                            if self.notes.iter().all(|(n, _)| *n != key) {
-                                let free_buf = self
-                                    .midi_out
-                                    .remove(self.notes.len().min(self.num_ports - 1));
-                                buffers_out.get(free_buf)[i].push(event.clone());
-                                self.midi_out.insert(0, free_buf);
-                                self.notes.insert(0, (key, event.clone()));
+                            let first = self.state.held.is_empty();
+                            self.state.held.retain(|&k| k != key);
+                            self.state.held.push(key);
+                            self.state.target = (key as i16 - 69) as f32;
+                            if first {
+                                self.state.semitone = self.state.target;
                             }
                         }
-                        midly::MidiMessage::NoteOff { key, .. } => {
+                        midly::MidiMessage::NoteOn { key, .. }
+                        | midly::MidiMessage::NoteOff { key, .. } => {
                             let key = key.as_int();
-                            match self.notes.iter().position(|(n, _)| *n == key) {
-                                Some(idx) => {
-                                    self.notes.remove(idx);
-                                    if idx < self.num_ports {
-                                        let old_buf = self.midi_out.remove(idx);
-                                        self.midi_out.push(old_buf);
-                                        buffers_out.get(old_buf)[i].push(
-                                            if let Some((_, on_event)) =
-                                                self.notes.get(self.num_ports - 1)
-                                            {
-                                                on_event.clone()
-                                            } else {
-                                                event.clone()
-                                            },
-                                        );
-                                    }
-                                }
-                                None => {}
+                            self.state.held.retain(|&k| k != key);
+                            // fall back to the most recently held note
+                            if let Some(&last) = self.state.held.last() {
+                                self.state.target = (last as i16 - 69) as f32;
                             }
                         }
-                        _ => {
-                            for buf_out in buffers_out.get_iter(self.midi_out_variadic) {
-                                buf_out[i].push(event.clone())
-                            }
+                        midly::MidiMessage::PitchBend { bend } => {
+                            self.state.bend_target = bend_semitones(bend, bend_deadzone[i]);
                         }
+                        _ => (),
                     }
                 }
             }
+
+            if glide[i] > 0.0 {
+                self.state.semitone += (self.state.target - self.state.semitone)
+                    * (1.0 - (-sample_time / glide[i]).exp());
+            } else {
+                self.state.semitone = self.state.target;
+            }
+            self.state.bend = smooth_bend(
+                self.state.bend,
+                self.state.bend_target,
+                bend_smoothing[i],
+                sample_time,
+            );
+
+            freq_out[i] = ((self.state.semitone
+                + self.state.bend
+                + semitone_offset[i]
+                + self.state.global_tuning)
+                / 12.0)
+                .exp2()
+                * 440.0;
+            gate_out[i] = if self.state.held.is_empty() { 0.0 } else { 1.0 };
         }
     }
+
+    fn reset(&mut self) {
+        self.state = NoteToFreqState {
+            global_tuning: self.state.global_tuning,
+            ..Default::default()
+        };
+    }
+
+    fn set_global_tuning(&mut self, semitones: f32) {
+        self.state.global_tuning = semitones;
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+}
+
+impl StateSnapshot for NoteToFreq {
+    type State = NoteToFreqState;
+
+    fn snapshot(&self) -> NoteToFreqState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &NoteToFreqState) {
+        self.state = state.clone();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ArpMode {
+    Up,
+    Down,
+    UpDown,
+    Random,
+}
+
+#[derive(Clone)]
+pub struct ArpSettings {
+    pub mode: ArpMode,
+    pub rate_hz: f32,
+    pub octaves: u8,
+}
+
+#[derive(Clone)]
+pub struct ArpState {
+    held: Vec<(u8, u7)>,
+    channel: u4,
+    playing: Option<u8>,
+    phase: f32,
+    step: usize,
+    rng: u32,
+}
+
+pub struct Arpeggiator {
+    midi_in: BufferHandle<In<MidiEvents>>,
+    rate_in: BufferHandle<In<f32>>,
+    midi_out: BufferHandle<Out<MidiEvents>>,
+    mode: ArpMode,
+    octaves: u8,
+    state: ArpState,
+    sample_rate: f32,
+}
+
+impl ArpState {
+    fn new() -> Self {
+        Self {
+            held: Vec::new(),
+            channel: u4::from(0),
+            playing: None,
+            phase: 0.0,
+            step: 0,
+            rng: 0x9E37_79B9,
+        }
+    }
+
+    fn next_random(&mut self) -> u32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng
+    }
+}
+
+impl ModuleSettings for Arpeggiator {
+    type Settings = ArpSettings;
+    type Error = Infallible;
+}
+
+impl Arpeggiator {
+    // held keys in ascending order, repeated an octave up for each extra octave
+    fn sequence(&self) -> Vec<(u8, u7)> {
+        let mut base = self.state.held.clone();
+        base.sort_by_key(|(key, _)| *key);
+        (0..self.octaves.max(1))
+            .flat_map(|octave| {
+                base.iter()
+                    .map(move |&(key, vel)| (key as u16 + 12 * octave as u16, vel))
+            })
+            .filter(|&(key, _)| key <= 127)
+            .map(|(key, vel)| (key as u8, vel))
+            .collect()
+    }
+
+    fn next_note(&mut self) -> Option<(u8, u7)> {
+        let sequence = self.sequence();
+        let len = sequence.len();
+        if len == 0 {
+            return None;
+        }
+        let step = self.state.step;
+        self.state.step += 1;
+        let idx = match self.mode {
+            ArpMode::Up => step % len,
+            ArpMode::Down => len - 1 - step % len,
+            ArpMode::UpDown => {
+                if len == 1 {
+                    0
+                } else {
+                    let pos = step % (2 * len - 2);
+                    if pos < len {
+                        pos
+                    } else {
+                        2 * len - 2 - pos
+                    }
+                }
+            }
+            ArpMode::Random => self.state.next_random() as usize % len,
+        };
+        Some(sequence[idx])
+    }
+
+    fn note_off(&mut self, events: &mut MidiEvents) {
+        if let Some(key) = self.state.playing.take() {
+            events.push(MidiEvent::Midi {
+                channel: self.state.channel,
+                message: midly::MidiMessage::NoteOff {
+                    key: u7::from(key),
+                    vel: u7::from(0),
+                },
+            });
+        }
+    }
+}
+
+impl Module for Arpeggiator {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: ArpSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            midi_in: desc.with_buf_in::<MidiEvents>("in"),
+            rate_in: desc.with_buf_in_default::<f32>("rate", settings.rate_hz),
+            midi_out: desc.with_buf_out::<MidiEvents>("out"),
+            mode: settings.mode,
+            octaves: settings.octaves,
+            state: ArpState::new(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let midi_in = buffers_in.get(self.midi_in);
+        let rate_in = buffers_in.get(self.rate_in);
+        let midi_out = buffers_out.get(self.midi_out);
+
+        for i in 0..midi_out.len() {
+            midi_out[i].clear();
+            let was_empty = self.state.held.is_empty();
+            for event in midi_in[i].iter() {
+                if let MidiEvent::Midi { channel, message } = event {
+                    match *message {
+                        midly::MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            let key = key.as_int();
+                            self.state.channel = *channel;
+                            self.state.held.retain(|(k, _)| *k != key);
+                            self.state.held.push((key, vel));
+                        }
+                        midly::MidiMessage::NoteOn { key, .. }
+                        | midly::MidiMessage::NoteOff { key, .. } => {
+                            let key = key.as_int();
+                            self.state.held.retain(|(k, _)| *k != key);
+                        }
+                        _ => midi_out[i].push(event.clone()),
+                    }
+                }
+            }
+
+            if self.state.held.is_empty() {
+                self.note_off(&mut midi_out[i]);
+                self.state.step = 0;
+                self.state.phase = 0.0;
+                continue;
+            }
+
+            // a new chord starts playing immediately rather than waiting for the next tick
+            if was_empty {
+                self.state.phase = 1.0;
+            }
+            if self.state.phase >= 1.0 {
+                self.state.phase -= self.state.phase.floor();
+                self.note_off(&mut midi_out[i]);
+                if let Some((key, vel)) = self.next_note() {
+                    midi_out[i].push(MidiEvent::Midi {
+                        channel: self.state.channel,
+                        message: midly::MidiMessage::NoteOn {
+                            key: u7::from(key),
+                            vel,
+                        },
+                    });
+                    self.state.playing = Some(key);
+                }
+            }
+            self.state.phase += rate_in[i].max(0.0) * sample_time;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = ArpState::new();
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+}
+
+impl StateSnapshot for Arpeggiator {
+    type State = ArpState;
+
+    fn snapshot(&self) -> ArpState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &ArpState) {
+        self.state = state.clone();
+    }
+}
+
+const SEQUENCER_VELOCITY: u8 = 100;
+const DEFAULT_GATE_LENGTH: f32 = 0.5;
+
+// steps advance at `rate_hz` and on every rising edge of the `clock` input; set the rate to zero
+// to follow the clock alone
+#[derive(Clone)]
+pub struct SequencerSettings {
+    pub steps: Vec<Option<u8>>,
+    pub rate_hz: f32,
+    // fraction of a step each note is held for, missing steps use DEFAULT_GATE_LENGTH
+    pub gate_lengths: Vec<f32>,
+}
+
+#[derive(Clone)]
+pub struct SequencerState {
+    step: usize,
+    phase: f32,
+    clock_high: bool,
+    playing: Option<u8>,
+    samples_since_step: u32,
+    step_len: u32,
+    gate_len: Option<u32>,
+}
+
+impl SequencerState {
+    fn new() -> Self {
+        Self {
+            step: 0,
+            // the first step plays immediately
+            phase: 1.0,
+            clock_high: false,
+            playing: None,
+            samples_since_step: 0,
+            step_len: 0,
+            gate_len: None,
+        }
+    }
+}
+
+pub struct StepSequencer {
+    rate_in: BufferHandle<In<f32>>,
+    clock_in: BufferHandle<In<f32>>,
+    midi_out: BufferHandle<Out<MidiEvents>>,
+    steps: Vec<Option<u8>>,
+    gate_lengths: Vec<f32>,
+    state: SequencerState,
+    sample_rate: f32,
+}
+
+impl ModuleSettings for StepSequencer {
+    type Settings = SequencerSettings;
+    type Error = Infallible;
+}
+
+impl StepSequencer {
+    fn note_off(&mut self, events: &mut MidiEvents) {
+        if let Some(key) = self.state.playing.take() {
+            events.push(MidiEvent::Midi {
+                channel: u4::from(0),
+                message: midly::MidiMessage::NoteOff {
+                    key: u7::from(key),
+                    vel: u7::from(0),
+                },
+            });
+        }
+    }
+
+    fn advance(&mut self, events: &mut MidiEvents) {
+        self.note_off(events);
+        if self.steps.is_empty() {
+            return;
+        }
+        let idx = self.state.step % self.steps.len();
+        self.state.step = idx + 1;
+        if let Some(key) = self.steps[idx] {
+            let key = key.min(127);
+            events.push(MidiEvent::Midi {
+                channel: u4::from(0),
+                message: midly::MidiMessage::NoteOn {
+                    key: u7::from(key),
+                    vel: u7::from(SEQUENCER_VELOCITY),
+                },
+            });
+            self.state.playing = Some(key);
+            let gate = self
+                .gate_lengths
+                .get(idx)
+                .copied()
+                .unwrap_or(DEFAULT_GATE_LENGTH)
+                .max(0.0);
+            // until a step length is known the note is held through to the next step
+            self.state.gate_len = if self.state.step_len > 0 {
+                Some((gate * self.state.step_len as f32) as u32)
+            } else {
+                None
+            };
+        }
+    }
+}
+
+impl Module for StepSequencer {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: SequencerSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            rate_in: desc.with_buf_in_default::<f32>("rate", settings.rate_hz),
+            clock_in: desc.with_buf_in::<f32>("clock"),
+            midi_out: desc.with_buf_out::<MidiEvents>("out"),
+            steps: settings.steps,
+            gate_lengths: settings.gate_lengths,
+            state: SequencerState::new(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let rate_in = buffers_in.get(self.rate_in);
+        let clock_in = buffers_in.get(self.clock_in);
+        let midi_out = buffers_out.get(self.midi_out);
+
+        for i in 0..midi_out.len() {
+            midi_out[i].clear();
+
+            let clock_high = clock_in[i] > 0.0;
+            let mut advance = clock_high && !self.state.clock_high;
+            self.state.clock_high = clock_high;
+
+            let rate = rate_in[i].max(0.0);
+            if rate > 0.0 {
+                if self.state.phase >= 1.0 {
+                    self.state.phase -= self.state.phase.floor();
+                    advance = true;
+                }
+                self.state.phase += rate * sample_time;
+            }
+
+            if advance {
+                self.state.step_len = if rate > 0.0 {
+                    (self.sample_rate / rate) as u32
+                } else if self.state.step > 0 {
+                    self.state.samples_since_step
+                } else {
+                    0
+                };
+                self.state.samples_since_step = 0;
+                self.advance(&mut midi_out[i]);
+            } else if let Some(gate_len) = self.state.gate_len {
+                if self.state.samples_since_step >= gate_len {
+                    self.note_off(&mut midi_out[i]);
+                    self.state.gate_len = None;
+                }
+            }
+            self.state.samples_since_step = self.state.samples_since_step.saturating_add(1);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = SequencerState::new();
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+}
+
+impl StateSnapshot for StepSequencer {
+    type State = SequencerState;
+
+    fn snapshot(&self) -> SequencerState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &SequencerState) {
+        self.state = state.clone();
+    }
+}
+
+pub struct MidiPoly {
+    num_ports: usize,
+    steal_mode: VoiceStealMode,
+    notes: Vec<(u8, MidiEvent)>,
+    sustain: bool,
+    sustained: Vec<(u8, MidiEvent)>,
+    midi_in: BufferHandle<In<MidiEvents>>,
+    midi_out: Vec<BufferHandle<Out<MidiEvents>>>,
+    midi_out_variadic: VariadicBufferHandle<Out<MidiEvents>>,
+}
+
+const SUSTAIN_CONTROLLER: u8 = 64;
+
+#[derive(Clone, Copy, Default)]
+pub enum VoiceStealMode {
+    #[default]
+    Oldest,
+    Quietest,
+    Lowest,
+    Highest,
+    None,
+}
+
+#[derive(Clone)]
+pub struct MidiPolyState {
+    notes: Vec<(u8, MidiEvent)>,
+    sustain: bool,
+    sustained: Vec<(u8, MidiEvent)>,
+    midi_out: Vec<BufferHandle<Out<MidiEvents>>>,
+}
+
+impl StateSnapshot for MidiPoly {
+    type State = MidiPolyState;
+
+    fn snapshot(&self) -> MidiPolyState {
+        MidiPolyState {
+            notes: self.notes.clone(),
+            sustain: self.sustain,
+            sustained: self.sustained.clone(),
+            midi_out: self.midi_out.clone(),
+        }
+    }
+
+    fn restore(&mut self, state: &MidiPolyState) {
+        self.notes.clone_from(&state.notes);
+        self.sustain = state.sustain;
+        self.sustained.clone_from(&state.sustained);
+        self.midi_out.clone_from(&state.midi_out);
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("MidiPoly must have at least one input buffer")]
+pub struct MidiPolyError;
+
+impl ModuleSettings for MidiPoly {
+    type Settings = VoiceStealMode;
+    type Error = MidiPolyError;
+}
+
+impl Module for MidiPoly {
+    fn init(
+        mut desc: ModuleDescriptor,
+        steal_mode: VoiceStealMode,
+        num_ports: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, MidiPolyError> {
+        if num_ports == 0 {
+            return Err(MidiPolyError);
+        }
+
+        let midi_out = desc.with_variadic_buf_out::<MidiEvents>("out");
+        let module = Self {
+            num_ports,
+            steal_mode,
+            notes: Default::default(),
+            sustain: false,
+            sustained: Default::default(),
+            midi_in: desc.with_buf_in::<MidiEvents>("in"),
+            midi_out: midi_out.all().collect(),
+            midi_out_variadic: midi_out,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        for buffer in buffers_out.get_iter(self.midi_out_variadic) {
+            for events in buffer {
+                events.clear();
+            }
+        }
+
+        for (i, events) in buffers_in.get(self.midi_in).iter().enumerate() {
+            for event in events {
+                if let MidiEvent::Midi { message, .. } = event {
+                    match message {
+                        midly::MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            let key = key.as_int();
+                            // re-pressing a sustained key keeps it held after the pedal lifts
+                            self.sustained.retain(|(n, _)| *n != key);
+                            if self.notes.iter().all(|(n, _)| *n != key) {
+                                if self.notes.len() >= self.num_ports {
+                                    match self.steal_victim() {
+                                        Some(idx) => self.steal(idx, buffers_out, i),
+                                        None => {
+                                            // wait for a voice to free up rather than steal one
+                                            self.notes.insert(self.num_ports, (key, event.clone()));
+                                            continue;
+                                        }
+                                    }
+                                }
+                                let free_buf = self
+                                    .midi_out
+                                    .remove(self.notes.len().min(self.num_ports - 1));
+                                buffers_out.get(free_buf)[i].push(event.clone());
+                                self.midi_out.insert(0, free_buf);
+                                self.notes.insert(0, (key, event.clone()));
+                            }
+                        }
+                        midly::MidiMessage::NoteOn { key, .. }
+                        | midly::MidiMessage::NoteOff { key, .. } => {
+                            let key = key.as_int();
+                            if self.sustain {
+                                if self.notes.iter().any(|(n, _)| *n == key) {
+                                    self.sustained.push((key, event.clone()));
+                                }
+                            } else {
+                                self.release(key, event, buffers_out, i);
+                            }
+                        }
+                        midly::MidiMessage::Controller { controller, value }
+                            if controller.as_int() == SUSTAIN_CONTROLLER =>
+                        {
+                            self.sustain = value.as_int() >= 64;
+                            if !self.sustain {
+                                for (key, off_event) in std::mem::take(&mut self.sustained) {
+                                    self.release(key, &off_event, buffers_out, i);
+                                }
+                            }
+                            for buf_out in buffers_out.get_iter(self.midi_out_variadic) {
+                                buf_out[i].push(event.clone())
+                            }
+                        }
+                        _ => {
+                            for buf_out in buffers_out.get_iter(self.midi_out_variadic) {
+                                buf_out[i].push(event.clone())
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.notes.clear();
+        self.sustain = false;
+        self.sustained.clear();
+    }
+}
+
+impl MidiPoly {
+    fn release(
+        &mut self,
+        key: u8,
+        off_event: &MidiEvent,
+        buffers_out: &mut ModuleBuffersOut,
+        i: usize,
+    ) {
+        if let Some(idx) = self.notes.iter().position(|(n, _)| *n == key) {
+            self.notes.remove(idx);
+            if idx < self.num_ports {
+                let old_buf = self.midi_out.remove(idx);
+                self.midi_out.push(old_buf);
+                // hand the freed voice to the newest note still waiting for one
+                buffers_out.get(old_buf)[i].push(
+                    if let Some((_, on_event)) = self.notes.get(self.num_ports - 1) {
+                        on_event.clone()
+                    } else {
+                        off_event.clone()
+                    },
+                );
+            }
+        }
+    }
+
+    // the first num_ports notes hold voices, newest first, so ties go to the oldest note
+    fn steal_victim(&self) -> Option<usize> {
+        let voiced = self.notes.len().min(self.num_ports);
+        let candidates = self.notes[..voiced].iter().enumerate().rev();
+        match self.steal_mode {
+            VoiceStealMode::Oldest => voiced.checked_sub(1),
+            VoiceStealMode::Quietest => candidates
+                .min_by_key(|(_, (_, on_event))| note_velocity(on_event))
+                .map(|(idx, _)| idx),
+            VoiceStealMode::Lowest => candidates.min_by_key(|(_, (n, _))| *n).map(|(idx, _)| idx),
+            VoiceStealMode::Highest => candidates.max_by_key(|(_, (n, _))| *n).map(|(idx, _)| idx),
+            VoiceStealMode::None => None,
+        }
+    }
+
+    // silences a voiced note but keeps it queued, so it sounds again once a voice frees up
+    fn steal(&mut self, idx: usize, buffers_out: &mut ModuleBuffersOut, i: usize) {
+        let (key, on_event) = self.notes.remove(idx);
+        let channel = match on_event {
+            MidiEvent::Midi { channel, .. } => channel,
+            _ => u4::from(0),
+        };
+        let old_buf = self.midi_out.remove(idx);
+        self.midi_out.push(old_buf);
+        buffers_out.get(old_buf)[i].push(MidiEvent::Midi {
+            channel,
+            message: midly::MidiMessage::NoteOff {
+                key: u7::from(key),
+                vel: u7::from(0),
+            },
+        });
+        self.notes.insert(self.num_ports - 1, (key, on_event));
+    }
+}
+
+fn note_velocity(event: &MidiEvent) -> u8 {
+    match event {
+        MidiEvent::Midi {
+            message: midly::MidiMessage::NoteOn { vel, .. },
+            ..
+        } => vel.as_int(),
+        _ => 0,
+    }
+}
+
+// times are kept in seconds so schedules can be shared by hosts running at any sample rate
+#[derive(Clone, Copy)]
+struct ScheduledEvent {
+    time: f64,
+    key: u7,
+    velocity: u7,
+    on: bool,
+}
+
+impl ScheduledEvent {
+    fn sample(&self, sample_rate: f32) -> u64 {
+        (self.time * sample_rate as f64).round() as u64
+    }
+
+    // times that only differ by float error can round onto the same sample out of order,
+    // so note-offs are slotted ahead of any note-ons already queued there
+    fn push_to(&self, events: &mut Vec<MidiEvent>) {
+        let message = if self.on {
+            midly::MidiMessage::NoteOn {
+                key: self.key,
+                vel: self.velocity,
+            }
+        } else {
+            midly::MidiMessage::NoteOff {
+                key: self.key,
+                vel: self.velocity,
+            }
+        };
+        let event = MidiEvent::Midi {
+            channel: u4::from(0),
+            message,
+        };
+        let idx = if self.on {
+            events.len()
+        } else {
+            events
+                .iter()
+                .position(|e| {
+                    matches!(
+                        e,
+                        MidiEvent::Midi {
+                            message: midly::MidiMessage::NoteOn { .. },
+                            ..
+                        }
+                    )
+                })
+                .unwrap_or(events.len())
+        };
+        events.insert(idx, event);
+    }
+}
+
+// note-offs sort before note-ons at the same time so back-to-back notes retrigger
+fn sort_scheduled_events(events: &mut [ScheduledEvent]) {
+    events.sort_by(|a, b| {
+        a.time
+            .partial_cmp(&b.time)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.on.cmp(&b.on))
+    });
+}
+
+// edits rebuild the event list under the lock and publish it as a new snapshot; the
+// audio thread only ever `try_lock`s to pick up the latest `Arc`, so it never waits on
+// (or re-sorts alongside) the UI
+#[derive(Default)]
+struct NoteScheduleInner {
+    events: Arc<Vec<ScheduledEvent>>,
+    version: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct NoteSchedule(Arc<Mutex<NoteScheduleInner>>);
+
+impl NoteSchedule {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_note(&self, time_secs: f32, duration: f32, key: u8, velocity: u8) {
+        let start = time_secs.max(0.0) as f64;
+        let end = start + duration.max(0.0) as f64;
+        let key = u7::from(key.min(127));
+        let mut inner = self.0.lock().unwrap();
+        let mut events = Vec::with_capacity(inner.events.len() + 2);
+        events.extend_from_slice(&inner.events);
+        events.push(ScheduledEvent {
+            time: start,
+            key,
+            velocity: u7::from(velocity.min(127)),
+            on: true,
+        });
+        events.push(ScheduledEvent {
+            time: end,
+            key,
+            velocity: u7::from(0),
+            on: false,
+        });
+        sort_scheduled_events(&mut events);
+        inner.events = Arc::new(events);
+        inner.version += 1;
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.0.lock().unwrap();
+        inner.events = Default::default();
+        inner.version += 1;
+    }
+
+    fn snapshot(&self) -> (Arc<Vec<ScheduledEvent>>, u64) {
+        let inner = self.0.lock().unwrap();
+        (inner.events.clone(), inner.version)
+    }
+
+    // `None` if nothing changed since `version` or an edit is in progress
+    fn try_update(&self, version: u64) -> Option<(Arc<Vec<ScheduledEvent>>, u64)> {
+        let inner = self.0.try_lock().ok()?;
+        if inner.version == version {
+            None
+        } else {
+            Some((inner.events.clone(), inner.version))
+        }
+    }
+}
+
+// index of the first event at or after `position`
+fn seek_scheduled_events(events: &[ScheduledEvent], position: u64, sample_rate: f32) -> usize {
+    events.partition_point(|e| e.sample(sample_rate) < position)
+}
+
+pub struct ScheduledMidi {
+    midi_out: BufferHandle<Out<MidiEvents>>,
+    schedule: NoteSchedule,
+    events: Arc<Vec<ScheduledEvent>>,
+    version: u64,
+    next_event: usize,
+    position: u64,
+    sample_rate: f32,
+}
+
+impl ModuleSettings for ScheduledMidi {
+    type Settings = NoteSchedule;
+    type Error = Infallible;
+}
+
+impl ScheduledMidi {
+    fn seek(&mut self) {
+        self.next_event = seek_scheduled_events(&self.events, self.position, self.sample_rate);
+    }
+}
+
+impl Module for ScheduledMidi {
+    fn init(
+        mut desc: ModuleDescriptor,
+        schedule: NoteSchedule,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let (events, version) = schedule.snapshot();
+        let module = Self {
+            midi_out: desc.with_buf_out::<MidiEvents>("midi_out"),
+            schedule,
+            events,
+            version,
+            next_event: 0,
+            position: 0,
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, _buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let buffer = buffers_out.get(self.midi_out);
+        for events in buffer.iter_mut() {
+            events.clear();
+        }
+
+        if let Some((events, version)) = self.schedule.try_update(self.version) {
+            self.events = events;
+            self.version = version;
+            self.seek();
+        }
+
+        let block_end = self.position + buffer.len() as u64;
+        while let Some(event) = self.events.get(self.next_event) {
+            let sample = event.sample(self.sample_rate);
+            if sample >= block_end {
+                break;
+            }
+            self.next_event += 1;
+            event.push_to(&mut buffer[(sample - self.position) as usize]);
+        }
+
+        self.position = block_end;
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+        self.next_event = 0;
+    }
+}
+
+impl StateSnapshot for ScheduledMidi {
+    type State = u64;
+
+    fn snapshot(&self) -> u64 {
+        self.position
+    }
+
+    fn restore(&mut self, state: &u64) {
+        self.position = *state;
+        self.seek();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct PatternNote {
+    pub start: f32,
+    pub duration: f32,
+    pub key: u8,
+    pub velocity: u8,
+}
+
+#[derive(Clone, Default)]
+pub struct Pattern {
+    pub notes: Vec<PatternNote>,
+    pub length: f32,
+}
+
+impl Pattern {
+    pub fn new(length: f32) -> Self {
+        Self {
+            notes: Vec::new(),
+            length,
+        }
+    }
+
+    pub fn with_note(mut self, start: f32, duration: f32, key: u8, velocity: u8) -> Self {
+        self.notes.push(PatternNote {
+            start,
+            duration,
+            key,
+            velocity,
+        });
+        self
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ArrangementError {
+    #[error("the pattern `{0}` was not found in this arrangement")]
+    UnknownPattern(String),
+}
+
+#[derive(Default)]
+struct Timeline {
+    events: Vec<ScheduledEvent>,
+    length: f64,
+}
+
+#[derive(Default)]
+struct ArrangementInner {
+    patterns: Vec<(String, Pattern)>,
+    song: Vec<(String, usize)>,
+    timeline: Arc<Timeline>,
+    version: u64,
+}
+
+impl ArrangementInner {
+    fn rebuild(&mut self) {
+        let mut events = Vec::new();
+        let mut offset = 0.0;
+        for (name, repeats) in self.song.iter() {
+            let pattern = match self.patterns.iter().find(|(n, _)| n == name) {
+                Some((_, pattern)) => pattern,
+                None => continue,
+            };
+            let pattern_len = pattern.length.max(0.0) as f64;
+            for _ in 0..*repeats {
+                for note in pattern.notes.iter() {
+                    let start = offset + note.start.max(0.0) as f64;
+                    let end = start + note.duration.max(0.0) as f64;
+                    let key = u7::from(note.key.min(127));
+                    events.push(ScheduledEvent {
+                        time: start,
+                        key,
+                        velocity: u7::from(note.velocity.min(127)),
+                        on: true,
+                    });
+                    events.push(ScheduledEvent {
+                        time: end.min(offset + pattern_len),
+                        key,
+                        velocity: u7::from(0),
+                        on: false,
+                    });
+                }
+                offset += pattern_len;
+            }
+        }
+        sort_scheduled_events(&mut events);
+        self.timeline = Arc::new(Timeline {
+            events,
+            length: offset,
+        });
+        self.version += 1;
+    }
+}
+
+// like `NoteSchedule`, edits publish a fresh timeline that the player picks up with
+// `try_lock`; transport flags are atomics so play/stop never contend with the audio thread
+#[derive(Default)]
+struct ArrangementShared {
+    inner: Mutex<ArrangementInner>,
+    playing: AtomicBool,
+    looping: AtomicBool,
+    rewind: AtomicBool,
+}
+
+#[derive(Clone, Default)]
+pub struct Arrangement(Arc<ArrangementShared>);
+
+impl Arrangement {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_pattern(&self, name: &str, pattern: Pattern) {
+        let mut inner = self.0.inner.lock().unwrap();
+        match inner.patterns.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = pattern,
+            None => inner.patterns.push((name.to_owned(), pattern)),
+        }
+        inner.rebuild();
+    }
+
+    pub fn push(&self, name: &str, repeats: usize) -> Result<(), ArrangementError> {
+        let mut inner = self.0.inner.lock().unwrap();
+        if inner.patterns.iter().all(|(n, _)| n != name) {
+            return Err(ArrangementError::UnknownPattern(name.to_owned()));
+        }
+        inner.song.push((name.to_owned(), repeats));
+        inner.rebuild();
+        Ok(())
+    }
+
+    pub fn play(&self) {
+        self.0.playing.store(true, Ordering::Release);
+    }
+
+    pub fn stop(&self) {
+        self.0.rewind.store(true, Ordering::Release);
+        self.0.playing.store(false, Ordering::Release);
+    }
+
+    pub fn set_loop(&self, looping: bool) {
+        self.0.looping.store(looping, Ordering::Relaxed);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.0.playing.load(Ordering::Acquire)
+    }
+
+    fn snapshot(&self) -> (Arc<Timeline>, u64) {
+        let inner = self.0.inner.lock().unwrap();
+        (inner.timeline.clone(), inner.version)
+    }
+
+    fn try_update(&self, version: u64) -> Option<(Arc<Timeline>, u64)> {
+        let inner = self.0.inner.try_lock().ok()?;
+        if inner.version == version {
+            None
+        } else {
+            Some((inner.timeline.clone(), inner.version))
+        }
+    }
+}
+
+pub struct ArrangementPlayer {
+    midi_out: BufferHandle<Out<MidiEvents>>,
+    arrangement: Arrangement,
+    timeline: Arc<Timeline>,
+    version: u64,
+    next_event: usize,
+    position: u64,
+    held_keys: Vec<u7>,
+    sample_rate: f32,
+}
+
+impl ModuleSettings for ArrangementPlayer {
+    type Settings = Arrangement;
+    type Error = Infallible;
+}
+
+impl ArrangementPlayer {
+    fn seek(&mut self) {
+        self.next_event =
+            seek_scheduled_events(&self.timeline.events, self.position, self.sample_rate);
+    }
+
+    fn release_held(&mut self, events: &mut MidiEvents) {
+        for key in self.held_keys.drain(..) {
+            events.push(MidiEvent::Midi {
+                channel: u4::from(0),
+                message: midly::MidiMessage::NoteOff {
+                    key,
+                    vel: u7::from(0),
+                },
+            });
+        }
+    }
+}
+
+impl Module for ArrangementPlayer {
+    fn init(
+        mut desc: ModuleDescriptor,
+        arrangement: Arrangement,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let (timeline, version) = arrangement.snapshot();
+        let module = Self {
+            midi_out: desc.with_buf_out::<MidiEvents>("midi_out"),
+            arrangement,
+            timeline,
+            version,
+            next_event: 0,
+            position: 0,
+            held_keys: Vec::with_capacity(128),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, _buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let buffer = buffers_out.get(self.midi_out);
+        for events in buffer.iter_mut() {
+            events.clear();
+        }
+
+        if let Some((timeline, version)) = self.arrangement.try_update(self.version) {
+            self.timeline = timeline;
+            self.version = version;
+            self.seek();
+        }
+
+        let shared = self.arrangement.0.clone();
+        if shared.rewind.swap(false, Ordering::AcqRel) {
+            self.position = 0;
+            self.next_event = 0;
+            self.release_held(&mut buffer[0]);
+        }
+        if !shared.playing.load(Ordering::Acquire) {
+            return;
+        }
+
+        let timeline = self.timeline.clone();
+        let length = (timeline.length * self.sample_rate as f64).round() as u64;
+        for events in buffer.iter_mut() {
+            if self.position >= length {
+                self.release_held(events);
+                self.position = 0;
+                self.next_event = 0;
+                if !shared.looping.load(Ordering::Relaxed) || length == 0 {
+                    shared.playing.store(false, Ordering::Release);
+                    return;
+                }
+            }
+
+            while let Some(event) = timeline.events.get(self.next_event) {
+                if event.sample(self.sample_rate) != self.position {
+                    break;
+                }
+                self.next_event += 1;
+                if event.on {
+                    self.held_keys.push(event.key);
+                } else if let Some(idx) = self.held_keys.iter().position(|&k| k == event.key) {
+                    self.held_keys.remove(idx);
+                }
+                event.push_to(events);
+            }
+            self.position += 1;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+        self.next_event = 0;
+        self.held_keys.clear();
+    }
+}
+
+impl StateSnapshot for ArrangementPlayer {
+    type State = (u64, Vec<u7>);
+
+    fn snapshot(&self) -> (u64, Vec<u7>) {
+        (self.position, self.held_keys.clone())
+    }
+
+    fn restore(&mut self, state: &(u64, Vec<u7>)) {
+        self.position = state.0;
+        self.held_keys.clone_from(&state.1);
+        self.seek();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::{testing, Buffer};
+
+    const SAMPLE_RATE: u32 = 44100;
+    const LEN: usize = 64;
+
+    fn message(message: midly::MidiMessage) -> MidiEvent {
+        MidiEvent::Midi {
+            channel: u4::from(0),
+            message,
+        }
+    }
+
+    fn note_on(key: u8, vel: u8) -> MidiEvent {
+        message(midly::MidiMessage::NoteOn {
+            key: u7::from(key),
+            vel: u7::from(vel),
+        })
+    }
+
+    fn note_off(key: u8) -> MidiEvent {
+        message(midly::MidiMessage::NoteOff {
+            key: u7::from(key),
+            vel: u7::from(64),
+        })
+    }
+
+    fn pitch_bend(value: u16) -> MidiEvent {
+        message(midly::MidiMessage::PitchBend {
+            bend: midly::PitchBend(u14::from(value)),
+        })
+    }
+
+    // one block per entry, each event placed at its sample within the block
+    fn blocks(events: &[&[(usize, MidiEvent)]]) -> Vec<Buffer<MidiEvents>> {
+        events
+            .iter()
+            .map(|events| {
+                let mut block = vec![MidiEvents::new(); LEN].into_boxed_slice();
+                for (i, event) in events.iter() {
+                    block[*i].push(event.clone());
+                }
+                block
+            })
+            .collect()
+    }
+
+    fn run<T: Module + ModuleSettings>(
+        settings: T::Settings,
+        num_args: usize,
+        inputs: &[(&str, Vec<Buffer<f32>>)],
+        midi_inputs: &[(&str, Vec<Buffer<MidiEvents>>)],
+        num_blocks: usize,
+    ) -> testing::ModuleOutputs {
+        testing::run_module::<T>(
+            settings,
+            num_args,
+            SAMPLE_RATE,
+            LEN,
+            inputs,
+            midi_inputs,
+            num_blocks,
+        )
+        .unwrap()
+    }
+
+    fn signal(outputs: &testing::ModuleOutputs, name: &str) -> Vec<f32> {
+        outputs.signal[name]
+            .iter()
+            .flat_map(|block| block.iter().copied())
+            .collect()
+    }
+
+    // (sample, key, velocity) of every note-on, with velocity 0 for note-offs
+    fn notes(outputs: &testing::ModuleOutputs, name: &str) -> Vec<(usize, u8, u8)> {
+        outputs.midi[name]
+            .iter()
+            .flat_map(|block| block.iter())
+            .enumerate()
+            .flat_map(|(i, events)| events.iter().map(move |event| (i, event)))
+            .filter_map(|(i, event)| match event {
+                MidiEvent::Midi {
+                    message: midly::MidiMessage::NoteOn { key, vel },
+                    ..
+                } => Some((i, key.as_int(), vel.as_int())),
+                MidiEvent::Midi {
+                    message: midly::MidiMessage::NoteOff { key, .. },
+                    ..
+                } => Some((i, key.as_int(), 0)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bend_deadzone_is_clamped() {
+        let full = midly::PitchBend(u14::from(0x3fff));
+        assert!((bend_semitones(full, 0.0) - 1.0).abs() < 1e-3);
+        assert_eq!(
+            bend_semitones(midly::PitchBend(u14::from(0x2100)), 0.5),
+            0.0
+        );
+        for deadzone in [1.0, 1.5, -1.0, f32::NAN].iter() {
+            let bend = bend_semitones(full, *deadzone);
+            assert!(bend.is_finite() && (0.0..=1.0).contains(&bend));
+        }
+    }
+
+    #[test]
+    fn note_to_freq_smooths_bends() {
+        let midi = blocks(&[&[(0, note_on(69, 100)), (0, pitch_bend(0x3fff))]]);
+        let outputs = run::<NoteToFreq>(
+            (),
+            0,
+            &[("bend_smoothing", vec![vec![0.01; LEN].into_boxed_slice()])],
+            &[("in", midi)],
+            100,
+        );
+        let freq = signal(&outputs, "freq");
+        assert!(freq[0] < 445.0);
+        let bent = 440.0 * (1.0f32 / 12.0).exp2();
+        assert!((freq.last().unwrap() - bent).abs() < 0.1);
+    }
+
+    #[test]
+    fn midi_poly_revoices_stolen_notes() {
+        let midi = blocks(&[&[
+            (0, note_on(60, 100)),
+            (1, note_on(62, 100)),
+            (2, note_on(64, 100)),
+            (3, note_off(64)),
+        ]]);
+        let outputs = run::<MidiPoly>(VoiceStealMode::Oldest, 2, &[], &[("in", midi)], 1);
+        // the third note steals the oldest voice, which takes its note back once released
+        assert_eq!(
+            notes(&outputs, "out[0]"),
+            vec![(0, 60, 100), (2, 60, 0), (2, 64, 100), (3, 60, 100)]
+        );
+        assert_eq!(notes(&outputs, "out[1]"), vec![(1, 62, 100)]);
+    }
+
+    #[test]
+    fn midi_poly_queues_notes_without_stealing() {
+        let midi = blocks(&[&[
+            (0, note_on(60, 100)),
+            (1, note_on(62, 100)),
+            (2, note_off(60)),
+        ]]);
+        let outputs = run::<MidiPoly>(VoiceStealMode::None, 1, &[], &[("in", midi)], 1);
+        assert_eq!(notes(&outputs, "out[0]"), vec![(0, 60, 100), (2, 62, 100)]);
+    }
+
+    fn note_on_key(event: &MidiEvent) -> Option<u8> {
+        match event {
+            MidiEvent::Midi {
+                message: midly::MidiMessage::NoteOn { key, .. },
+                ..
+            } => Some(key.as_int()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn malformed_messages_are_skipped() {
+        let dropped = DroppedEvents::new();
+        let mut running_status = None;
+        // a stray data byte, a note-on, then a note-on cut short by the end of the packet
+        let events = parse_raw_messages(
+            &[0x3c, 0x90, 60, 100, 0x90, 61],
+            &mut running_status,
+            None,
+            &dropped,
+        );
+        assert_eq!(
+            events.iter().map(note_on_key).collect::<Vec<_>>(),
+            vec![Some(60)]
+        );
+        assert_eq!(dropped.count(), 1);
+
+        // the next packet carries on under the running status
+        let events = parse_raw_messages(&[62, 100], &mut running_status, None, &dropped);
+        assert_eq!(
+            events.iter().map(note_on_key).collect::<Vec<_>>(),
+            vec![Some(62)]
+        );
+        assert_eq!(dropped.count(), 1);
+    }
+
+    #[test]
+    fn packets_split_on_running_status_and_realtime() {
+        let mut running_status = None;
+        let messages = split_raw_messages(
+            &[
+                0x90, 60, 100, 0xf8, 62, 100, 0xf0, 1, 2, 0xf7, 64, 100, 0xc0, 5,
+            ],
+            &mut running_status,
+        );
+        // sysex cancels the running status, so the bare 64, 100 has nothing to attach to
+        assert_eq!(
+            messages,
+            vec![
+                vec![0x90, 60, 100],
+                vec![0xf8],
+                vec![0x90, 62, 100],
+                vec![0xf0, 1, 2, 0xf7],
+                vec![0xc0, 5],
+            ]
+        );
+        assert_eq!(running_status, Some(0xc0));
+    }
+
+    #[test]
+    fn realtime_bytes_parse_between_messages() {
+        let dropped = DroppedEvents::new();
+        let events = parse_raw_messages(&[0x90, 60, 0xf8, 100], &mut None, None, &dropped);
+        assert_eq!(dropped.count(), 0);
+        assert!(matches!(
+            events[..],
+            [
+                MidiEvent::Realtime(midly::live::SystemRealtime::TimingClock),
+                MidiEvent::Midi { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn channel_filter_keeps_system_messages() {
+        let channels = [u4::from(1)];
+        let events = parse_raw_messages(
+            &[0x90, 60, 100, 0x91, 62, 100, 0xf8],
+            &mut None,
+            Some(&channels),
+            &DroppedEvents::new(),
+        );
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            MidiEvent::Midi { channel, .. } if channel.as_int() == 1
+        ));
+        assert!(matches!(events[1], MidiEvent::Realtime(_)));
+    }
+
+    #[test]
+    fn invalid_port_is_an_error() {
+        let result =
+            testing::run_module::<MidiInput>(usize::MAX.into(), 0, SAMPLE_RATE, LEN, &[], &[], 1);
+        assert!(matches!(
+            result,
+            Err(crate::host::HostError::ModuleInit { .. })
+        ));
+    }
+
+    fn slider(source: SliderSource) -> MidiSliderSettings {
+        MidiSliderSettings {
+            source,
+            curve: SliderCurve::Linear,
+            default: 0.0,
+            min: 0.0,
+            max: 10.0,
+            smoothing_ms: 0.0,
+            high_resolution: false,
+        }
+    }
+
+    #[test]
+    fn slider_follows_pitch_bend_and_pressure() {
+        let midi = blocks(&[&[
+            (0, pitch_bend(0x3fff)),
+            (1, pitch_bend(0)),
+            (
+                2,
+                message(midly::MidiMessage::ChannelAftertouch { vel: u7::from(127) }),
+            ),
+        ]]);
+        let bend = run::<MidiSlider>(
+            slider(SliderSource::PitchBend),
+            0,
+            &[],
+            &[("in", midi.clone())],
+            1,
+        );
+        let bend = signal(&bend, "out");
+        assert_eq!((bend[0], bend[1], bend[2]), (10.0, 0.0, 0.0));
+
+        let pressure = run::<MidiSlider>(
+            slider(SliderSource::ChannelPressure),
+            0,
+            &[],
+            &[("in", midi)],
+            1,
+        );
+        let pressure = signal(&pressure, "out");
+        assert_eq!((pressure[1], pressure[2]), (0.0, 10.0));
+    }
+
+    fn controller(controller: u8, value: u8) -> MidiEvent {
+        message(midly::MidiMessage::Controller {
+            controller: u7::from(controller),
+            value: u7::from(value),
+        })
+    }
+
+    #[test]
+    fn slider_reaches_both_ends_of_its_range() {
+        let midi = blocks(&[&[(0, controller(1, 127)), (1, controller(1, 0))]]);
+        let outputs = run::<MidiSlider>(
+            MidiSliderSettings {
+                min: -2.0,
+                max: 3.0,
+                ..slider(SliderSource::Controller(1))
+            },
+            0,
+            &[],
+            &[("in", midi)],
+            1,
+        );
+        let out = signal(&outputs, "out");
+        assert_eq!((out[0], out[1]), (3.0, -2.0));
+    }
+
+    #[test]
+    fn slider_smoothing_ramps_to_new_values() {
+        let midi = blocks(&[&[(0, controller(1, 127))]]);
+        let outputs = run::<MidiSlider>(
+            MidiSliderSettings {
+                smoothing_ms: 5.0,
+                ..slider(SliderSource::Controller(1))
+            },
+            0,
+            &[],
+            &[("in", midi)],
+            100,
+        );
+        let out = signal(&outputs, "out");
+        assert!(out[0] > 0.0 && out[0] < 1.0);
+        assert!(out.windows(2).all(|w| w[1] >= w[0]));
+        assert!((out.last().unwrap() - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn slider_curves_bend_the_midpoint() {
+        let midi = blocks(&[&[(0, controller(1, 64))]]);
+        let midpoint = |curve| {
+            let outputs = run::<MidiSlider>(
+                MidiSliderSettings {
+                    curve,
+                    ..slider(SliderSource::Controller(1))
+                },
+                0,
+                &[],
+                &[("in", midi.clone())],
+                1,
+            );
+            signal(&outputs, "out")[0]
+        };
+        let linear = midpoint(SliderCurve::Linear);
+        assert!((linear - 10.0 * 64.0 / 127.0).abs() < 1e-4);
+        assert!(midpoint(SliderCurve::Exponential) < linear);
+        assert!(midpoint(SliderCurve::Logarithmic) > linear);
+    }
+
+    #[test]
+    fn sustain_pedal_holds_released_notes() {
+        let midi = blocks(&[&[
+            (0, note_on(60, 100)),
+            (1, controller(SUSTAIN_CONTROLLER, 127)),
+            (2, note_off(60)),
+            (3, controller(SUSTAIN_CONTROLLER, 0)),
+        ]]);
+        let outputs = run::<MidiPoly>(VoiceStealMode::Oldest, 1, &[], &[("in", midi)], 1);
+        assert_eq!(notes(&outputs, "out[0]"), vec![(0, 60, 100), (3, 60, 0)]);
+    }
+
+    #[test]
+    fn note_to_freq_tracks_the_last_held_note() {
+        let midi = blocks(&[&[
+            (0, note_on(69, 100)),
+            (1, note_on(81, 100)),
+            (2, note_off(81)),
+            (3, note_off(69)),
+        ]]);
+        let outputs = run::<NoteToFreq>((), 0, &[], &[("in", midi)], 1);
+        let freq = signal(&outputs, "freq");
+        let gate = signal(&outputs, "gate");
+        assert!((freq[0] - 440.0).abs() < 1e-3);
+        assert!((freq[1] - 880.0).abs() < 1e-3);
+        assert!((freq[2] - 440.0).abs() < 1e-3);
+        assert_eq!(&gate[..4], &[1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn arpeggiator_walks_held_notes() {
+        let midi = blocks(&[&[
+            (0, note_on(64, 90)),
+            (0, note_on(60, 90)),
+            (0, note_on(67, 90)),
+        ]]);
+        let settings = ArpSettings {
+            mode: ArpMode::UpDown,
+            // one step every 16 samples
+            rate_hz: SAMPLE_RATE as f32 / 16.0,
+            octaves: 1,
+        };
+        let outputs = run::<Arpeggiator>(settings, 0, &[], &[("in", midi)], 2);
+        let played = notes(&outputs, "out")
+            .into_iter()
+            .filter(|&(_, _, vel)| vel > 0)
+            .map(|(i, key, _)| (i, key))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            &played[..6],
+            &[(0, 60), (16, 64), (32, 67), (48, 64), (64, 60), (80, 64)]
+        );
+    }
+
+    #[test]
+    fn aftertouch_follows_channel_and_poly_pressure() {
+        let midi = blocks(&[&[
+            (
+                0,
+                message(midly::MidiMessage::ChannelAftertouch { vel: u7::from(100) }),
+            ),
+            (
+                1,
+                message(midly::MidiMessage::Aftertouch {
+                    key: u7::from(60),
+                    vel: u7::from(50),
+                }),
+            ),
+        ]]);
+        let channel = run::<Aftertouch>(Default::default(), 0, &[], &[("in", midi.clone())], 1);
+        let channel = signal(&channel, "out");
+        assert_eq!((channel[0], channel[1]), (100.0 / 127.0, 100.0 / 127.0));
+
+        let poly = run::<Aftertouch>(
+            AftertouchSettings { key: Some(60) },
+            0,
+            &[],
+            &[("in", midi)],
+            1,
+        );
+        let poly = signal(&poly, "out");
+        assert_eq!((poly[0], poly[1]), (0.0, 50.0 / 127.0));
+    }
+
+    #[test]
+    fn high_resolution_slider_combines_msb_and_lsb() {
+        let midi = blocks(&[&[
+            (0, controller(1, 64)),
+            (1, controller(33, 127)),
+            (2, controller(1, 127)),
+            (3, controller(33, 127)),
+        ]]);
+        let outputs = run::<MidiSlider>(
+            MidiSliderSettings {
+                max: 1.0,
+                high_resolution: true,
+                ..slider(SliderSource::Controller(1))
+            },
+            0,
+            &[],
+            &[("in", midi)],
+            1,
+        );
+        let out = signal(&outputs, "out");
+        assert_eq!(out[0], 8192.0 / 16383.0);
+        assert_eq!(out[1], (8192.0 + 127.0) / 16383.0);
+        // a new MSB drops the old LSB until the next one arrives
+        assert_eq!(out[2], 16256.0 / 16383.0);
+        assert_eq!(out[3], 1.0);
+    }
+
+    #[test]
+    fn sequencer_plays_steps_in_order() {
+        let settings = SequencerSettings {
+            steps: vec![Some(60), None, Some(64)],
+            rate_hz: SAMPLE_RATE as f32 / 16.0,
+            gate_lengths: Vec::new(),
+        };
+        let outputs = run::<StepSequencer>(settings, 0, &[], &[], 2);
+        // half-length gates, a rest on the second step, then the pattern repeats
+        assert_eq!(
+            notes(&outputs, "out"),
+            vec![
+                (0, 60, 100),
+                (8, 60, 0),
+                (32, 64, 100),
+                (40, 64, 0),
+                (48, 60, 100),
+                (56, 60, 0),
+                (80, 64, 100),
+                (88, 64, 0),
+                (96, 60, 100),
+                (104, 60, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn scheduled_notes_land_on_their_samples() {
+        let schedule = NoteSchedule::new();
+        schedule.add_note(
+            100.0 / SAMPLE_RATE as f32,
+            30.0 / SAMPLE_RATE as f32,
+            60,
+            90,
+        );
+        // added out of order, and back to back with the first note
+        schedule.add_note(
+            130.0 / SAMPLE_RATE as f32,
+            10.0 / SAMPLE_RATE as f32,
+            62,
+            80,
+        );
+        let outputs = run::<ScheduledMidi>(schedule, 0, &[], &[], 3);
+        assert_eq!(
+            notes(&outputs, "midi_out"),
+            vec![(100, 60, 90), (130, 60, 0), (130, 62, 80), (140, 62, 0)]
+        );
+    }
+
+    #[test]
+    fn arrangement_plays_patterns_in_sequence() {
+        let arrangement = Arrangement::new();
+        let beat = 32.0 / SAMPLE_RATE as f32;
+        arrangement.add_pattern("a", Pattern::new(beat).with_note(0.0, beat / 2.0, 60, 100));
+        arrangement.add_pattern("b", Pattern::new(beat).with_note(0.0, beat * 2.0, 64, 100));
+        arrangement.push("a", 2).unwrap();
+        arrangement.push("b", 1).unwrap();
+        assert!(matches!(
+            arrangement.push("nope", 1),
+            Err(ArrangementError::UnknownPattern(_))
+        ));
+        arrangement.play();
+
+        let outputs = run::<ArrangementPlayer>(arrangement.clone(), 0, &[], &[], 2);
+        // notes are cut off at the end of their pattern, and playback stops after the song
+        assert_eq!(
+            notes(&outputs, "midi_out"),
+            vec![
+                (0, 60, 100),
+                (16, 60, 0),
+                (32, 60, 100),
+                (48, 60, 0),
+                (64, 64, 100),
+                (96, 64, 0),
+            ]
+        );
+        assert!(!arrangement.is_playing());
+    }
+
+    #[test]
+    fn midi_file_notes_land_on_their_samples() {
+        #[rustfmt::skip]
+        let bytes = [
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xE0, // one track, 480 tpb
+            b'M', b'T', b'r', b'k', 0, 0, 0, 20,
+            0x00, 0x90, 60, 100,
+            0x83, 0x60, 0x80, 60, 0, // one beat later
+            0x00, 0x90, 62, 90,
+            0x83, 0x60, 0x80, 62, 0,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let path = std::env::temp_dir().join("rustsynth_midi_file_notes.mid");
+        std::fs::write(&path, bytes).unwrap();
+        let settings = MidiFileSettings {
+            path: path.clone(),
+            tempo_scale: 1.0,
+            looping: false,
+        };
+        // the default tempo is 120bpm, so each beat is half a second
+        let num_blocks = SAMPLE_RATE as usize / LEN + 1;
+        let outputs = run::<MidiFilePlayer>(settings, 0, &[], &[], num_blocks);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            notes(&outputs, "out"),
+            vec![
+                (0, 60, 100),
+                (22050, 60, 0),
+                (22050, 62, 90),
+                (44100, 62, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn sustained_voices_stay_busy_until_the_pedal_lifts() {
+        let midi = blocks(&[&[
+            (0, note_on(60, 100)),
+            (1, controller(SUSTAIN_CONTROLLER, 127)),
+            // a zero-velocity note-on is a note-off, so 60 is only held by the pedal
+            (2, note_on(60, 0)),
+            (3, note_on(62, 100)),
+            (4, controller(SUSTAIN_CONTROLLER, 0)),
+        ]]);
+        let outputs = run::<MidiPoly>(VoiceStealMode::Oldest, 2, &[], &[("in", midi)], 1);
+        // the new note takes the free voice rather than the sustained one
+        assert_eq!(notes(&outputs, "out[0]"), vec![(0, 60, 100), (4, 60, 0)]);
+        assert_eq!(notes(&outputs, "out[1]"), vec![(3, 62, 100)]);
+    }
+
+    #[test]
+    fn lifting_the_pedal_releases_notes_in_order() {
+        let midi = blocks(&[&[
+            (0, note_on(60, 100)),
+            (1, note_on(62, 100)),
+            // no voice is free, so 64 waits for one
+            (2, note_on(64, 100)),
+            (3, controller(SUSTAIN_CONTROLLER, 127)),
+            (4, note_off(62)),
+            (5, note_off(60)),
+            (6, controller(SUSTAIN_CONTROLLER, 0)),
+        ]]);
+        let outputs = run::<MidiPoly>(VoiceStealMode::None, 2, &[], &[("in", midi)], 1);
+        // 62 was let go first, so its voice is the one handed to 64
+        assert_eq!(notes(&outputs, "out[0]"), vec![(0, 60, 100), (6, 60, 0)]);
+        assert_eq!(notes(&outputs, "out[1]"), vec![(1, 62, 100), (6, 64, 100)]);
+    }
+
+    #[test]
+    fn midi_poly_steals_the_quietest_or_lowest_note() {
+        let midi = || {
+            blocks(&[&[
+                (0, note_on(64, 40)),
+                (1, note_on(60, 100)),
+                // releases 67 with a zero-velocity note-on, so it can't be picked as a victim
+                (2, note_on(67, 10)),
+                (3, note_on(67, 0)),
+                (4, note_on(72, 100)),
+                (5, note_on(74, 100)),
+            ]])
+        };
+        let quietest = run::<MidiPoly>(VoiceStealMode::Quietest, 3, &[], &[("in", midi())], 1);
+        // 72 takes the voice 67 freed, then 74 steals from 64, the quietest note still held
+        assert_eq!(
+            notes(&quietest, "out[0]"),
+            vec![(0, 64, 40), (5, 64, 0), (5, 74, 100)]
+        );
+        assert_eq!(notes(&quietest, "out[1]"), vec![(1, 60, 100)]);
+        assert_eq!(
+            notes(&quietest, "out[2]"),
+            vec![(2, 67, 10), (3, 67, 0), (4, 72, 100)]
+        );
+
+        let lowest = run::<MidiPoly>(VoiceStealMode::Lowest, 3, &[], &[("in", midi())], 1);
+        assert_eq!(notes(&lowest, "out[0]"), vec![(0, 64, 40)]);
+        assert_eq!(
+            notes(&lowest, "out[1]"),
+            vec![(1, 60, 100), (5, 60, 0), (5, 74, 100)]
+        );
+    }
+
+    #[test]
+    fn arrangement_position_is_restored_from_snapshots() {
+        use crate::host::Host;
+        let arrangement = Arrangement::new();
+        let bar = 128.0 / SAMPLE_RATE as f32;
+        let pattern = Pattern::new(bar)
+            .with_note(0.0, bar / 2.0, 57, 100)
+            .with_note(bar / 2.0, bar / 2.0, 69, 100);
+        arrangement.add_pattern("a", pattern);
+        arrangement.push("a", 1).unwrap();
+        arrangement.set_loop(true);
+        arrangement.play();
+
+        let mut host = Host::new(SAMPLE_RATE, LEN).unwrap();
+        let player = host
+            .create_module::<ArrangementPlayer>("player", arrangement)
+            .unwrap();
+        let to_freq = host.create_module::<NoteToFreq>("to_freq", ()).unwrap();
+        host.link::<MidiEvents>(
+            host.buf(player, "midi_out").unwrap(),
+            host.buf(to_freq, "in").unwrap(),
+        )
+        .unwrap();
+        let freq = |host: &Host| {
+            host.peek_output::<f32>(host.buf(to_freq, "freq").unwrap())
+                .unwrap()[LEN - 1]
+        };
+
+        host.step(1);
+        assert!((freq(&host) - 220.0).abs() < 1e-3);
+        // halfway through the bar, just before the second note
+        let snapshot = host.snapshot_state();
+        // to the end of the next loop, so playing on would start the bar over
+        host.step(3);
+        assert!((freq(&host) - 440.0).abs() < 1e-3);
+
+        host.restore_state(&snapshot);
+        host.step(1);
+        assert!((freq(&host) - 440.0).abs() < 1e-3);
+    }
 }
@@ -1,27 +1,38 @@
-use std::convert::Infallible;
+use std::{
+    convert::Infallible,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 use crate::{
-    constants::*,
     host::{
         BufferHandle, BuiltModuleDescriptor, In, Module, ModuleBuffersIn, ModuleBuffersOut,
-        ModuleDescriptor, ModuleSettings, Out, VariadicBufferHandle,
+        ModuleDescriptor, ModuleSettings, ModuleState, Out, StateSnapshot, VariadicBufferHandle,
     },
-    midi::{MidiEvent, MidiEvents},
+    midi::{bend_semitones, smooth_bend, MidiEvent, MidiEvents},
 };
 use float_cmp::ApproxEq;
+use thiserror::Error;
+#[cfg(feature = "simd")]
+use wide::f32x8;
 
+#[derive(Clone, Copy)]
 enum EnvelopeStage {
     Silence,
     Attack,
     Decay,
     Sustain,
     Release,
+    // the falling segment of a LoopADSR cycle, while the note is still held
+    LoopRelease,
 }
 
 pub struct Envelope {
     midi_in: BufferHandle<In<MidiEvents>>,
+    gate_in: BufferHandle<In<f32>>,
     signal_in: BufferHandle<In<f32>>,
     signal_out: BufferHandle<Out<f32>>,
+    gate_out: BufferHandle<Out<f32>>,
     attack_in: BufferHandle<In<f32>>,
     decay_in: BufferHandle<In<f32>>,
     sustain_in: BufferHandle<In<f32>>,
@@ -33,6 +44,169 @@ pub struct Envelope {
     current_stage: EnvelopeStage,
     time_elapsed: f32,
     release_amplitude: f32,
+    release_scale: f32,
+    velocity_scale: f32,
+    attack_start: f32,
+    gate_high: bool,
+    held_notes: usize,
+    sample_rate: f32,
+}
+
+impl Envelope {
+    fn note_on(&mut self, velocity_scale: f32) {
+        self.attack_start = match self.settings.retrigger_mode {
+            RetriggerMode::Reset => 0.0,
+            // carry the level heard so far into the new attack, compensating for a change in
+            // velocity so the output itself doesn't jump
+            RetriggerMode::FromCurrent if velocity_scale > 0.0 => {
+                (self.current_level() * self.velocity_scale / velocity_scale).min(1.0)
+            }
+            RetriggerMode::FromCurrent => 0.0,
+        };
+        self.current_stage = EnvelopeStage::Attack;
+        self.time_elapsed = 0.0;
+        self.release_amplitude = self.attack_start;
+        self.velocity_scale = velocity_scale;
+    }
+
+    fn note_off(&mut self, release_scale: f32) {
+        match self.current_stage {
+            EnvelopeStage::Release | EnvelopeStage::Silence => {}
+            EnvelopeStage::LoopRelease => {
+                self.release_amplitude = self.loop_release_level();
+                self.current_stage = EnvelopeStage::Release;
+                self.time_elapsed = 0.0;
+                self.release_scale = release_scale;
+            }
+            _ => {
+                self.current_stage = EnvelopeStage::Release;
+                self.time_elapsed = 0.0;
+                self.release_scale = release_scale;
+            }
+        }
+    }
+
+    fn attack_level(&self) -> f32 {
+        let progress = (self.time_elapsed * self.inv_attack).clamp(0.0, 1.0);
+        self.attack_start
+            + (1.0 - self.attack_start) * curve_progress(progress, self.settings.curve)
+    }
+
+    fn release_level(&self) -> f32 {
+        let progress = (self.time_elapsed * self.inv_release / self.release_scale).clamp(0.0, 1.0);
+        self.release_amplitude * (1.0 - curve_progress(progress, self.settings.curve))
+    }
+
+    fn current_level(&self) -> f32 {
+        match self.current_stage {
+            EnvelopeStage::Silence => 0.0,
+            EnvelopeStage::Attack | EnvelopeStage::Decay | EnvelopeStage::Sustain => {
+                self.release_amplitude
+            }
+            EnvelopeStage::Release => self.release_level(),
+            EnvelopeStage::LoopRelease => self.loop_release_level(),
+        }
+    }
+
+    // release_amplitude holds the sustain level the loop's falling segment started from
+    fn loop_release_level(&self) -> f32 {
+        let progress = (self.time_elapsed * self.inv_release).clamp(0.0, 1.0);
+        self.release_amplitude * (1.0 - curve_progress(progress, self.settings.curve))
+    }
+}
+
+#[derive(Clone)]
+pub struct EnvelopeState {
+    settings: EnvelopeSettings,
+    inv_attack: f32,
+    inv_decay: f32,
+    inv_release: f32,
+    current_stage: EnvelopeStage,
+    time_elapsed: f32,
+    release_amplitude: f32,
+    release_scale: f32,
+    velocity_scale: f32,
+    attack_start: f32,
+    gate_high: bool,
+    held_notes: usize,
+}
+
+impl StateSnapshot for Envelope {
+    type State = EnvelopeState;
+
+    fn snapshot(&self) -> EnvelopeState {
+        EnvelopeState {
+            settings: self.settings.clone(),
+            inv_attack: self.inv_attack,
+            inv_decay: self.inv_decay,
+            inv_release: self.inv_release,
+            current_stage: self.current_stage,
+            time_elapsed: self.time_elapsed,
+            release_amplitude: self.release_amplitude,
+            release_scale: self.release_scale,
+            velocity_scale: self.velocity_scale,
+            attack_start: self.attack_start,
+            gate_high: self.gate_high,
+            held_notes: self.held_notes,
+        }
+    }
+
+    fn restore(&mut self, state: &EnvelopeState) {
+        self.settings = state.settings.clone();
+        self.inv_attack = state.inv_attack;
+        self.inv_decay = state.inv_decay;
+        self.inv_release = state.inv_release;
+        self.current_stage = state.current_stage;
+        self.time_elapsed = state.time_elapsed;
+        self.release_amplitude = state.release_amplitude;
+        self.release_scale = state.release_scale;
+        self.velocity_scale = state.velocity_scale;
+        self.attack_start = state.attack_start;
+        self.gate_high = state.gate_high;
+        self.held_notes = state.held_notes;
+    }
+}
+
+fn stage_inverse(time: f32) -> f32 {
+    if time > 0.0 {
+        1.0 / time
+    } else {
+        0.0
+    }
+}
+
+// bends a segment's 0..1 progress: 0 is linear, positive curves move quickly at first like an RC
+// charge, negative curves start slowly
+fn curve_progress(progress: f32, curve: f32) -> f32 {
+    if curve.abs() < 1e-3 {
+        progress
+    } else {
+        (1.0 - (-curve * progress).exp()) / (1.0 - (-curve).exp())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum EnvelopeTrigger {
+    Midi,
+    Gate,
+}
+
+// what a NoteOn does to an envelope that is still sounding: Reset restarts the attack from
+// silence, FromCurrent ramps up from the current level without a click
+#[derive(Clone, Copy)]
+pub enum RetriggerMode {
+    Reset,
+    FromCurrent,
+}
+
+// OneShot holds the sustain level until the note ends. While a note is held, LoopAD restarts the
+// attack as soon as the decay finishes, and LoopADSR releases from the sustain level back to zero
+// before restarting.
+#[derive(Clone, Copy)]
+pub enum EnvelopeMode {
+    OneShot,
+    LoopAD,
+    LoopADSR,
 }
 
 #[derive(Clone)]
@@ -41,6 +215,30 @@ pub struct EnvelopeSettings {
     pub decay: f32,
     pub sustain: f32,
     pub release: f32,
+    pub trigger: EnvelopeTrigger,
+    pub release_velocity_sensitivity: f32,
+    pub curve: f32,
+    pub mode: EnvelopeMode,
+    // 0 ignores NoteOn velocity, 1 scales the output fully by it
+    pub velocity_amount: f32,
+    pub retrigger_mode: RetriggerMode,
+}
+
+impl Default for EnvelopeSettings {
+    fn default() -> Self {
+        Self {
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.7,
+            release: 0.2,
+            trigger: EnvelopeTrigger::Midi,
+            release_velocity_sensitivity: 0.0,
+            curve: 0.0,
+            mode: EnvelopeMode::OneShot,
+            velocity_amount: 0.0,
+            retrigger_mode: RetriggerMode::Reset,
+        }
+    }
 }
 
 impl ModuleSettings for Envelope {
@@ -56,91 +254,147 @@ impl Module for Envelope {
     ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
         let module = Self {
             midi_in: desc.with_buf_in::<MidiEvents>("in"),
+            gate_in: desc.with_buf_in::<f32>("gate"),
             signal_in: desc.with_buf_in::<f32>("in"),
             signal_out: desc.with_buf_out::<f32>("out"),
+            gate_out: desc.with_buf_out::<f32>("gate"),
             attack_in: desc.with_buf_in_default::<f32>("attack", settings.attack),
             decay_in: desc.with_buf_in_default::<f32>("decay", settings.decay),
             sustain_in: desc.with_buf_in_default::<f32>("sustain", settings.sustain),
             release_in: desc.with_buf_in_default::<f32>("release", settings.release),
             current_stage: EnvelopeStage::Silence,
-            inv_attack: 1.0 / settings.attack,
-            inv_decay: 1.0 / settings.decay,
-            inv_release: 1.0 / settings.release,
+            inv_attack: stage_inverse(settings.attack),
+            inv_decay: stage_inverse(settings.decay),
+            inv_release: stage_inverse(settings.release),
             time_elapsed: 0.0,
             release_amplitude: 0.0,
+            release_scale: 1.0,
+            velocity_scale: 1.0,
+            attack_start: 0.0,
+            gate_high: false,
+            held_notes: 0,
             settings,
+            sample_rate: desc.sample_rate() as f32,
         };
         Ok(desc.build(module))
     }
 
     fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
-        for ((((((midis, signal_in), &attack), &decay), &sustain), &release), signal_out) in
-            buffers_in
-                .get(self.midi_in)
-                .iter()
-                .zip(buffers_in.get(self.signal_in).iter())
-                .zip(buffers_in.get(self.attack_in).iter())
-                .zip(buffers_in.get(self.decay_in).iter())
-                .zip(buffers_in.get(self.sustain_in).iter())
-                .zip(buffers_in.get(self.release_in).iter())
-                .zip(buffers_out.get(self.signal_out).iter_mut())
-        {
+        let sample_time = 1.0 / self.sample_rate;
+        let midi_in = buffers_in.get(self.midi_in);
+        let gate_in = buffers_in.get(self.gate_in);
+        let signal_in = buffers_in.get(self.signal_in);
+        let attack_in = buffers_in.get(self.attack_in);
+        let decay_in = buffers_in.get(self.decay_in);
+        let sustain_in = buffers_in.get(self.sustain_in);
+        let release_in = buffers_in.get(self.release_in);
+        let [signal_out, gate_out] = buffers_out.get_many([self.signal_out, self.gate_out]);
+
+        for i in 0..signal_out.len() {
+            let (attack, decay, sustain, release) =
+                (attack_in[i], decay_in[i], sustain_in[i], release_in[i]);
+            let signal_in = signal_in[i];
+            let signal_out = &mut signal_out[i];
+
             let margin = (0.0, 2);
             if !attack.approx_eq(self.settings.attack, margin) {
                 self.settings.attack = attack;
-                self.inv_attack = 1.0 / attack;
+                self.inv_attack = stage_inverse(attack);
             }
             if !decay.approx_eq(self.settings.decay, margin) {
                 self.settings.decay = decay;
-                self.inv_decay = 1.0 / decay;
+                self.inv_decay = stage_inverse(decay);
             }
             if !sustain.approx_eq(self.settings.sustain, margin) {
                 self.settings.sustain = sustain;
             }
             if !release.approx_eq(self.settings.release, margin) {
                 self.settings.release = release;
-                self.inv_release = 1.0 / release;
+                self.inv_release = stage_inverse(release);
             }
 
-            for midi in midis.iter() {
-                if let MidiEvent::Midi { message, .. } = midi {
-                    match message {
-                        midly::MidiMessage::NoteOn { .. } => {
-                            self.current_stage = EnvelopeStage::Attack;
-                            self.time_elapsed = 0.0;
-                            self.release_amplitude = 0.0;
-                        }
-                        midly::MidiMessage::NoteOff { .. } => match self.current_stage {
-                            EnvelopeStage::Release | EnvelopeStage::Silence => {}
-                            _ => {
-                                self.current_stage = EnvelopeStage::Release;
-                                self.time_elapsed = 0.0;
+            match self.settings.trigger {
+                EnvelopeTrigger::Midi => {
+                    for midi in midi_in[i].iter() {
+                        if let MidiEvent::Midi { message, .. } = midi {
+                            match message {
+                                midly::MidiMessage::NoteOn { vel, .. } if vel.as_int() > 0 => {
+                                    self.held_notes += 1;
+                                    let amount = self.settings.velocity_amount.clamp(0.0, 1.0);
+                                    self.note_on(
+                                        1.0 - amount + amount * vel.as_int() as f32 / 127.0,
+                                    );
+                                }
+                                // running-status senders use a zero-velocity note-on as note-off
+                                midly::MidiMessage::NoteOn { .. } => {
+                                    self.held_notes = self.held_notes.saturating_sub(1);
+                                    self.note_off(1.0);
+                                }
+                                midly::MidiMessage::NoteOff { vel, .. } => {
+                                    self.held_notes = self.held_notes.saturating_sub(1);
+                                    let release_scale = 1.0
+                                        - self.settings.release_velocity_sensitivity
+                                            * (vel.as_int() as f32 - 64.0)
+                                            / 127.0;
+                                    self.note_off(release_scale.max(0.01));
+                                }
+                                _ => {}
                             }
-                        },
-                        _ => {}
+                        }
+                    }
+                }
+                EnvelopeTrigger::Gate => {
+                    let gate_high = gate_in[i] > 0.0;
+                    if gate_high && !self.gate_high {
+                        self.note_on(1.0);
+                    } else if !gate_high && self.gate_high {
+                        self.note_off(1.0);
                     }
+                    self.gate_high = gate_high;
                 }
             }
+            let gate = match self.settings.trigger {
+                EnvelopeTrigger::Midi => self.held_notes > 0,
+                EnvelopeTrigger::Gate => self.gate_high,
+            };
+            gate_out[i] = if gate { 1.0 } else { 0.0 };
 
-            self.time_elapsed += SAMPLE_TIME;
+            let signal_in = signal_in * self.velocity_scale;
+            self.time_elapsed += sample_time;
 
             if let EnvelopeStage::Attack = self.current_stage {
                 if self.time_elapsed >= self.settings.attack {
                     self.time_elapsed -= self.settings.attack;
                     self.current_stage = EnvelopeStage::Decay;
                 } else {
-                    self.release_amplitude = self.time_elapsed * self.inv_attack;
+                    self.release_amplitude = self.attack_level();
                     *signal_out = signal_in * self.release_amplitude;
                     continue;
                 }
             }
             if let EnvelopeStage::Decay = self.current_stage {
                 if self.time_elapsed >= self.settings.decay {
-                    self.current_stage = EnvelopeStage::Sustain;
+                    match self.settings.mode {
+                        EnvelopeMode::OneShot => self.current_stage = EnvelopeStage::Sustain,
+                        EnvelopeMode::LoopAD => {
+                            self.time_elapsed -= self.settings.decay;
+                            self.current_stage = EnvelopeStage::Attack;
+                            self.attack_start = 0.0;
+                            self.release_amplitude = self.attack_level();
+                            *signal_out = signal_in * self.release_amplitude;
+                            continue;
+                        }
+                        EnvelopeMode::LoopADSR => {
+                            self.time_elapsed -= self.settings.decay;
+                            self.current_stage = EnvelopeStage::LoopRelease;
+                            self.release_amplitude = self.settings.sustain.clamp(0.0, 1.0);
+                        }
+                    }
                 } else {
-                    self.release_amplitude = (1.0 - self.settings.sustain)
-                        * (1.0 - self.time_elapsed * self.inv_decay)
-                        + self.settings.sustain;
+                    let sustain = self.settings.sustain.clamp(0.0, 1.0);
+                    let progress = (self.time_elapsed * self.inv_decay).clamp(0.0, 1.0);
+                    self.release_amplitude =
+                        1.0 + (sustain - 1.0) * curve_progress(progress, self.settings.curve);
                     *signal_out = signal_in * self.release_amplitude;
                     continue;
                 }
@@ -150,13 +404,23 @@ impl Module for Envelope {
                 *signal_out = signal_in * self.release_amplitude;
                 continue;
             }
-            if let EnvelopeStage::Release = self.current_stage {
+            if let EnvelopeStage::LoopRelease = self.current_stage {
                 if self.time_elapsed >= self.settings.release {
+                    self.time_elapsed -= self.settings.release;
+                    self.current_stage = EnvelopeStage::Attack;
+                    self.attack_start = 0.0;
+                    self.release_amplitude = self.attack_level();
+                    *signal_out = signal_in * self.release_amplitude;
+                } else {
+                    *signal_out = signal_in * self.loop_release_level();
+                }
+                continue;
+            }
+            if let EnvelopeStage::Release = self.current_stage {
+                if self.time_elapsed >= self.settings.release * self.release_scale {
                     self.current_stage = EnvelopeStage::Silence;
                 } else {
-                    *signal_out = signal_in
-                        * self.release_amplitude
-                        * (1.0 - self.time_elapsed * self.inv_release);
+                    *signal_out = signal_in * self.release_level();
                     continue;
                 }
             }
@@ -164,19 +428,170 @@ impl Module for Envelope {
             *signal_out = 0.0;
         }
     }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.current_stage = EnvelopeStage::Silence;
+        self.time_elapsed = 0.0;
+        self.release_amplitude = 0.0;
+        self.release_scale = 1.0;
+        self.velocity_scale = 1.0;
+        self.attack_start = 0.0;
+        self.gate_high = false;
+        self.held_notes = 0;
+    }
+}
+
+#[derive(Clone, Copy)]
+enum LaneOp {
+    Add,
+    Multiply,
+    Subtract,
+}
+
+impl LaneOp {
+    #[cfg(not(feature = "simd"))]
+    fn apply(self, a: f32, b: f32) -> f32 {
+        match self {
+            LaneOp::Add => a + b,
+            LaneOp::Multiply => a * b,
+            LaneOp::Subtract => a - b,
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    fn apply(self, a: f32x8, b: f32x8) -> f32x8 {
+        match self {
+            LaneOp::Add => a + b,
+            LaneOp::Multiply => a * b,
+            LaneOp::Subtract => a - b,
+        }
+    }
+}
+
+// out[i] = op(out[i], input[i])
+#[cfg(not(feature = "simd"))]
+fn accumulate(op: LaneOp, out: &mut [f32], input: &[f32]) {
+    for (val_in, val_out) in input.iter().zip(out.iter_mut()) {
+        *val_out = op.apply(*val_out, *val_in);
+    }
+}
+
+// same as above, 8 lanes at a time; the tail is padded out to a full lane rather than falling
+// back to scalar code
+#[cfg(feature = "simd")]
+fn accumulate(op: LaneOp, out: &mut [f32], input: &[f32]) {
+    for (chunk_in, chunk_out) in input.chunks(8).zip(out.chunks_mut(8)) {
+        let len = chunk_in.len().min(chunk_out.len());
+        let mut lanes_in = [0.0; 8];
+        let mut lanes_out = [0.0; 8];
+        lanes_in[..len].copy_from_slice(&chunk_in[..len]);
+        lanes_out[..len].copy_from_slice(&chunk_out[..len]);
+        let result = op.apply(f32x8::new(lanes_out), f32x8::new(lanes_in));
+        chunk_out[..len].copy_from_slice(&result.to_array()[..len]);
+    }
 }
 
+type BoundsIn = (BufferHandle<In<f32>>, BufferHandle<In<f32>>);
+
 pub struct Op {
     signal_in: VariadicBufferHandle<In<f32>>,
     signal_out: BufferHandle<Out<f32>>,
+    // only registered for OpType::Clamp
+    bounds_in: Option<BoundsIn>,
+    // last snapshot of the shared weights and its version, only used for OpType::WeightedSum
+    weights: (Arc<Vec<f32>>, u64),
     op: OpType,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum OpType {
     Add,
     Multiply,
     Negate,
+    Divide,
+    Reciprocal,
+    WeightedSum(Weights),
+    Min,
+    Max,
+    // clamps the first input between the "min" and "max" inputs, which are unbounded by default
+    Clamp,
+}
+
+// per-input gains for OpType::WeightedSum, shared so they can be changed while the host runs;
+// inputs without a weight pass through at 1.0. Edits publish a new snapshot under the lock and
+// the audio thread only `try_lock`s to pick it up, like `NoteSchedule`
+#[derive(Default)]
+struct WeightsInner {
+    weights: Arc<Vec<f32>>,
+    version: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct Weights(Arc<Mutex<WeightsInner>>);
+
+impl Weights {
+    pub fn new(weights: Vec<f32>) -> Self {
+        Self(Arc::new(Mutex::new(WeightsInner {
+            weights: Arc::new(weights),
+            version: 0,
+        })))
+    }
+
+    pub fn get(&self, idx: usize) -> f32 {
+        self.0
+            .lock()
+            .unwrap()
+            .weights
+            .get(idx)
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    pub fn set(&self, idx: usize, weight: f32) {
+        let mut inner = self.0.lock().unwrap();
+        let mut weights = inner.weights.as_ref().clone();
+        if weights.len() <= idx {
+            weights.resize(idx + 1, 1.0);
+        }
+        weights[idx] = weight;
+        inner.weights = Arc::new(weights);
+        inner.version += 1;
+    }
+
+    pub fn set_all(&self, weights: Vec<f32>) {
+        let mut inner = self.0.lock().unwrap();
+        inner.weights = Arc::new(weights);
+        inner.version += 1;
+    }
+
+    fn snapshot(&self) -> (Arc<Vec<f32>>, u64) {
+        let inner = self.0.lock().unwrap();
+        (inner.weights.clone(), inner.version)
+    }
+
+    // `None` if nothing changed since `version` or an edit is in progress
+    fn try_update(&self, version: u64) -> Option<(Arc<Vec<f32>>, u64)> {
+        let inner = self.0.try_lock().ok()?;
+        if inner.version == version {
+            None
+        } else {
+            Some((inner.weights.clone(), inner.version))
+        }
+    }
+}
+
+impl From<Vec<f32>> for Weights {
+    fn from(weights: Vec<f32>) -> Self {
+        Self::new(weights)
+    }
 }
 
 impl ModuleSettings for Op {
@@ -191,30 +606,39 @@ impl Module for Op {
         _: usize,
     ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
         let module = Self {
-            op: operation,
             signal_in: desc.with_variadic_buf_in_default(
                 "in",
                 match operation {
-                    OpType::Multiply => 1.0,
+                    OpType::Multiply | OpType::Divide => 1.0,
                     _ => 0.0,
                 },
             ),
             signal_out: desc.with_buf_out::<f32>("out"),
+            bounds_in: match operation {
+                OpType::Clamp => Some((
+                    desc.with_buf_in_default::<f32>("min", f32::NEG_INFINITY),
+                    desc.with_buf_in_default::<f32>("max", f32::INFINITY),
+                )),
+                _ => None,
+            },
+            weights: match &operation {
+                OpType::WeightedSum(weights) => weights.snapshot(),
+                _ => Default::default(),
+            },
+            op: operation,
         };
         Ok(desc.build(module))
     }
 
     fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
         let signal_out = buffers_out.get(self.signal_out);
-        match self.op {
+        match &self.op {
             OpType::Add => {
                 for val_out in signal_out.iter_mut() {
                     *val_out = 0.0;
                 }
                 for buf_in in buffers_in.get_variadic(self.signal_in) {
-                    for (val_in, val_out) in buf_in.iter().zip(signal_out.iter_mut()) {
-                        *val_out += val_in;
-                    }
+                    accumulate(LaneOp::Add, signal_out, buf_in);
                 }
             }
             OpType::Multiply => {
@@ -222,9 +646,7 @@ impl Module for Op {
                     *val_out = 1.0;
                 }
                 for buf_in in buffers_in.get_variadic(self.signal_in) {
-                    for (val_in, val_out) in buf_in.iter().zip(signal_out.iter_mut()) {
-                        *val_out *= val_in;
-                    }
+                    accumulate(LaneOp::Multiply, signal_out, buf_in);
                 }
             }
             OpType::Negate => {
@@ -232,8 +654,93 @@ impl Module for Op {
                     *val_out = 0.0;
                 }
                 for buf_in in buffers_in.get_variadic(self.signal_in) {
+                    accumulate(LaneOp::Subtract, signal_out, buf_in);
+                }
+            }
+            OpType::Divide => {
+                let mut bufs_in = buffers_in.get_variadic(self.signal_in);
+                match bufs_in.next() {
+                    Some(first) => signal_out.copy_from_slice(first),
+                    None => {
+                        for val_out in signal_out.iter_mut() {
+                            *val_out = 1.0;
+                        }
+                    }
+                }
+                for buf_in in bufs_in {
+                    for (val_in, val_out) in buf_in.iter().zip(signal_out.iter_mut()) {
+                        *val_out = if *val_in == 0.0 {
+                            0.0
+                        } else {
+                            *val_out / val_in
+                        };
+                    }
+                }
+            }
+            OpType::Reciprocal => {
+                let mut bufs_in = buffers_in.get_variadic(self.signal_in);
+                match bufs_in.next() {
+                    Some(buf_in) => {
+                        for (val_in, val_out) in buf_in.iter().zip(signal_out.iter_mut()) {
+                            *val_out = if *val_in == 0.0 { 0.0 } else { 1.0 / val_in };
+                        }
+                    }
+                    None => {
+                        for val_out in signal_out.iter_mut() {
+                            *val_out = 0.0;
+                        }
+                    }
+                }
+            }
+            OpType::WeightedSum(weights) => {
+                for val_out in signal_out.iter_mut() {
+                    *val_out = 0.0;
+                }
+                if let Some(snapshot) = weights.try_update(self.weights.1) {
+                    self.weights = snapshot;
+                }
+                for (i, buf_in) in buffers_in.get_variadic(self.signal_in).enumerate() {
+                    let weight = self.weights.0.get(i).copied().unwrap_or(1.0);
+                    for (val_in, val_out) in buf_in.iter().zip(signal_out.iter_mut()) {
+                        *val_out += val_in * weight;
+                    }
+                }
+            }
+            OpType::Min | OpType::Max => {
+                let mut bufs_in = buffers_in.get_variadic(self.signal_in);
+                match bufs_in.next() {
+                    Some(first) => signal_out.copy_from_slice(first),
+                    None => {
+                        for val_out in signal_out.iter_mut() {
+                            *val_out = 0.0;
+                        }
+                    }
+                }
+                let is_min = matches!(self.op, OpType::Min);
+                for buf_in in bufs_in {
                     for (val_in, val_out) in buf_in.iter().zip(signal_out.iter_mut()) {
-                        *val_out -= val_in;
+                        *val_out = if is_min {
+                            val_out.min(*val_in)
+                        } else {
+                            val_out.max(*val_in)
+                        };
+                    }
+                }
+            }
+            OpType::Clamp => {
+                let (min_in, max_in) = self.bounds_in.unwrap();
+                let min_in = buffers_in.get(min_in);
+                let max_in = buffers_in.get(max_in);
+                match buffers_in.get_variadic(self.signal_in).next() {
+                    Some(buf_in) => {
+                        for i in 0..signal_out.len() {
+                            signal_out[i] = buf_in[i].max(min_in[i]).min(max_in[i]);
+                        }
+                    }
+                    None => {
+                        for val_out in signal_out.iter_mut() {
+                            *val_out = 0.0;
+                        }
                     }
                 }
             }
@@ -241,26 +748,67 @@ impl Module for Op {
     }
 }
 
-#[derive(Default)]
-struct OscillatorData {
+#[derive(Default, Clone)]
+pub struct OscillatorData {
     velocity: u8,
     semitone: f32,
     bend: f32,
+    bend_target: f32,
+    global_tuning: f32,
     frequency: f32,
     wavetable: Vec<f32>,
-    wavetable_index: f32,
+    wavetable_indices: Vec<f32>,
+    reset_high: bool,
 }
 
 pub struct Oscillator {
     midi_in: BufferHandle<In<MidiEvents>>,
     pitch_shift: BufferHandle<In<f32>>,
+    semitone_offset: BufferHandle<In<f32>>,
+    bend_deadzone: BufferHandle<In<f32>>,
+    bend_smoothing: BufferHandle<In<f32>>,
     vel_amt: BufferHandle<In<f32>>,
     freq_mod: BufferHandle<In<f32>>,
+    phase_mod: BufferHandle<In<f32>>,
+    pulse_width: BufferHandle<In<f32>>,
+    detune: BufferHandle<In<f32>>,
+    reset_phase: BufferHandle<In<f32>>,
     signal_out: BufferHandle<Out<f32>>,
+    pulse: bool,
+    // off for the two-entry naive square, which interpolation would turn into a triangle
+    interpolate: bool,
+    // band-limited copies of the wavetable by octave, empty for waveforms without them
+    mip_levels: Vec<Vec<f32>>,
+    voices: usize,
     data: OscillatorData,
+    sample_rate: f32,
+}
+
+impl StateSnapshot for Oscillator {
+    type State = OscillatorData;
+
+    fn snapshot(&self) -> OscillatorData {
+        self.data.clone()
+    }
+
+    fn restore(&mut self, state: &OscillatorData) {
+        self.data = state.clone();
+    }
 }
 
 impl Oscillator {
+    fn read_wavetable(table: &[f32], index: f32, interpolate: bool) -> f32 {
+        let len = table.len();
+        let floor = index.floor();
+        let lower = (floor as isize).rem_euclid(len as isize) as usize;
+        if !interpolate {
+            return table[lower];
+        }
+        let frac = index - floor;
+        let upper = (lower + 1) % len;
+        table[lower] * (1.0 - frac) + table[upper] * frac
+    }
+
     fn sine(table_len: usize) -> Vec<f32> {
         let inv_len = 1.0 / table_len as f32;
         (0..table_len)
@@ -283,6 +831,48 @@ impl Oscillator {
     fn square() -> Vec<f32> {
         vec![-1.0, 1.0]
     }
+
+    // One table per octave, each with half the harmonics of the one before, so a note can read
+    // the fullest table whose top harmonic stays under Nyquist. Each level is scaled to its
+    // own peak, since the Gibbs overshoot grows as harmonics are dropped.
+    fn band_limited_square(table_len: usize) -> Vec<Vec<f32>> {
+        let inv_len = 1.0 / table_len as f32;
+        let mut max_harmonic = (table_len / 4).max(1);
+        let mut levels = Vec::new();
+        loop {
+            levels.push(
+                (0..table_len)
+                    .map(|i| {
+                        let phase = i as f32 * std::f32::consts::TAU * inv_len;
+                        (1..=max_harmonic)
+                            .step_by(2)
+                            .map(|k| (phase * k as f32).sin() / k as f32)
+                            .sum::<f32>()
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            if max_harmonic == 1 {
+                break;
+            }
+            max_harmonic /= 2;
+        }
+        for level in levels.iter_mut() {
+            let peak = level.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+            if peak > 0.0 {
+                level.iter_mut().for_each(|sample| *sample /= peak);
+            }
+        }
+        levels
+    }
+
+    // the level for a voice advancing `step` cycles per sample, or `None` without mip levels
+    fn mip_level(levels: &[Vec<f32>], step: f32) -> Option<&[f32]> {
+        let top = levels.first()?;
+        let max_harmonic = (top.len() / 4).max(1) as f32;
+        let below_nyquist = 0.5 / step.abs().max(f32::EPSILON);
+        let level = (max_harmonic / below_nyquist).log2().ceil().max(0.0) as usize;
+        Some(&levels[level.min(levels.len() - 1)])
+    }
 }
 
 #[derive(Clone)]
@@ -291,80 +881,4874 @@ pub enum OscillatorSettings {
     Saw(usize),
     Triangle(usize),
     Square,
+    BandLimitedSquare(usize),
+    Pulse(f32),
+}
+
+impl OscillatorSettings {
+    pub const DEFAULT_TABLE_LEN: usize = 2048;
+
+    pub fn sine_default() -> Self {
+        Self::Sine(Self::DEFAULT_TABLE_LEN)
+    }
+
+    pub fn saw_default() -> Self {
+        Self::Saw(Self::DEFAULT_TABLE_LEN)
+    }
+
+    pub fn triangle_default() -> Self {
+        Self::Triangle(Self::DEFAULT_TABLE_LEN)
+    }
+
+    pub fn square_default() -> Self {
+        Self::BandLimitedSquare(Self::DEFAULT_TABLE_LEN)
+    }
+}
+
+#[derive(Clone)]
+pub struct UnisonSettings {
+    pub voices: usize,
+    pub detune_cents: f32,
+    pub spread: f32,
+}
+
+impl Default for UnisonSettings {
+    fn default() -> Self {
+        Self {
+            voices: 1,
+            detune_cents: 0.0,
+            spread: 0.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OscillatorConfig {
+    pub waveform: OscillatorSettings,
+    pub unison: UnisonSettings,
+}
+
+impl From<OscillatorSettings> for OscillatorConfig {
+    fn from(waveform: OscillatorSettings) -> Self {
+        Self {
+            waveform,
+            unison: Default::default(),
+        }
+    }
 }
 
 impl ModuleSettings for Oscillator {
-    type Settings = OscillatorSettings;
+    type Settings = OscillatorConfig;
     type Error = Infallible;
 }
 
 impl Module for Oscillator {
     fn init(
         mut desc: ModuleDescriptor,
-        settings: OscillatorSettings,
+        config: OscillatorConfig,
         _: usize,
     ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let settings = config.waveform;
+        let voices = config.unison.voices.max(1);
+        let mip_levels = match settings {
+            OscillatorSettings::BandLimitedSquare(table_len) => {
+                Self::band_limited_square(table_len)
+            }
+            _ => Vec::new(),
+        };
         let module = Self {
             midi_in: desc.with_buf_in::<MidiEvents>("in"),
             pitch_shift: desc.with_buf_in_default::<f32>("pitch_shift", 1.0),
+            semitone_offset: desc.with_buf_in_default::<f32>("semitone_offset", 0.0),
+            bend_deadzone: desc.with_buf_in_default::<f32>("bend_deadzone", 0.0),
+            bend_smoothing: desc.with_buf_in_default::<f32>("bend_smoothing", 0.0),
             vel_amt: desc.with_buf_in_default::<f32>("vel_amt", 0.0),
             freq_mod: desc.with_buf_in_default::<f32>("freq_mod", 0.0),
+            phase_mod: desc.with_buf_in_default::<f32>("phase_mod", 0.0),
+            pulse_width: desc.with_buf_in_default::<f32>(
+                "pulse_width",
+                match settings {
+                    OscillatorSettings::Pulse(width) => width,
+                    _ => 0.5,
+                },
+            ),
+            detune: desc.with_buf_in_default::<f32>("detune", config.unison.detune_cents),
+            reset_phase: desc.with_buf_in_default::<f32>("reset_phase", 0.0),
             signal_out: desc.with_buf_out::<f32>("out"),
+            pulse: matches!(settings, OscillatorSettings::Pulse(_)),
+            interpolate: !matches!(settings, OscillatorSettings::Square),
+            voices,
             data: OscillatorData {
+                wavetable_indices: vec![0.0; voices],
                 wavetable: match settings {
                     OscillatorSettings::Sine(table_len) => Self::sine(table_len),
                     OscillatorSettings::Saw(table_len) => Self::saw(table_len),
                     OscillatorSettings::Triangle(table_len) => Self::triangle(table_len),
                     OscillatorSettings::Square => Self::square(),
+                    OscillatorSettings::BandLimitedSquare(_) => mip_levels[0].clone(),
+                    // pulses are computed from the phase directly, so a one-entry table makes
+                    // the wavetable index equal to the phase in cycles
+                    OscillatorSettings::Pulse(_) => vec![0.0],
                 },
                 ..Default::default()
             },
+            mip_levels,
+            sample_rate: desc.sample_rate() as f32,
         };
         Ok(desc.build(module))
     }
 
     fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
-        for (_i, ((((midis, pitch_shift), vel_amt), freq_mod), out)) in buffers_in
-            .get(self.midi_in)
-            .iter()
-            .zip(buffers_in.get(self.pitch_shift).iter())
-            .zip(buffers_in.get(self.vel_amt).iter())
-            .zip(buffers_in.get(self.freq_mod).iter())
-            .zip(buffers_out.get(self.signal_out).iter_mut())
-            .enumerate()
-        {
-            let mut updated = false;
-            for midi in midis.iter() {
+        let sample_time = 1.0 / self.sample_rate;
+        let midi_in = buffers_in.get(self.midi_in);
+        let pitch_shift = buffers_in.get(self.pitch_shift);
+        let semitone_offset = buffers_in.get(self.semitone_offset);
+        let bend_deadzone = buffers_in.get(self.bend_deadzone);
+        let bend_smoothing = buffers_in.get(self.bend_smoothing);
+        let vel_amt = buffers_in.get(self.vel_amt);
+        let freq_mod = buffers_in.get(self.freq_mod);
+        let phase_mod = buffers_in.get(self.phase_mod);
+        let pulse_width = buffers_in.get(self.pulse_width);
+        let detune = buffers_in.get(self.detune);
+        let reset_phase = buffers_in.get(self.reset_phase);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            let reset_high = reset_phase[i] > 0.0;
+            if reset_high && !self.data.reset_high {
+                self.reset_phase();
+            }
+            self.data.reset_high = reset_high;
+
+            for midi in midi_in[i].iter() {
                 if let MidiEvent::Midi { message, .. } = midi {
                     match message {
                         midly::MidiMessage::NoteOn { key, vel } => {
                             self.data.velocity = vel.as_int();
                             self.data.semitone = (key.as_int() as i16 - 69) as f32;
-                            self.data.wavetable_index = 0.0;
-                            updated = true;
+                            self.reset_phase();
                         }
                         midly::MidiMessage::PitchBend { bend } => {
-                            self.data.bend =
-                                (bend.0.as_int() as i32 - 0x2000) as f32 / (0x2000 as f32);
-                            updated = true;
+                            self.data.bend_target = bend_semitones(*bend, bend_deadzone[i]);
                         }
                         _ => (),
                     }
                 }
-                //println!("{}", _i);
             }
-            if updated {
-                self.data.frequency = ((self.data.semitone + self.data.bend) / 12.0).exp2() * 440.0;
+            self.data.bend = smooth_bend(
+                self.data.bend,
+                self.data.bend_target,
+                bend_smoothing[i],
+                sample_time,
+            );
+            self.data.frequency = ((self.data.semitone
+                + self.data.bend
+                + semitone_offset[i]
+                + self.data.global_tuning)
+                / 12.0)
+                .exp2()
+                * 440.0;
+
+            let table_len = self.data.wavetable.len() as f32;
+            let width = pulse_width[i].clamp(0.01, 0.99);
+            let voices = self.data.wavetable_indices.len();
+            // phase_mod is in cycles whatever the table size; the pulse's one-entry table makes
+            // its index a phase in cycles too
+            let phase_offset = phase_mod[i] * table_len;
+            let mut sample = 0.0;
+            for (v, index) in self.data.wavetable_indices.iter_mut().enumerate() {
+                let detune_ratio = if voices > 1 {
+                    let spread = 2.0 * v as f32 / (voices - 1) as f32 - 1.0;
+                    (detune[i] * spread / 1200.0).exp2()
+                } else {
+                    1.0
+                };
+                // cycles per sample
+                let step = (self.data.frequency * pitch_shift[i] * detune_ratio + freq_mod[i])
+                    * sample_time;
+
+                let read_index = *index + phase_offset;
+                sample += if self.pulse {
+                    let phase = (read_index / table_len).rem_euclid(1.0);
+                    let level = if phase < width { 1.0 } else { -1.0 };
+                    level - (2.0 * width - 1.0)
+                } else {
+                    let table =
+                        Self::mip_level(&self.mip_levels, step).unwrap_or(&self.data.wavetable);
+                    Self::read_wavetable(table, read_index, self.interpolate)
+                };
+
+                *index += step * table_len;
+                *index = index.rem_euclid(table_len);
             }
+            signal_out[i] = sample / voices as f32
+                * (1.0 + vel_amt[i] * ((self.data.velocity as f32 / 128.0) - 1.0));
+        }
+    }
+
+    fn reset_phase(&mut self) {
+        // unison voices start spread across the cycle so they don't phase-cancel on attack
+        let table_len = self.data.wavetable.len() as f32;
+        for (v, index) in self.data.wavetable_indices.iter_mut().enumerate() {
+            *index = (v as f32 * 0.618_034).fract() * table_len;
+        }
+    }
 
-            *out = self.data.wavetable[((self.data.wavetable_index + freq_mod) as usize)
-                .rem_euclid(self.data.wavetable.len())]
-                * (1.0 + vel_amt * ((self.data.velocity as f32 / 128.0) - 1.0));
+    fn reset(&mut self) {
+        self.data = OscillatorData {
+            wavetable: std::mem::take(&mut self.data.wavetable),
+            wavetable_indices: vec![0.0; self.voices],
+            ..Default::default()
+        };
+    }
 
-            let table_len = self.data.wavetable.len() as f32;
-            self.data.wavetable_index +=
-                self.data.frequency * pitch_shift * SAMPLE_TIME * table_len;
-            self.data.wavetable_index = self.data.wavetable_index.rem_euclid(table_len as f32);
+    fn set_global_tuning(&mut self, semitones: f32) {
+        self.data.global_tuning = semitones;
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+}
+
+#[derive(Clone)]
+pub struct DryWetState {
+    dry_delay: Vec<f32>,
+    dry_delay_index: usize,
+}
+
+impl StateSnapshot for DryWet {
+    type State = DryWetState;
+
+    fn snapshot(&self) -> DryWetState {
+        DryWetState {
+            dry_delay: self.dry_delay.clone(),
+            dry_delay_index: self.dry_delay_index,
         }
     }
+
+    fn restore(&mut self, state: &DryWetState) {
+        self.dry_delay.clone_from(&state.dry_delay);
+        self.dry_delay_index = state.dry_delay_index;
+    }
+}
+
+pub struct DryWet {
+    dry_in: BufferHandle<In<f32>>,
+    wet_in: BufferHandle<In<f32>>,
+    mix_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    dry_delay: Vec<f32>,
+    dry_delay_index: usize,
+}
+
+#[derive(Clone)]
+pub struct DryWetSettings {
+    pub mix: f32,
+    pub dry_delay: usize,
+}
+
+impl ModuleSettings for DryWet {
+    type Settings = DryWetSettings;
+    type Error = Infallible;
+}
+
+impl Module for DryWet {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: DryWetSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            dry_in: desc.with_buf_in::<f32>("dry"),
+            wet_in: desc.with_buf_in::<f32>("wet"),
+            mix_in: desc.with_buf_in_default::<f32>("mix", settings.mix),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            dry_delay: vec![0.0; settings.dry_delay],
+            dry_delay_index: 0,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        for (((&dry, &wet), &mix), out) in buffers_in
+            .get(self.dry_in)
+            .iter()
+            .zip(buffers_in.get(self.wet_in).iter())
+            .zip(buffers_in.get(self.mix_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            let dry = if self.dry_delay.is_empty() {
+                dry
+            } else {
+                let delayed = std::mem::replace(&mut self.dry_delay[self.dry_delay_index], dry);
+                self.dry_delay_index = (self.dry_delay_index + 1) % self.dry_delay.len();
+                delayed
+            };
+            *out = dry * (1.0 - mix) + wet * mix;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        for sample in self.dry_delay.iter_mut() {
+            *sample = 0.0;
+        }
+        self.dry_delay_index = 0;
+    }
+}
+
+#[derive(Clone)]
+struct DelayLine {
+    buffer: Vec<f32>,
+    write_index: usize,
+}
+
+impl DelayLine {
+    fn new(max_delay: f32, sample_rate: f32) -> Self {
+        Self::with_len(((max_delay * sample_rate) as usize).max(1) + 1)
+    }
+
+    fn with_len(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(2)],
+            write_index: 0,
+        }
+    }
+
+    fn max_delay_samples(&self) -> f32 {
+        (self.buffer.len() - 1) as f32
+    }
+
+    fn read(&self, delay: f32) -> f32 {
+        let delay = delay.max(1.0).min(self.max_delay_samples());
+        let len = self.buffer.len();
+        let pos = (self.write_index as f32 - delay).rem_euclid(len as f32);
+        let idx = pos as usize % len;
+        let frac = pos - pos.floor();
+        self.buffer[idx] * (1.0 - frac) + self.buffer[(idx + 1) % len] * frac
+    }
+
+    fn write(&mut self, sample: f32) {
+        self.buffer[self.write_index] = sample;
+        self.write_index = (self.write_index + 1) % self.buffer.len();
+    }
+
+    fn clear(&mut self) {
+        for sample in self.buffer.iter_mut() {
+            *sample = 0.0;
+        }
+        self.write_index = 0;
+    }
+}
+
+pub struct PingPongDelay {
+    signal_in: BufferHandle<In<f32>>,
+    delay_time_in: BufferHandle<In<f32>>,
+    feedback_in: BufferHandle<In<f32>>,
+    mix_in: BufferHandle<In<f32>>,
+    left_out: BufferHandle<Out<f32>>,
+    right_out: BufferHandle<Out<f32>>,
+    left_line: DelayLine,
+    right_line: DelayLine,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct PingPongDelaySettings {
+    pub max_delay: f32,
+    pub delay_time: f32,
+    pub feedback: f32,
+    pub mix: f32,
+}
+
+#[derive(Clone)]
+pub struct PingPongDelayState {
+    left_line: DelayLine,
+    right_line: DelayLine,
+}
+
+impl StateSnapshot for PingPongDelay {
+    type State = PingPongDelayState;
+
+    fn snapshot(&self) -> PingPongDelayState {
+        PingPongDelayState {
+            left_line: self.left_line.clone(),
+            right_line: self.right_line.clone(),
+        }
+    }
+
+    fn restore(&mut self, state: &PingPongDelayState) {
+        self.left_line.clone_from(&state.left_line);
+        self.right_line.clone_from(&state.right_line);
+    }
+}
+
+impl ModuleSettings for PingPongDelay {
+    type Settings = PingPongDelaySettings;
+    type Error = Infallible;
+}
+
+impl Module for PingPongDelay {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: PingPongDelaySettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            delay_time_in: desc.with_buf_in_default::<f32>("delay_time", settings.delay_time),
+            feedback_in: desc.with_buf_in_default::<f32>("feedback", settings.feedback),
+            mix_in: desc.with_buf_in_default::<f32>("mix", settings.mix),
+            left_out: desc.with_buf_out::<f32>("out_left"),
+            right_out: desc.with_buf_out::<f32>("out_right"),
+            left_line: DelayLine::new(settings.max_delay, desc.sample_rate() as f32),
+            right_line: DelayLine::new(settings.max_delay, desc.sample_rate() as f32),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let signal_in = buffers_in.get(self.signal_in);
+        let delay_time_in = buffers_in.get(self.delay_time_in);
+        let feedback_in = buffers_in.get(self.feedback_in);
+        let mix_in = buffers_in.get(self.mix_in);
+        let [left_out, right_out] = buffers_out.get_many([self.left_out, self.right_out]);
+
+        for i in 0..signal_in.len() {
+            let delay = delay_time_in[i] * self.sample_rate;
+            let feedback = feedback_in[i].clamp(0.0, 0.999);
+            let mix = mix_in[i];
+
+            let left = self.left_line.read(delay);
+            let right = self.right_line.read(delay);
+
+            self.left_line.write(signal_in[i] + right * feedback);
+            self.right_line.write(left * feedback);
+
+            let dry = signal_in[i] * (1.0 - mix);
+            left_out[i] = dry + left * mix;
+            right_out[i] = dry + right * mix;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.left_line.clear();
+        self.right_line.clear();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum SuperWaveform {
+    Sine,
+    Saw,
+    Pulse,
+}
+
+impl SuperWaveform {
+    fn from_index(idx: f32) -> Self {
+        match idx.round() as i32 {
+            i32::MIN..=0 => Self::Sine,
+            1 => Self::Saw,
+            _ => Self::Pulse,
+        }
+    }
+
+    fn index(&self) -> f32 {
+        match self {
+            Self::Sine => 0.0,
+            Self::Saw => 1.0,
+            Self::Pulse => 2.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SuperOscillatorSettings {
+    pub waveform: SuperWaveform,
+    pub sync_ratio: f32,
+    pub pulse_width: f32,
+}
+
+#[derive(Clone, Default)]
+pub struct SuperOscillatorState {
+    semitone: f32,
+    bend: f32,
+    global_tuning: f32,
+    master_phase: f32,
+    slave_phase: f32,
+    synced: bool,
+    sync_blep: f32,
+}
+
+pub struct SuperOscillator {
+    midi_in: BufferHandle<In<MidiEvents>>,
+    waveform_in: BufferHandle<In<f32>>,
+    sync_ratio_in: BufferHandle<In<f32>>,
+    pulse_width_in: BufferHandle<In<f32>>,
+    freq_mod_in: BufferHandle<In<f32>>,
+    bend_deadzone_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    state: SuperOscillatorState,
+    sample_rate: f32,
+}
+
+impl SuperOscillator {
+    fn poly_blep(t: f32, dt: f32) -> f32 {
+        if t < dt {
+            let t = t / dt;
+            t + t - t * t - 1.0
+        } else if t > 1.0 - dt {
+            let t = (t - 1.0) / dt;
+            t * t + t + t + 1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn naive(waveform: SuperWaveform, phase: f32, width: f32) -> f32 {
+        match waveform {
+            SuperWaveform::Sine => (phase * std::f32::consts::TAU).sin(),
+            SuperWaveform::Saw => 2.0 * phase - 1.0,
+            SuperWaveform::Pulse => {
+                if phase < width {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+impl StateSnapshot for SuperOscillator {
+    type State = SuperOscillatorState;
+
+    fn snapshot(&self) -> SuperOscillatorState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &SuperOscillatorState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for SuperOscillator {
+    type Settings = SuperOscillatorSettings;
+    type Error = Infallible;
+}
+
+impl Module for SuperOscillator {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: SuperOscillatorSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            midi_in: desc.with_buf_in::<MidiEvents>("in"),
+            waveform_in: desc.with_buf_in_default::<f32>("waveform", settings.waveform.index()),
+            sync_ratio_in: desc.with_buf_in_default::<f32>("sync_ratio", settings.sync_ratio),
+            pulse_width_in: desc.with_buf_in_default::<f32>("pulse_width", settings.pulse_width),
+            freq_mod_in: desc.with_buf_in_default::<f32>("freq_mod", 0.0),
+            bend_deadzone_in: desc.with_buf_in_default::<f32>("bend_deadzone", 0.0),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let midi_in = buffers_in.get(self.midi_in);
+        let waveform_in = buffers_in.get(self.waveform_in);
+        let sync_ratio_in = buffers_in.get(self.sync_ratio_in);
+        let pulse_width_in = buffers_in.get(self.pulse_width_in);
+        let freq_mod_in = buffers_in.get(self.freq_mod_in);
+        let bend_deadzone_in = buffers_in.get(self.bend_deadzone_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            for midi in midi_in[i].iter() {
+                if let MidiEvent::Midi { message, .. } = midi {
+                    match message {
+                        midly::MidiMessage::NoteOn { key, .. } => {
+                            self.state.semitone = (key.as_int() as i16 - 69) as f32;
+                            self.state.master_phase = 0.0;
+                            self.state.slave_phase = 0.0;
+                        }
+                        midly::MidiMessage::PitchBend { bend } => {
+                            self.state.bend = bend_semitones(*bend, bend_deadzone_in[i]);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+
+            let frequency =
+                ((self.state.semitone + self.state.bend + self.state.global_tuning) / 12.0).exp2()
+                    * 440.0
+                    + freq_mod_in[i];
+            let sync_ratio = sync_ratio_in[i].max(1.0);
+            let master_dt = (frequency * sample_time).clamp(0.0, 0.5);
+            let slave_dt = (master_dt * sync_ratio).min(0.5);
+            let phase = self.state.slave_phase;
+            let waveform = SuperWaveform::from_index(waveform_in[i]);
+            let width = pulse_width_in[i].clamp(0.01, 0.99);
+            // Fraction of a sample by which the next sample lands past the master's wrap
+            let sync_offset = (self.state.master_phase + master_dt >= 1.0)
+                .then(|| (self.state.master_phase + master_dt - 1.0) / master_dt);
+
+            // A hard sync replaces the slave's own wrap, so its edge gets the sync correction instead
+            let wrap_synced = if phase < 0.5 {
+                self.state.synced
+            } else {
+                sync_offset.is_some()
+            };
+            let wrap_blep = if wrap_synced {
+                0.0
+            } else {
+                Self::poly_blep(phase, slave_dt)
+            };
+
+            signal_out[i] = match waveform {
+                SuperWaveform::Sine => Self::naive(waveform, phase, width),
+                SuperWaveform::Saw => Self::naive(waveform, phase, width) - wrap_blep,
+                SuperWaveform::Pulse => {
+                    Self::naive(waveform, phase, width) + wrap_blep
+                        - Self::poly_blep((phase - width).rem_euclid(1.0), slave_dt)
+                }
+            } + self.state.sync_blep;
+            self.state.sync_blep = 0.0;
+
+            // Spread the reset's discontinuity over the samples either side, scaled by its height.
+            // The phase is left unwrapped so a slave wrapping at the reset, as at whole-number
+            // ratios, still counts its own jump, whose correction was skipped above
+            if let Some(offset) = sync_offset {
+                let reset_phase = phase + slave_dt * (1.0 - offset);
+                let half_jump = (Self::naive(waveform, 0.0, width)
+                    - Self::naive(waveform, reset_phase, width))
+                    * 0.5;
+                signal_out[i] += half_jump * offset * offset;
+                self.state.sync_blep = -half_jump * (1.0 - offset) * (1.0 - offset);
+            }
+            self.state.synced = sync_offset.is_some();
+
+            self.state.master_phase += master_dt;
+            self.state.slave_phase += slave_dt;
+            if self.state.master_phase >= 1.0 {
+                self.state.master_phase -= 1.0;
+                self.state.slave_phase = self.state.master_phase * sync_ratio;
+            }
+            self.state.slave_phase = self.state.slave_phase.rem_euclid(1.0);
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset_phase(&mut self) {
+        self.state.master_phase = 0.0;
+        self.state.slave_phase = 0.0;
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+
+    fn set_global_tuning(&mut self, semitones: f32) {
+        self.state.global_tuning = semitones;
+    }
+}
+
+pub struct AllPass {
+    signal_in: BufferHandle<In<f32>>,
+    delay_time_in: BufferHandle<In<f32>>,
+    gain_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    line: DelayLine,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct AllPassSettings {
+    pub max_delay: f32,
+    pub delay_time: f32,
+    pub gain: f32,
+}
+
+#[derive(Clone)]
+pub struct AllPassState {
+    line: DelayLine,
+}
+
+impl StateSnapshot for AllPass {
+    type State = AllPassState;
+
+    fn snapshot(&self) -> AllPassState {
+        AllPassState {
+            line: self.line.clone(),
+        }
+    }
+
+    fn restore(&mut self, state: &AllPassState) {
+        self.line.clone_from(&state.line);
+    }
+}
+
+impl ModuleSettings for AllPass {
+    type Settings = AllPassSettings;
+    type Error = Infallible;
+}
+
+impl Module for AllPass {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: AllPassSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            delay_time_in: desc.with_buf_in_default::<f32>("delay_time", settings.delay_time),
+            gain_in: desc.with_buf_in_default::<f32>("gain", settings.gain),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            line: DelayLine::new(settings.max_delay, desc.sample_rate() as f32),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        for (((&signal_in, &delay_time), &gain), out) in buffers_in
+            .get(self.signal_in)
+            .iter()
+            .zip(buffers_in.get(self.delay_time_in).iter())
+            .zip(buffers_in.get(self.gain_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            let gain = gain.clamp(-0.999, 0.999);
+            let delayed = self.line.read(delay_time * self.sample_rate);
+            let v = signal_in + gain * delayed;
+            self.line.write(v);
+            *out = delayed - gain * v;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.line.clear();
+    }
+}
+
+pub struct Leveler {
+    signal_in: BufferHandle<In<f32>>,
+    target_level_in: BufferHandle<In<f32>>,
+    response_time_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    max_gain: f32,
+    state: LevelerState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct LevelerSettings {
+    pub target_level: f32,
+    pub response_time: f32,
+    pub max_gain: f32,
+}
+
+impl Default for LevelerSettings {
+    fn default() -> Self {
+        Self {
+            target_level: 0.25,
+            response_time: 2.0,
+            max_gain: 16.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LevelerState {
+    mean_square: f32,
+    gain: f32,
+}
+
+impl Default for LevelerState {
+    fn default() -> Self {
+        Self {
+            mean_square: 0.0,
+            gain: 1.0,
+        }
+    }
+}
+
+impl StateSnapshot for Leveler {
+    type State = LevelerState;
+
+    fn snapshot(&self) -> LevelerState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &LevelerState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for Leveler {
+    type Settings = LevelerSettings;
+    type Error = Infallible;
+}
+
+impl Module for Leveler {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: LevelerSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            target_level_in: desc.with_buf_in_default::<f32>("target_level", settings.target_level),
+            response_time_in: desc
+                .with_buf_in_default::<f32>("response_time", settings.response_time),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            max_gain: settings.max_gain,
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        for (((&signal_in, &target_level), &response_time), out) in buffers_in
+            .get(self.signal_in)
+            .iter()
+            .zip(buffers_in.get(self.target_level_in).iter())
+            .zip(buffers_in.get(self.response_time_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            let coeff = if response_time > 0.0 {
+                1.0 - (-sample_time / response_time).exp()
+            } else {
+                1.0
+            };
+            self.state.mean_square += (signal_in * signal_in - self.state.mean_square) * coeff;
+
+            let rms = self.state.mean_square.sqrt();
+            let target_gain = if rms > 0.0 {
+                (target_level.max(0.0) / rms).min(self.max_gain)
+            } else {
+                self.state.gain
+            };
+            self.state.gain += (target_gain - self.state.gain) * coeff;
+            *out = signal_in * self.state.gain;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+pub fn interpolate_block(from: f32, to: f32, out: &mut [f32]) {
+    let step = (to - from) / out.len() as f32;
+    for (i, sample) in out.iter_mut().enumerate() {
+        *sample = from + step * (i + 1) as f32;
+    }
+}
+
+pub struct ControlInterpolator {
+    signal_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    last_value: Option<f32>,
+}
+
+impl StateSnapshot for ControlInterpolator {
+    type State = Option<f32>;
+
+    fn snapshot(&self) -> Option<f32> {
+        self.last_value
+    }
+
+    fn restore(&mut self, state: &Option<f32>) {
+        self.last_value = *state;
+    }
+}
+
+impl ModuleSettings for ControlInterpolator {
+    type Settings = ();
+    type Error = Infallible;
+}
+
+impl Module for ControlInterpolator {
+    fn init(
+        mut desc: ModuleDescriptor,
+        _settings: (),
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            last_value: None,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let signal_in = buffers_in.get(self.signal_in);
+        let target = signal_in[signal_in.len() - 1];
+        let from = self.last_value.unwrap_or(target);
+        interpolate_block(from, target, buffers_out.get(self.signal_out));
+        self.last_value = Some(target);
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.last_value = None;
+    }
+}
+
+pub struct RandomLfo {
+    rate_in: BufferHandle<In<f32>>,
+    smoothing_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    bipolar: bool,
+    seed: u32,
+    state: RandomLfoState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct RandomLfoSettings {
+    pub rate: f32,
+    pub smoothing: f32,
+    pub bipolar: bool,
+    pub seed: u32,
+}
+
+impl Default for RandomLfoSettings {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            smoothing: 0.0,
+            bipolar: true,
+            seed: 0x1234_5678,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RandomLfoState {
+    rng: u32,
+    phase: f32,
+    held: f32,
+    value: f32,
+}
+
+impl RandomLfoState {
+    fn new(seed: u32) -> Self {
+        let mut state = Self {
+            rng: seed.max(1),
+            phase: 0.0,
+            held: 0.0,
+            value: 0.0,
+        };
+        state.held = state.next_random();
+        state.value = state.held;
+        state
+    }
+
+    fn next_random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f32 / u32::MAX as f32
+    }
+}
+
+impl StateSnapshot for RandomLfo {
+    type State = RandomLfoState;
+
+    fn snapshot(&self) -> RandomLfoState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &RandomLfoState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for RandomLfo {
+    type Settings = RandomLfoSettings;
+    type Error = Infallible;
+}
+
+impl Module for RandomLfo {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: RandomLfoSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            rate_in: desc.with_buf_in_default::<f32>("rate", settings.rate),
+            smoothing_in: desc.with_buf_in_default::<f32>("smoothing", settings.smoothing),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            bipolar: settings.bipolar,
+            seed: settings.seed,
+            state: RandomLfoState::new(settings.seed),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        for ((&rate, &smoothing), out) in buffers_in
+            .get(self.rate_in)
+            .iter()
+            .zip(buffers_in.get(self.smoothing_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            self.state.phase += rate.max(0.0) * sample_time;
+            if self.state.phase >= 1.0 {
+                self.state.phase -= self.state.phase.floor();
+                self.state.held = self.state.next_random();
+            }
+
+            if smoothing > 0.0 {
+                self.state.value +=
+                    (self.state.held - self.state.value) * (1.0 - (-sample_time / smoothing).exp());
+            } else {
+                self.state.value = self.state.held;
+            }
+
+            *out = if self.bipolar {
+                self.state.value * 2.0 - 1.0
+            } else {
+                self.state.value
+            };
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = RandomLfoState::new(self.seed);
+    }
+}
+
+pub struct Ducker {
+    signal_in: BufferHandle<In<f32>>,
+    key_in: BufferHandle<In<f32>>,
+    midi_in: BufferHandle<In<MidiEvents>>,
+    amount_in: BufferHandle<In<f32>>,
+    attack_in: BufferHandle<In<f32>>,
+    release_in: BufferHandle<In<f32>>,
+    threshold_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    state: DuckerState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct DuckerSettings {
+    pub amount: f32,
+    pub attack: f32,
+    pub release: f32,
+    pub threshold: f32,
+}
+
+impl Default for DuckerSettings {
+    fn default() -> Self {
+        Self {
+            amount: 0.75,
+            attack: 0.005,
+            release: 0.2,
+            threshold: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct DuckerState {
+    reduction: f32,
+    held_notes: usize,
+}
+
+impl StateSnapshot for Ducker {
+    type State = DuckerState;
+
+    fn snapshot(&self) -> DuckerState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &DuckerState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for Ducker {
+    type Settings = DuckerSettings;
+    type Error = Infallible;
+}
+
+impl Module for Ducker {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: DuckerSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            key_in: desc.with_buf_in::<f32>("key"),
+            midi_in: desc.with_buf_in::<MidiEvents>("midi"),
+            amount_in: desc.with_buf_in_default::<f32>("amount", settings.amount),
+            attack_in: desc.with_buf_in_default::<f32>("attack", settings.attack),
+            release_in: desc.with_buf_in_default::<f32>("release", settings.release),
+            threshold_in: desc.with_buf_in_default::<f32>("threshold", settings.threshold),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let signal_in = buffers_in.get(self.signal_in);
+        let key_in = buffers_in.get(self.key_in);
+        let midi_in = buffers_in.get(self.midi_in);
+        let amount_in = buffers_in.get(self.amount_in);
+        let attack_in = buffers_in.get(self.attack_in);
+        let release_in = buffers_in.get(self.release_in);
+        let threshold_in = buffers_in.get(self.threshold_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            for event in midi_in[i].iter() {
+                if let MidiEvent::Midi { message, .. } = event {
+                    match message {
+                        midly::MidiMessage::NoteOn { vel, .. } if vel.as_int() > 0 => {
+                            self.state.held_notes += 1
+                        }
+                        midly::MidiMessage::NoteOn { .. } | midly::MidiMessage::NoteOff { .. } => {
+                            self.state.held_notes = self.state.held_notes.saturating_sub(1)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            let triggered = key_in[i].abs() > threshold_in[i] || self.state.held_notes > 0;
+            let target = if triggered {
+                amount_in[i].clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let time = if target > self.state.reduction {
+                attack_in[i]
+            } else {
+                release_in[i]
+            };
+            if time > 0.0 {
+                self.state.reduction +=
+                    (target - self.state.reduction) * (1.0 - (-sample_time / time).exp());
+            } else {
+                self.state.reduction = target;
+            }
+
+            signal_out[i] = signal_in[i] * (1.0 - self.state.reduction);
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+// one-pole peak follower: rises with the attack time constant, falls with the release one
+fn follow_envelope(level: f32, input: f32, attack: f32, release: f32, sample_time: f32) -> f32 {
+    let input = input.abs();
+    let time = if input > level { attack } else { release };
+    if time > 0.0 {
+        level + (input - level) * (1.0 - (-sample_time / time).exp())
+    } else {
+        input
+    }
+}
+
+pub struct EnvelopeFollower {
+    signal_in: BufferHandle<In<f32>>,
+    attack_in: BufferHandle<In<f32>>,
+    release_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    level: f32,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct EnvelopeFollowerSettings {
+    pub attack: f32,
+    pub release: f32,
+}
+
+impl Default for EnvelopeFollowerSettings {
+    fn default() -> Self {
+        Self {
+            attack: 0.005,
+            release: 0.2,
+        }
+    }
+}
+
+impl StateSnapshot for EnvelopeFollower {
+    type State = f32;
+
+    fn snapshot(&self) -> f32 {
+        self.level
+    }
+
+    fn restore(&mut self, state: &f32) {
+        self.level = *state;
+    }
+}
+
+impl ModuleSettings for EnvelopeFollower {
+    type Settings = EnvelopeFollowerSettings;
+    type Error = Infallible;
+}
+
+impl Module for EnvelopeFollower {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: EnvelopeFollowerSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            attack_in: desc.with_buf_in_default::<f32>("attack", settings.attack),
+            release_in: desc.with_buf_in_default::<f32>("release", settings.release),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            level: 0.0,
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let signal_in = buffers_in.get(self.signal_in);
+        let attack_in = buffers_in.get(self.attack_in);
+        let release_in = buffers_in.get(self.release_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            self.level = follow_envelope(
+                self.level,
+                signal_in[i],
+                attack_in[i],
+                release_in[i],
+                sample_time,
+            );
+            signal_out[i] = self.level;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.level = 0.0;
+    }
+}
+
+// taken by Waveshaper and Compressor; the ladder filter's tanh feedback still runs at the
+// base rate
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Oversampling {
+    #[default]
+    None,
+    X2,
+    X4,
+}
+
+impl Oversampling {
+    pub fn factor(self) -> usize {
+        match self {
+            Self::None => 1,
+            Self::X2 => 2,
+            Self::X4 => 4,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Oversampler {
+    factor: usize,
+    kernel: Vec<f32>,
+    up_history: Vec<f32>,
+    up_index: usize,
+    down_history: Vec<f32>,
+    down_index: usize,
+}
+
+impl Oversampler {
+    const TAPS_PER_FACTOR: usize = 16;
+
+    pub fn new(oversampling: Oversampling) -> Self {
+        let factor = oversampling.factor();
+        let kernel = if factor > 1 {
+            let len = Self::TAPS_PER_FACTOR * factor + 1;
+            let center = (len - 1) as f32 / 2.0;
+            let mut kernel = (0..len)
+                .map(|i| {
+                    let x = (i as f32 - center) / factor as f32;
+                    let sinc = if x == 0.0 {
+                        1.0
+                    } else {
+                        (std::f32::consts::PI * x).sin() / (std::f32::consts::PI * x)
+                    };
+                    let w = 2.0 * std::f32::consts::PI * i as f32 / (len - 1) as f32;
+                    sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos())
+                })
+                .collect::<Vec<_>>();
+            let sum: f32 = kernel.iter().sum();
+            for tap in kernel.iter_mut() {
+                *tap /= sum;
+            }
+            kernel
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            factor,
+            up_history: vec![0.0; kernel.len().div_ceil(factor)],
+            up_index: 0,
+            down_history: vec![0.0; kernel.len()],
+            down_index: 0,
+            kernel,
+        }
+    }
+
+    pub fn latency(&self) -> usize {
+        self.kernel.len().saturating_sub(1) / self.factor
+    }
+
+    pub fn process(&mut self, input: f32, mut f: impl FnMut(f32) -> f32) -> f32 {
+        if self.factor == 1 {
+            return f(input);
+        }
+
+        let phase_len = self.up_history.len();
+        let down_len = self.down_history.len();
+        self.up_history[self.up_index] = input;
+        for phase in 0..self.factor {
+            let mut upsampled = 0.0;
+            for k in 0..phase_len {
+                if let Some(&tap) = self.kernel.get(phase + k * self.factor) {
+                    upsampled += tap * self.up_history[(self.up_index + phase_len - k) % phase_len];
+                }
+            }
+            self.down_history[self.down_index] = f(upsampled * self.factor as f32);
+            self.down_index = (self.down_index + 1) % down_len;
+        }
+        self.up_index = (self.up_index + 1) % phase_len;
+
+        self.kernel
+            .iter()
+            .enumerate()
+            .map(|(k, &tap)| {
+                tap * self.down_history[(self.down_index + down_len - 1 - k) % down_len]
+            })
+            .sum()
+    }
+
+    pub fn clear(&mut self) {
+        for sample in self
+            .up_history
+            .iter_mut()
+            .chain(self.down_history.iter_mut())
+        {
+            *sample = 0.0;
+        }
+        self.up_index = 0;
+        self.down_index = 0;
+    }
+}
+
+pub struct StateVariableFilter {
+    signal_in: BufferHandle<In<f32>>,
+    cutoff_in: BufferHandle<In<f32>>,
+    q_in: BufferHandle<In<f32>>,
+    lp_out: BufferHandle<Out<f32>>,
+    hp_out: BufferHandle<Out<f32>>,
+    bp_out: BufferHandle<Out<f32>>,
+    notch_out: BufferHandle<Out<f32>>,
+    state: StateVariableFilterState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct StateVariableFilterSettings {
+    pub cutoff: f32,
+    pub q: f32,
+}
+
+#[derive(Clone, Default)]
+pub struct StateVariableFilterState {
+    low: f32,
+    band: f32,
+}
+
+impl StateSnapshot for StateVariableFilter {
+    type State = StateVariableFilterState;
+
+    fn snapshot(&self) -> StateVariableFilterState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &StateVariableFilterState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for StateVariableFilter {
+    type Settings = StateVariableFilterSettings;
+    type Error = Infallible;
+}
+
+impl Module for StateVariableFilter {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: StateVariableFilterSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            cutoff_in: desc.with_buf_in_default::<f32>("cutoff", settings.cutoff),
+            q_in: desc.with_buf_in_default::<f32>("q", settings.q),
+            lp_out: desc.with_buf_out::<f32>("lp"),
+            hp_out: desc.with_buf_out::<f32>("hp"),
+            bp_out: desc.with_buf_out::<f32>("bp"),
+            notch_out: desc.with_buf_out::<f32>("notch"),
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let signal_in = buffers_in.get(self.signal_in);
+        let cutoff_in = buffers_in.get(self.cutoff_in);
+        let q_in = buffers_in.get(self.q_in);
+        let [lp_out, hp_out, bp_out, notch_out] =
+            buffers_out.get_many([self.lp_out, self.hp_out, self.bp_out, self.notch_out]);
+
+        for i in 0..signal_in.len() {
+            let cutoff = cutoff_in[i].max(0.0).min(self.sample_rate / 6.0);
+            let f = 2.0 * (std::f32::consts::PI * cutoff * sample_time).sin();
+            let damping = (1.0 / q_in[i].max(0.5)).min(2.0 - f);
+
+            self.state.low += f * self.state.band;
+            let high = signal_in[i] - self.state.low - damping * self.state.band;
+            self.state.band += f * high;
+
+            lp_out[i] = self.state.low;
+            hp_out[i] = high;
+            bp_out[i] = self.state.band;
+            notch_out[i] = high + self.state.low;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+pub struct LadderFilter {
+    signal_in: BufferHandle<In<f32>>,
+    cutoff_in: BufferHandle<In<f32>>,
+    resonance_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    state: LadderFilterState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct LadderFilterSettings {
+    pub cutoff: f32,
+    pub resonance: f32,
+}
+
+#[derive(Clone)]
+pub struct LadderFilterState {
+    stage_in: [f32; 4],
+    stage_out: [f32; 4],
+    noise: u32,
+}
+
+impl Default for LadderFilterState {
+    fn default() -> Self {
+        Self {
+            stage_in: [0.0; 4],
+            stage_out: [0.0; 4],
+            noise: 0x6d2b_79f5,
+        }
+    }
+}
+
+impl StateSnapshot for LadderFilter {
+    type State = LadderFilterState;
+
+    fn snapshot(&self) -> LadderFilterState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &LadderFilterState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for LadderFilter {
+    type Settings = LadderFilterSettings;
+    type Error = Infallible;
+}
+
+impl Module for LadderFilter {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: LadderFilterSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            cutoff_in: desc.with_buf_in_default::<f32>("cutoff", settings.cutoff),
+            resonance_in: desc.with_buf_in_default::<f32>("resonance", settings.resonance),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        for (((&signal_in, &cutoff), &resonance), out) in buffers_in
+            .get(self.signal_in)
+            .iter()
+            .zip(buffers_in.get(self.cutoff_in).iter())
+            .zip(buffers_in.get(self.resonance_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            // a little noise keeps the loop excited so it can self-oscillate from silence
+            let state = &mut self.state;
+            state.noise ^= state.noise << 13;
+            state.noise ^= state.noise >> 17;
+            state.noise ^= state.noise << 5;
+            let noise = (state.noise as f32 / u32::MAX as f32 - 0.5) * 1e-6;
+
+            // each stage's pole sits at `f` radians per sample, which is where the loop rings
+            let f = (cutoff * sample_time * std::f32::consts::TAU).clamp(0.0, 1.0);
+            // the loop only starts to self-oscillate just past 4, so leave headroom above it
+            let feedback = resonance.clamp(0.0, 4.5) * (1.0 - 0.15 * f * f);
+            let mut x = signal_in + noise - (state.stage_out[3] * feedback).tanh();
+            x *= 0.35013 * f * f * f * f;
+            for stage in 0..4 {
+                let y = x + 0.3 * state.stage_in[stage] + (1.0 - f) * state.stage_out[stage];
+                state.stage_in[stage] = x;
+                state.stage_out[stage] = y;
+                x = y;
+            }
+            *out = state.stage_out[3];
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+pub struct Delay {
+    signal_in: BufferHandle<In<f32>>,
+    delay_time_in: BufferHandle<In<f32>>,
+    feedback_in: BufferHandle<In<f32>>,
+    mix_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    line: DelayLine,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct DelaySettings {
+    pub max_delay_secs: f32,
+}
+
+#[derive(Clone)]
+pub struct DelayState {
+    line: DelayLine,
+}
+
+impl StateSnapshot for Delay {
+    type State = DelayState;
+
+    fn snapshot(&self) -> DelayState {
+        DelayState {
+            line: self.line.clone(),
+        }
+    }
+
+    fn restore(&mut self, state: &DelayState) {
+        self.line.clone_from(&state.line);
+    }
+}
+
+impl ModuleSettings for Delay {
+    type Settings = DelaySettings;
+    type Error = Infallible;
+}
+
+impl Module for Delay {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: DelaySettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            delay_time_in: desc
+                .with_buf_in_default::<f32>("delay_time", settings.max_delay_secs * 0.5),
+            feedback_in: desc.with_buf_in_default::<f32>("feedback", 0.0),
+            mix_in: desc.with_buf_in_default::<f32>("mix", 0.5),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            line: DelayLine::new(settings.max_delay_secs, desc.sample_rate() as f32),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        for ((((&signal_in, &delay_time), &feedback), &mix), out) in buffers_in
+            .get(self.signal_in)
+            .iter()
+            .zip(buffers_in.get(self.delay_time_in).iter())
+            .zip(buffers_in.get(self.feedback_in).iter())
+            .zip(buffers_in.get(self.mix_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            let feedback = feedback.clamp(0.0, 0.999);
+            let delayed = self.line.read(delay_time * self.sample_rate);
+            self.line.write(signal_in + delayed * feedback);
+            *out = signal_in * (1.0 - mix) + delayed * mix;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.line.clear();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum NoiseKind {
+    White,
+    Pink,
+}
+
+#[derive(Clone)]
+pub struct NoiseSettings {
+    pub kind: NoiseKind,
+    pub seed: u32,
+}
+
+pub struct Noise {
+    amplitude_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    kind: NoiseKind,
+    seed: u32,
+    state: NoiseState,
+}
+
+#[derive(Clone)]
+pub struct NoiseState {
+    rng: u32,
+    pink: [f32; 7],
+}
+
+impl NoiseState {
+    fn new(seed: u32) -> Self {
+        Self {
+            rng: seed.max(1),
+            pink: [0.0; 7],
+        }
+    }
+
+    fn next_white(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    // Paul Kellet's refined pink noise filter
+    fn next_pink(&mut self) -> f32 {
+        let white = self.next_white();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153_852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * 0.11
+    }
+}
+
+impl StateSnapshot for Noise {
+    type State = NoiseState;
+
+    fn snapshot(&self) -> NoiseState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &NoiseState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for Noise {
+    type Settings = NoiseSettings;
+    type Error = Infallible;
+}
+
+impl Module for Noise {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: NoiseSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            amplitude_in: desc.with_buf_in_default::<f32>("amplitude", 1.0),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            kind: settings.kind,
+            seed: settings.seed,
+            state: NoiseState::new(settings.seed),
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        for (&amplitude, out) in buffers_in
+            .get(self.amplitude_in)
+            .iter()
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            let sample = match self.kind {
+                NoiseKind::White => self.state.next_white(),
+                NoiseKind::Pink => self.state.next_pink(),
+            };
+            *out = sample * amplitude;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = NoiseState::new(self.seed);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum LfoWaveform {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+    SampleAndHold,
+}
+
+#[derive(Clone)]
+pub struct LfoSettings {
+    pub waveform: LfoWaveform,
+    pub rate_hz: f32,
+    pub bipolar: bool,
+}
+
+pub struct Lfo {
+    rate_in: BufferHandle<In<f32>>,
+    reset_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    waveform: LfoWaveform,
+    bipolar: bool,
+    state: LfoState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct LfoState {
+    phase: f32,
+    reset_high: bool,
+    held: f32,
+    rng: u32,
+}
+
+impl Default for LfoState {
+    fn default() -> Self {
+        Self {
+            phase: 0.0,
+            reset_high: false,
+            held: 0.0,
+            rng: 0x2f6b_1a93,
+        }
+    }
+}
+
+impl StateSnapshot for Lfo {
+    type State = LfoState;
+
+    fn snapshot(&self) -> LfoState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &LfoState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for Lfo {
+    type Settings = LfoSettings;
+    type Error = Infallible;
+}
+
+impl Module for Lfo {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: LfoSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            rate_in: desc.with_buf_in_default::<f32>("rate", settings.rate_hz),
+            reset_in: desc.with_buf_in::<f32>("reset"),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            waveform: settings.waveform,
+            bipolar: settings.bipolar,
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        for ((&rate, &reset), out) in buffers_in
+            .get(self.rate_in)
+            .iter()
+            .zip(buffers_in.get(self.reset_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            let reset_high = reset > 0.0;
+            if reset_high && !self.state.reset_high {
+                self.state.phase = 0.0;
+            }
+            self.state.reset_high = reset_high;
+
+            let phase = self.state.phase;
+            // every shape below is bipolar in [-1, 1]
+            let value = match self.waveform {
+                LfoWaveform::Sine => (phase * 2.0 * std::f32::consts::PI).sin(),
+                LfoWaveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+                LfoWaveform::Saw => phase * 2.0 - 1.0,
+                LfoWaveform::Square => {
+                    if phase < 0.5 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                LfoWaveform::SampleAndHold => self.state.held,
+            };
+            *out = if self.bipolar {
+                value
+            } else {
+                (value + 1.0) * 0.5
+            };
+
+            self.state.phase += rate * sample_time;
+            if self.state.phase >= 1.0 || self.state.phase < 0.0 {
+                self.state.phase = self.state.phase.rem_euclid(1.0);
+                self.state.rng ^= self.state.rng << 13;
+                self.state.rng ^= self.state.rng >> 17;
+                self.state.rng ^= self.state.rng << 5;
+                self.state.held = self.state.rng as f32 / u32::MAX as f32 * 2.0 - 1.0;
+            }
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset_phase(&mut self) {
+        self.state.phase = 0.0;
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum LoopMode {
+    OneShot,
+    Loop,
+}
+
+#[derive(Clone)]
+pub struct SamplerSettings {
+    pub path: PathBuf,
+    pub root_note: u8,
+    pub loop_mode: LoopMode,
+}
+
+#[derive(Error, Debug)]
+pub enum SamplerError {
+    #[error("failed to read sample file `{path}`")]
+    Read { path: PathBuf, source: hound::Error },
+    #[error("sample file `{0}` contains no audio")]
+    Empty(PathBuf),
+}
+
+pub struct Sampler {
+    midi_in: BufferHandle<In<MidiEvents>>,
+    signal_out: BufferHandle<Out<f32>>,
+    samples: Vec<f32>,
+    rate_scale: f32,
+    root_note: u8,
+    loop_mode: LoopMode,
+    state: SamplerState,
+}
+
+#[derive(Clone, Default)]
+pub struct SamplerState {
+    playing: bool,
+    position: f32,
+    increment: f32,
+    gain: f32,
+}
+
+impl Sampler {
+    fn load(path: &Path) -> Result<(Vec<f32>, u32), hound::Error> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let interleaved = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|s| s.map(|s| s as f32 * scale))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+        };
+        let channels = spec.channels.max(1) as usize;
+        let mono = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        Ok((mono, spec.sample_rate))
+    }
+
+    fn read(&self, position: f32) -> f32 {
+        let idx = position as usize;
+        let frac = position - idx as f32;
+        let next = match self.loop_mode {
+            LoopMode::Loop => (idx + 1) % self.samples.len(),
+            LoopMode::OneShot => idx + 1,
+        };
+        self.samples[idx] * (1.0 - frac) + self.samples.get(next).copied().unwrap_or(0.0) * frac
+    }
+}
+
+impl StateSnapshot for Sampler {
+    type State = SamplerState;
+
+    fn snapshot(&self) -> SamplerState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &SamplerState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for Sampler {
+    type Settings = SamplerSettings;
+    type Error = SamplerError;
+}
+
+impl Module for Sampler {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: SamplerSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, SamplerError> {
+        let (samples, sample_rate) =
+            Self::load(&settings.path).map_err(|source| SamplerError::Read {
+                path: settings.path.clone(),
+                source,
+            })?;
+        if samples.is_empty() {
+            return Err(SamplerError::Empty(settings.path));
+        }
+
+        let module = Self {
+            midi_in: desc.with_buf_in::<MidiEvents>("in"),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            samples,
+            rate_scale: sample_rate as f32 / desc.sample_rate() as f32,
+            root_note: settings.root_note,
+            loop_mode: settings.loop_mode,
+            state: Default::default(),
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let midi_in = buffers_in.get(self.midi_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            for midi in midi_in[i].iter() {
+                if let MidiEvent::Midi { message, .. } = midi {
+                    match message {
+                        midly::MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            let semitones = key.as_int() as f32 - self.root_note as f32;
+                            self.state = SamplerState {
+                                playing: true,
+                                position: 0.0,
+                                increment: (semitones / 12.0).exp2() * self.rate_scale,
+                                gain: vel.as_int() as f32 / 127.0,
+                            };
+                        }
+                        midly::MidiMessage::NoteOn { .. } | midly::MidiMessage::NoteOff { .. } => {
+                            if let LoopMode::Loop = self.loop_mode {
+                                self.state.playing = false;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            if !self.state.playing {
+                signal_out[i] = 0.0;
+                continue;
+            }
+
+            signal_out[i] = self.read(self.state.position) * self.state.gain;
+            self.state.position += self.state.increment;
+            let len = self.samples.len() as f32;
+            if self.state.position >= len {
+                match self.loop_mode {
+                    LoopMode::Loop => self.state.position = self.state.position.rem_euclid(len),
+                    LoopMode::OneShot => self.state.playing = false,
+                }
+            }
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+pub struct PluckedString {
+    midi_in: BufferHandle<In<MidiEvents>>,
+    damping_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    state: PluckedStringState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct PluckedStringSettings {
+    pub damping: f32,
+}
+
+#[derive(Clone)]
+pub struct PluckedStringState {
+    line: Vec<f32>,
+    index: usize,
+    rng: u32,
+}
+
+impl Default for PluckedStringState {
+    fn default() -> Self {
+        Self {
+            line: Vec::new(),
+            index: 0,
+            rng: 0x3c6e_f372,
+        }
+    }
+}
+
+impl PluckedStringState {
+    fn pluck(&mut self, key: u8, velocity: u8, sample_rate: f32) {
+        let frequency = ((key as f32 - 69.0) / 12.0).exp2() * 440.0;
+        let len = ((sample_rate / frequency) as usize).max(2);
+        let amplitude = velocity as f32 / 127.0;
+        let rng = &mut self.rng;
+        self.line = (0..len)
+            .map(|_| {
+                *rng ^= *rng << 13;
+                *rng ^= *rng >> 17;
+                *rng ^= *rng << 5;
+                (*rng as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect();
+        self.index = 0;
+    }
+}
+
+impl StateSnapshot for PluckedString {
+    type State = PluckedStringState;
+
+    fn snapshot(&self) -> PluckedStringState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &PluckedStringState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for PluckedString {
+    type Settings = PluckedStringSettings;
+    type Error = Infallible;
+}
+
+impl Module for PluckedString {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: PluckedStringSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            midi_in: desc.with_buf_in::<MidiEvents>("in"),
+            damping_in: desc.with_buf_in_default::<f32>("damping", settings.damping),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let midi_in = buffers_in.get(self.midi_in);
+        let damping_in = buffers_in.get(self.damping_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            for midi in midi_in[i].iter() {
+                if let MidiEvent::Midi {
+                    message: midly::MidiMessage::NoteOn { key, vel },
+                    ..
+                } = midi
+                {
+                    if vel.as_int() > 0 {
+                        self.state
+                            .pluck(key.as_int(), vel.as_int(), self.sample_rate);
+                    }
+                }
+            }
+
+            let len = self.state.line.len();
+            if len == 0 {
+                signal_out[i] = 0.0;
+                continue;
+            }
+
+            let blend = damping_in[i].clamp(0.0, 1.0) * 0.5;
+            let current = self.state.line[self.state.index];
+            let next = self.state.line[(self.state.index + 1) % len];
+            self.state.line[self.state.index] = (current * (1.0 - blend) + next * blend) * 0.996;
+            self.state.index = (self.state.index + 1) % len;
+            signal_out[i] = current;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ShaperKind {
+    Tanh,
+    HardClip,
+    Cubic,
+    Arctan,
+}
+
+impl ShaperKind {
+    fn apply(self, x: f32) -> f32 {
+        match self {
+            Self::Tanh => x.tanh(),
+            Self::HardClip => x.clamp(-1.0, 1.0),
+            Self::Cubic => {
+                let x = x.clamp(-1.0, 1.0);
+                1.5 * x - 0.5 * x * x * x
+            }
+            Self::Arctan => x.atan() * std::f32::consts::FRAC_2_PI,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShaperSettings {
+    pub kind: ShaperKind,
+    pub oversampling: Oversampling,
+}
+
+pub struct Waveshaper {
+    signal_in: BufferHandle<In<f32>>,
+    drive_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    kind: ShaperKind,
+    oversampler: Oversampler,
+}
+
+impl Waveshaper {
+    pub fn latency(&self) -> usize {
+        self.oversampler.latency()
+    }
+}
+
+impl StateSnapshot for Waveshaper {
+    type State = Oversampler;
+
+    fn snapshot(&self) -> Oversampler {
+        self.oversampler.clone()
+    }
+
+    fn restore(&mut self, state: &Oversampler) {
+        self.oversampler.clone_from(state);
+    }
+}
+
+impl ModuleSettings for Waveshaper {
+    type Settings = ShaperSettings;
+    type Error = Infallible;
+}
+
+impl Module for Waveshaper {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: ShaperSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            drive_in: desc.with_buf_in_default::<f32>("drive", 1.0),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            kind: settings.kind,
+            oversampler: Oversampler::new(settings.oversampling),
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let kind = self.kind;
+        for ((&signal_in, &drive), out) in buffers_in
+            .get(self.signal_in)
+            .iter()
+            .zip(buffers_in.get(self.drive_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            *out = self
+                .oversampler
+                .process(signal_in, |x| kind.apply(x * drive));
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.oversampler.clear();
+    }
+}
+
+pub struct Bitcrusher {
+    signal_in: BufferHandle<In<f32>>,
+    bits_in: BufferHandle<In<f32>>,
+    downsample_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    state: BitcrusherState,
+}
+
+#[derive(Clone)]
+pub struct BitcrusherSettings {
+    pub bits: f32,
+    pub downsample: f32,
+}
+
+#[derive(Clone, Default)]
+pub struct BitcrusherState {
+    held: f32,
+    counter: usize,
+}
+
+impl StateSnapshot for Bitcrusher {
+    type State = BitcrusherState;
+
+    fn snapshot(&self) -> BitcrusherState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &BitcrusherState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for Bitcrusher {
+    type Settings = BitcrusherSettings;
+    type Error = Infallible;
+}
+
+impl Module for Bitcrusher {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: BitcrusherSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            bits_in: desc.with_buf_in_default::<f32>("bits", settings.bits),
+            downsample_in: desc.with_buf_in_default::<f32>("downsample", settings.downsample),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: Default::default(),
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        for (((&signal_in, &bits), &downsample), out) in buffers_in
+            .get(self.signal_in)
+            .iter()
+            .zip(buffers_in.get(self.bits_in).iter())
+            .zip(buffers_in.get(self.downsample_in).iter())
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            if self.state.counter == 0 {
+                let step = 2.0 / (bits.clamp(1.0, 24.0).exp2() - 1.0);
+                let x = signal_in.clamp(-1.0, 1.0);
+                self.state.held = ((x + 1.0) / step).round() * step - 1.0;
+            }
+            self.state.counter += 1;
+            if self.state.counter >= (downsample.round() as usize).max(1) {
+                self.state.counter = 0;
+            }
+            *out = self.state.held;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+pub struct Panner {
+    signal_in: BufferHandle<In<f32>>,
+    pan_in: BufferHandle<In<f32>>,
+    left_out: BufferHandle<Out<f32>>,
+    right_out: BufferHandle<Out<f32>>,
+}
+
+impl ModuleSettings for Panner {
+    type Settings = f32;
+    type Error = Infallible;
+}
+
+impl Module for Panner {
+    fn init(
+        mut desc: ModuleDescriptor,
+        pan: f32,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            pan_in: desc.with_buf_in_default::<f32>("pan", pan),
+            left_out: desc.with_buf_out::<f32>("out_left"),
+            right_out: desc.with_buf_out::<f32>("out_right"),
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let signal_in = buffers_in.get(self.signal_in);
+        let pan_in = buffers_in.get(self.pan_in);
+        let [left_out, right_out] = buffers_out.get_many([self.left_out, self.right_out]);
+
+        for i in 0..signal_in.len() {
+            let angle = (pan_in[i].clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+            left_out[i] = signal_in[i] * angle.cos();
+            right_out[i] = signal_in[i] * angle.sin();
+        }
+    }
+}
+
+pub struct RingMod {
+    signal_in: BufferHandle<In<f32>>,
+    carrier_freq_in: BufferHandle<In<f32>>,
+    mix_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    phase: f32,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct RingModSettings {
+    pub carrier_freq: f32,
+    pub mix: f32,
+}
+
+impl Default for RingModSettings {
+    fn default() -> Self {
+        Self {
+            carrier_freq: 100.0,
+            mix: 1.0,
+        }
+    }
+}
+
+impl StateSnapshot for RingMod {
+    type State = f32;
+
+    fn snapshot(&self) -> f32 {
+        self.phase
+    }
+
+    fn restore(&mut self, state: &f32) {
+        self.phase = *state;
+    }
+}
+
+impl ModuleSettings for RingMod {
+    type Settings = RingModSettings;
+    type Error = Infallible;
+}
+
+impl Module for RingMod {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: RingModSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            carrier_freq_in: desc.with_buf_in_default::<f32>("carrier_freq", settings.carrier_freq),
+            mix_in: desc.with_buf_in_default::<f32>("mix", settings.mix),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            phase: 0.0,
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let signal_in = buffers_in.get(self.signal_in);
+        let carrier_freq_in = buffers_in.get(self.carrier_freq_in);
+        let mix_in = buffers_in.get(self.mix_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            let carrier = (self.phase * std::f32::consts::TAU).sin();
+            let mix = mix_in[i].clamp(0.0, 1.0);
+            signal_out[i] = signal_in[i] * (1.0 - mix) + signal_in[i] * carrier * mix;
+
+            self.phase += carrier_freq_in[i] * sample_time;
+            if self.phase >= 1.0 || self.phase < 0.0 {
+                self.phase = self.phase.rem_euclid(1.0);
+            }
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset_phase(&mut self) {
+        self.phase = 0.0;
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+}
+
+// Freeverb tunings, in samples at 44.1 kHz
+const REVERB_COMB_LENS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const REVERB_ALLPASS_LENS: [usize; 4] = [556, 441, 341, 225];
+const REVERB_TUNING_RATE: f32 = 44100.0;
+
+#[derive(Clone)]
+struct CombFilter {
+    line: DelayLine,
+    len: f32,
+    filter_store: f32,
+}
+
+impl CombFilter {
+    fn new(len: usize) -> Self {
+        Self {
+            line: DelayLine::with_len(len + 1),
+            len: len as f32,
+            filter_store: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let out = self.line.read(self.len);
+        self.filter_store = out * (1.0 - damping) + self.filter_store * damping;
+        self.line.write(input + self.filter_store * feedback);
+        out
+    }
+
+    fn clear(&mut self) {
+        self.line.clear();
+        self.filter_store = 0.0;
+    }
+}
+
+pub struct Reverb {
+    signal_in: BufferHandle<In<f32>>,
+    room_size_in: BufferHandle<In<f32>>,
+    damping_in: BufferHandle<In<f32>>,
+    mix_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    state: ReverbState,
+}
+
+#[derive(Clone)]
+pub struct ReverbSettings {
+    pub room_size: f32,
+    pub damping: f32,
+    pub mix: f32,
+}
+
+impl Default for ReverbSettings {
+    fn default() -> Self {
+        Self {
+            room_size: 0.5,
+            damping: 0.5,
+            mix: 0.3,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ReverbState {
+    combs: Vec<CombFilter>,
+    allpasses: Vec<(DelayLine, f32)>,
+}
+
+impl ReverbState {
+    fn new(sample_rate: f32) -> Self {
+        let scale = |len: usize| ((len as f32 * sample_rate / REVERB_TUNING_RATE) as usize).max(1);
+        Self {
+            combs: REVERB_COMB_LENS
+                .iter()
+                .map(|&len| CombFilter::new(scale(len)))
+                .collect(),
+            allpasses: REVERB_ALLPASS_LENS
+                .iter()
+                .map(|&len| {
+                    let len = scale(len);
+                    (DelayLine::with_len(len + 1), len as f32)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl StateSnapshot for Reverb {
+    type State = ReverbState;
+
+    fn snapshot(&self) -> ReverbState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &ReverbState) {
+        self.state.clone_from(state);
+    }
+}
+
+impl ModuleSettings for Reverb {
+    type Settings = ReverbSettings;
+    type Error = Infallible;
+}
+
+impl Module for Reverb {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: ReverbSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            room_size_in: desc.with_buf_in_default::<f32>("room_size", settings.room_size),
+            damping_in: desc.with_buf_in_default::<f32>("damping", settings.damping),
+            mix_in: desc.with_buf_in_default::<f32>("mix", settings.mix),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: ReverbState::new(desc.sample_rate() as f32),
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let signal_in = buffers_in.get(self.signal_in);
+        let room_size_in = buffers_in.get(self.room_size_in);
+        let damping_in = buffers_in.get(self.damping_in);
+        let mix_in = buffers_in.get(self.mix_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            let feedback = room_size_in[i].clamp(0.0, 1.0) * 0.28 + 0.7;
+            let damping = damping_in[i].clamp(0.0, 1.0) * 0.4;
+            let mix = mix_in[i].clamp(0.0, 1.0);
+
+            let input = signal_in[i] * 0.015;
+            let mut wet = 0.0;
+            for comb in self.state.combs.iter_mut() {
+                wet += comb.process(input, feedback, damping);
+            }
+            for (line, len) in self.state.allpasses.iter_mut() {
+                let delayed = line.read(*len);
+                line.write(wet + delayed * 0.5);
+                wet = delayed - wet;
+            }
+
+            signal_out[i] = signal_in[i] * (1.0 - mix) + wet * 3.0 * mix;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        for comb in self.state.combs.iter_mut() {
+            comb.clear();
+        }
+        for (line, _) in self.state.allpasses.iter_mut() {
+            line.clear();
+        }
+    }
+}
+
+const CHORUS_MIN_DELAY: f32 = 0.005; // seconds
+const CHORUS_MAX_DELAY: f32 = 0.03; // seconds
+
+pub struct Chorus {
+    signal_in: BufferHandle<In<f32>>,
+    rate_in: BufferHandle<In<f32>>,
+    depth_in: BufferHandle<In<f32>>,
+    mix_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    state: ChorusState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct ChorusSettings {
+    pub rate_hz: f32,
+    pub depth: f32,
+    pub mix: f32,
+}
+
+impl Default for ChorusSettings {
+    fn default() -> Self {
+        Self {
+            rate_hz: 0.8,
+            depth: 0.5,
+            mix: 0.5,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ChorusState {
+    line: DelayLine,
+    phase: f32,
+}
+
+impl StateSnapshot for Chorus {
+    type State = ChorusState;
+
+    fn snapshot(&self) -> ChorusState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &ChorusState) {
+        self.state.clone_from(state);
+    }
+}
+
+impl ModuleSettings for Chorus {
+    type Settings = ChorusSettings;
+    type Error = Infallible;
+}
+
+impl Module for Chorus {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: ChorusSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            rate_in: desc.with_buf_in_default::<f32>("rate", settings.rate_hz),
+            depth_in: desc.with_buf_in_default::<f32>("depth", settings.depth),
+            mix_in: desc.with_buf_in_default::<f32>("mix", settings.mix),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: ChorusState {
+                // one extra sample for the current input at zero depth
+                line: DelayLine::with_len(
+                    (CHORUS_MAX_DELAY * desc.sample_rate() as f32) as usize + 2,
+                ),
+                phase: 0.0,
+            },
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let signal_in = buffers_in.get(self.signal_in);
+        let rate_in = buffers_in.get(self.rate_in);
+        let depth_in = buffers_in.get(self.depth_in);
+        let mix_in = buffers_in.get(self.mix_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            let depth = depth_in[i].clamp(0.0, 1.0);
+            let mix = mix_in[i].clamp(0.0, 1.0);
+
+            // depth scales the whole sweep, down to a one-sample delay that reads back the
+            // sample just written, so the wet signal fades into the dry one as depth goes to zero
+            let lfo = 0.5 - 0.5 * (self.state.phase * std::f32::consts::TAU).cos();
+            let delay = (CHORUS_MIN_DELAY + (CHORUS_MAX_DELAY - CHORUS_MIN_DELAY) * lfo) * depth;
+            self.state.line.write(signal_in[i]);
+            let wet = self.state.line.read(1.0 + delay * self.sample_rate);
+
+            signal_out[i] = signal_in[i] * (1.0 - mix) + wet * mix;
+
+            self.state.phase += rate_in[i].max(0.0) * sample_time;
+            if self.state.phase >= 1.0 {
+                self.state.phase = self.state.phase.rem_euclid(1.0);
+            }
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset_phase(&mut self) {
+        self.state.phase = 0.0;
+    }
+
+    fn reset(&mut self) {
+        self.state.line.clear();
+        self.state.phase = 0.0;
+    }
+}
+
+const DC_BLOCKER_POLE: f32 = 0.995;
+
+pub struct DcBlocker {
+    signal_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    // previous input and output
+    state: (f32, f32),
+}
+
+impl StateSnapshot for DcBlocker {
+    type State = (f32, f32);
+
+    fn snapshot(&self) -> (f32, f32) {
+        self.state
+    }
+
+    fn restore(&mut self, state: &(f32, f32)) {
+        self.state = *state;
+    }
+}
+
+impl ModuleSettings for DcBlocker {
+    type Settings = ();
+    type Error = Infallible;
+}
+
+impl Module for DcBlocker {
+    fn init(
+        mut desc: ModuleDescriptor,
+        _settings: (),
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: (0.0, 0.0),
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let (mut last_in, mut last_out) = self.state;
+        for (&val_in, val_out) in buffers_in
+            .get(self.signal_in)
+            .iter()
+            .zip(buffers_out.get(self.signal_out).iter_mut())
+        {
+            last_out = val_in - last_in + DC_BLOCKER_POLE * last_out;
+            last_in = val_in;
+            *val_out = last_out;
+        }
+        self.state = (last_in, last_out);
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = (0.0, 0.0);
+    }
+}
+
+pub struct Slew {
+    signal_in: BufferHandle<In<f32>>,
+    rise_in: BufferHandle<In<f32>>,
+    fall_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    current: f32,
+    sample_rate: f32,
+}
+
+// rates are in units per second; a rate of zero or less lets that edge through unslewed
+#[derive(Clone)]
+pub struct SlewSettings {
+    pub rise: f32,
+    pub fall: f32,
+}
+
+impl Default for SlewSettings {
+    fn default() -> Self {
+        Self {
+            rise: 10.0,
+            fall: 10.0,
+        }
+    }
+}
+
+impl StateSnapshot for Slew {
+    type State = f32;
+
+    fn snapshot(&self) -> f32 {
+        self.current
+    }
+
+    fn restore(&mut self, state: &f32) {
+        self.current = *state;
+    }
+}
+
+impl ModuleSettings for Slew {
+    type Settings = SlewSettings;
+    type Error = Infallible;
+}
+
+impl Module for Slew {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: SlewSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            rise_in: desc.with_buf_in_default::<f32>("rise", settings.rise),
+            fall_in: desc.with_buf_in_default::<f32>("fall", settings.fall),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            current: 0.0,
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate;
+        let signal_in = buffers_in.get(self.signal_in);
+        let rise_in = buffers_in.get(self.rise_in);
+        let fall_in = buffers_in.get(self.fall_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            let target = signal_in[i];
+            let rate = if target > self.current {
+                rise_in[i]
+            } else {
+                fall_in[i]
+            };
+            if rate > 0.0 {
+                let max_step = rate * sample_time;
+                self.current += (target - self.current).max(-max_step).min(max_step);
+            } else {
+                self.current = target;
+            }
+            signal_out[i] = self.current;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.current = 0.0;
+    }
+}
+
+pub struct SampleAndHold {
+    signal_in: BufferHandle<In<f32>>,
+    trigger_in: BufferHandle<In<f32>>,
+    threshold_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    state: SampleAndHoldState,
+}
+
+#[derive(Clone, Default)]
+pub struct SampleAndHoldSettings {
+    pub threshold: f32,
+}
+
+#[derive(Clone, Default)]
+pub struct SampleAndHoldState {
+    held: f32,
+    trigger_high: bool,
+}
+
+impl StateSnapshot for SampleAndHold {
+    type State = SampleAndHoldState;
+
+    fn snapshot(&self) -> SampleAndHoldState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &SampleAndHoldState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for SampleAndHold {
+    type Settings = SampleAndHoldSettings;
+    type Error = Infallible;
+}
+
+impl Module for SampleAndHold {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: SampleAndHoldSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            trigger_in: desc.with_buf_in::<f32>("trigger"),
+            threshold_in: desc.with_buf_in_default::<f32>("threshold", settings.threshold),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            state: Default::default(),
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let signal_in = buffers_in.get(self.signal_in);
+        let trigger_in = buffers_in.get(self.trigger_in);
+        let threshold_in = buffers_in.get(self.threshold_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        for i in 0..signal_out.len() {
+            let trigger_high = trigger_in[i] > threshold_in[i];
+            if trigger_high && !self.state.trigger_high {
+                self.state.held = signal_in[i];
+            }
+            self.state.trigger_high = trigger_high;
+            signal_out[i] = self.state.held;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+pub struct Clock {
+    bpm_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    quarter_out: BufferHandle<Out<f32>>,
+    eighth_out: BufferHandle<Out<f32>>,
+    sixteenth_out: BufferHandle<Out<f32>>,
+    division: u32,
+    state: ClockState,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct ClockSettings {
+    pub bpm: f32,
+    // pulses per beat on `out`
+    pub division: u32,
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        Self {
+            bpm: 120.0,
+            division: 1,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ClockState {
+    // in beats since the clock started
+    position: f64,
+    // last pulse index emitted on out, quarter, eighth and sixteenth
+    last_pulses: [i64; 4],
+}
+
+impl Default for ClockState {
+    fn default() -> Self {
+        Self {
+            position: 0.0,
+            last_pulses: [-1; 4],
+        }
+    }
+}
+
+impl StateSnapshot for Clock {
+    type State = ClockState;
+
+    fn snapshot(&self) -> ClockState {
+        self.state.clone()
+    }
+
+    fn restore(&mut self, state: &ClockState) {
+        self.state = state.clone();
+    }
+}
+
+impl ModuleSettings for Clock {
+    type Settings = ClockSettings;
+    type Error = Infallible;
+}
+
+impl Module for Clock {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: ClockSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            bpm_in: desc.with_buf_in_default::<f32>("bpm", settings.bpm),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            quarter_out: desc.with_buf_out::<f32>("quarter"),
+            eighth_out: desc.with_buf_out::<f32>("eighth"),
+            sixteenth_out: desc.with_buf_out::<f32>("sixteenth"),
+            division: settings.division.max(1),
+            state: Default::default(),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / self.sample_rate as f64;
+        let bpm_in = buffers_in.get(self.bpm_in);
+        let pulses_per_beat = [self.division as f64, 1.0, 2.0, 4.0];
+        let outs = buffers_out.get_many([
+            self.signal_out,
+            self.quarter_out,
+            self.eighth_out,
+            self.sixteenth_out,
+        ]);
+
+        for i in 0..bpm_in.len() {
+            for k in 0..outs.len() {
+                let pulse = (self.state.position * pulses_per_beat[k]).floor() as i64;
+                outs[k][i] = if pulse != self.state.last_pulses[k] {
+                    self.state.last_pulses[k] = pulse;
+                    1.0
+                } else {
+                    0.0
+                };
+            }
+            self.state.position += bpm_in[i].max(0.0) as f64 / 60.0 * sample_time;
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset_phase(&mut self) {
+        self.state = Default::default();
+    }
+
+    fn reset(&mut self) {
+        self.state = Default::default();
+    }
+}
+
+// threshold and makeup_gain are linear amplitudes, see Host::link_db; very high ratios limit
+pub struct Compressor {
+    signal_in: BufferHandle<In<f32>>,
+    threshold_in: BufferHandle<In<f32>>,
+    ratio_in: BufferHandle<In<f32>>,
+    attack_in: BufferHandle<In<f32>>,
+    release_in: BufferHandle<In<f32>>,
+    makeup_gain_in: BufferHandle<In<f32>>,
+    signal_out: BufferHandle<Out<f32>>,
+    level: f32,
+    oversampler: Oversampler,
+    sample_rate: f32,
+}
+
+#[derive(Clone)]
+pub struct CompressorSettings {
+    pub threshold: f32,
+    pub ratio: f32,
+    pub attack: f32,
+    pub release: f32,
+    pub makeup_gain: f32,
+    // with fast attack and release, the hard knee bends the waveform itself and aliases
+    pub oversampling: Oversampling,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 0.5,
+            ratio: 4.0,
+            attack: 0.01,
+            release: 0.1,
+            makeup_gain: 1.0,
+            oversampling: Oversampling::None,
+        }
+    }
+}
+
+impl Compressor {
+    pub fn latency(&self) -> usize {
+        self.oversampler.latency()
+    }
+}
+
+impl StateSnapshot for Compressor {
+    type State = (f32, Oversampler);
+
+    fn snapshot(&self) -> (f32, Oversampler) {
+        (self.level, self.oversampler.clone())
+    }
+
+    fn restore(&mut self, state: &(f32, Oversampler)) {
+        self.level = state.0;
+        self.oversampler.clone_from(&state.1);
+    }
+}
+
+impl ModuleSettings for Compressor {
+    type Settings = CompressorSettings;
+    type Error = Infallible;
+}
+
+impl Module for Compressor {
+    fn init(
+        mut desc: ModuleDescriptor,
+        settings: CompressorSettings,
+        _: usize,
+    ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
+        let module = Self {
+            signal_in: desc.with_buf_in::<f32>("in"),
+            threshold_in: desc.with_buf_in_default::<f32>("threshold", settings.threshold),
+            ratio_in: desc.with_buf_in_default::<f32>("ratio", settings.ratio),
+            attack_in: desc.with_buf_in_default::<f32>("attack", settings.attack),
+            release_in: desc.with_buf_in_default::<f32>("release", settings.release),
+            makeup_gain_in: desc.with_buf_in_default::<f32>("makeup_gain", settings.makeup_gain),
+            signal_out: desc.with_buf_out::<f32>("out"),
+            level: 0.0,
+            oversampler: Oversampler::new(settings.oversampling),
+            sample_rate: desc.sample_rate() as f32,
+        };
+        Ok(desc.build(module))
+    }
+
+    fn fill_buffers(&mut self, buffers_in: &ModuleBuffersIn, buffers_out: &mut ModuleBuffersOut) {
+        let sample_time = 1.0 / (self.sample_rate * self.oversampler.factor as f32);
+        let signal_in = buffers_in.get(self.signal_in);
+        let threshold_in = buffers_in.get(self.threshold_in);
+        let ratio_in = buffers_in.get(self.ratio_in);
+        let attack_in = buffers_in.get(self.attack_in);
+        let release_in = buffers_in.get(self.release_in);
+        let makeup_gain_in = buffers_in.get(self.makeup_gain_in);
+        let signal_out = buffers_out.get(self.signal_out);
+
+        let level = &mut self.level;
+        for i in 0..signal_out.len() {
+            let threshold = threshold_in[i].max(1e-6);
+            let ratio = ratio_in[i].max(1.0);
+            signal_out[i] = self.oversampler.process(signal_in[i], |x| {
+                *level = follow_envelope(*level, x, attack_in[i], release_in[i], sample_time);
+                // above the threshold, every `ratio` dB of input comes out as 1 dB
+                let gain = if *level > threshold {
+                    (*level / threshold).powf(1.0 / ratio - 1.0)
+                } else {
+                    1.0
+                };
+                x * gain
+            }) * makeup_gain_in[i];
+        }
+    }
+
+    fn snapshot_state(&self) -> Option<ModuleState> {
+        Some(ModuleState::new(self))
+    }
+
+    fn restore_state(&mut self, state: &ModuleState) {
+        state.restore(self);
+    }
+
+    fn reset(&mut self) {
+        self.level = 0.0;
+        self.oversampler.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use midly::num::{u4, u7};
+
+    use super::*;
+    use crate::host::{testing, Buffer};
+
+    const SAMPLE_RATE: u32 = 44100;
+    const LEN: usize = 64;
+
+    fn constant(value: f32) -> Vec<Buffer<f32>> {
+        vec![vec![value; LEN].into_boxed_slice()]
+    }
+
+    // one block per entry, each holding (sample, key, velocity) note-ons; velocity 0 is a note-off
+    fn notes(blocks: &[&[(usize, u8, u8)]]) -> Vec<Buffer<MidiEvents>> {
+        blocks
+            .iter()
+            .map(|events| {
+                let mut block = vec![MidiEvents::new(); LEN].into_boxed_slice();
+                for &(i, key, vel) in events.iter() {
+                    block[i].push(MidiEvent::Midi {
+                        channel: u4::from(0),
+                        message: midly::MidiMessage::NoteOn {
+                            key: u7::from(key),
+                            vel: u7::from(vel),
+                        },
+                    });
+                }
+                block
+            })
+            .collect()
+    }
+
+    fn run<T: Module + ModuleSettings>(
+        settings: T::Settings,
+        num_args: usize,
+        inputs: &[(&str, Vec<Buffer<f32>>)],
+        num_blocks: usize,
+    ) -> HashMap<String, Vec<f32>> {
+        run_midi::<T>(settings, num_args, inputs, &[], num_blocks)
+    }
+
+    fn run_midi<T: Module + ModuleSettings>(
+        settings: T::Settings,
+        num_args: usize,
+        inputs: &[(&str, Vec<Buffer<f32>>)],
+        midi_inputs: &[(&str, Vec<Buffer<MidiEvents>>)],
+        num_blocks: usize,
+    ) -> HashMap<String, Vec<f32>> {
+        testing::run_module::<T>(
+            settings,
+            num_args,
+            SAMPLE_RATE,
+            LEN,
+            inputs,
+            midi_inputs,
+            num_blocks,
+        )
+        .unwrap()
+        .signal
+        .into_iter()
+        .map(|(name, blocks)| {
+            (
+                name,
+                blocks.iter().flat_map(|b| b.iter().copied()).collect(),
+            )
+        })
+        .collect()
+    }
+
+    #[test]
+    fn panner_writes_both_outputs() {
+        let out = run::<Panner>(-1.0, 0, &[("in", constant(0.5))], 1);
+        assert!(out["out_left"].iter().all(|&x| (x - 0.5).abs() < 1e-6));
+        assert!(out["out_right"].iter().all(|&x| x.abs() < 1e-6));
+    }
+
+    #[test]
+    fn state_variable_filter_splits_dc() {
+        let settings = StateVariableFilterSettings {
+            cutoff: 1000.0,
+            q: 0.707,
+        };
+        let out = run::<StateVariableFilter>(settings, 0, &[("in", constant(1.0))], 40);
+        assert!((out["lp"].last().unwrap() - 1.0).abs() < 1e-3);
+        assert!(out["hp"].last().unwrap().abs() < 1e-3);
+        assert!(out["bp"].last().unwrap().abs() < 1e-3);
+    }
+
+    fn pulse_positions(signal: &[f32]) -> Vec<usize> {
+        signal
+            .iter()
+            .enumerate()
+            .filter(|(_, &x)| x > 0.5)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn clock_pulses_quarters_at_tempo() {
+        let num_blocks = 2 * SAMPLE_RATE as usize / LEN;
+        let out = run::<Clock>(Default::default(), 0, &[], num_blocks);
+        let quarters = pulse_positions(&out["quarter"]);
+        let spacing = (SAMPLE_RATE as f32 * 0.5) as usize;
+        assert_eq!(quarters, vec![0, spacing, 2 * spacing, 3 * spacing]);
+        assert_eq!(pulse_positions(&out["sixteenth"]).len(), 16);
+    }
+
+    fn peak(signal: &[f32]) -> f32 {
+        signal.iter().fold(0.0, |acc, &x| acc.max(x.abs()))
+    }
+
+    #[test]
+    fn envelope_scales_peak_by_velocity() {
+        let settings = EnvelopeSettings {
+            attack: 0.001,
+            velocity_amount: 1.0,
+            ..Default::default()
+        };
+        let soft = run_midi::<Envelope>(
+            settings.clone(),
+            0,
+            &[("in", constant(1.0))],
+            &[("in", notes(&[&[(0, 60, 64)]]))],
+            8,
+        );
+        let hard = run_midi::<Envelope>(
+            settings,
+            0,
+            &[("in", constant(1.0))],
+            &[("in", notes(&[&[(0, 60, 127)]]))],
+            8,
+        );
+        assert!((peak(&hard["out"]) - 1.0).abs() < 1e-3);
+        assert!((peak(&soft["out"]) - 64.0 / 127.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn envelope_gate_follows_held_notes() {
+        let out = run_midi::<Envelope>(
+            Default::default(),
+            0,
+            &[("in", constant(1.0))],
+            &[("in", notes(&[&[(10, 60, 100)], &[(20, 60, 0)]]))],
+            300,
+        );
+        let gate = &out["gate"];
+        assert_eq!(gate[9], 0.0);
+        assert_eq!(gate[10], 1.0);
+        assert_eq!(gate[LEN + 19], 1.0);
+        assert_eq!(gate[LEN + 20], 0.0);
+        // the zero-velocity note-on released the note instead of retriggering it
+        assert!(out["out"].last().unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    fn naive_square_keeps_hard_edges() {
+        let out = run::<Oscillator>(OscillatorSettings::Square.into(), 0, &[], 4);
+        assert!(out["out"].iter().all(|&x| x == 1.0 || x == -1.0));
+        assert!(out["out"].contains(&1.0));
+    }
+
+    #[test]
+    fn wavetables_interpolate_between_entries() {
+        let out = run::<Oscillator>(OscillatorSettings::Sine(4).into(), 0, &[], 4);
+        // a 4-entry sine holds 0, 1, 0, -1, so reading between the entries draws the straight
+        // lines of a triangle through them
+        let expected = |i: usize| {
+            let index = (i as f32 * 440.0 / SAMPLE_RATE as f32 * 4.0).rem_euclid(4.0);
+            match index {
+                x if x < 1.0 => x,
+                x if x < 3.0 => 2.0 - x,
+                x => x - 4.0,
+            }
+        };
+        assert!(out["out"].iter().any(|&x| x.abs() > 0.1 && x.abs() < 0.9));
+        for (i, &x) in out["out"].iter().enumerate() {
+            assert!(
+                (x - expected(i)).abs() < 1e-4,
+                "{}: {} vs {}",
+                i,
+                x,
+                expected(i)
+            );
+        }
+    }
+
+    #[test]
+    fn hard_sync_spreads_reset_jumps() {
+        let settings = SuperOscillatorSettings {
+            waveform: SuperWaveform::Saw,
+            sync_ratio: 1.5,
+            pulse_width: 0.5,
+        };
+        let out = run_midi::<SuperOscillator>(
+            settings,
+            0,
+            &[],
+            &[("in", notes(&[&[(0, 69, 100)]]))],
+            100,
+        );
+        // resets land mid-ramp, so each falls by about 1 and is spread over the samples around it
+        let period = SAMPLE_RATE as f32 / 440.0;
+        let signal = &out["out"];
+        let largest_reset_step = (1..60)
+            .flat_map(|k| {
+                let reset = (k as f32 * period) as usize;
+                reset - 2..reset + 2
+            })
+            .fold(0.0f32, |acc, i| acc.max(signal[i] - signal[i + 1]));
+        assert!(
+            (0.4..0.8).contains(&largest_reset_step),
+            "largest reset step {}",
+            largest_reset_step
+        );
+    }
+
+    #[test]
+    fn zero_length_envelope_stages_stay_finite() {
+        let settings = EnvelopeSettings {
+            attack: 0.0,
+            decay: 0.0,
+            sustain: 0.0,
+            release: 0.0,
+            ..Default::default()
+        };
+        let out = run_midi::<Envelope>(
+            settings,
+            0,
+            &[("in", constant(1.0))],
+            &[("in", notes(&[&[(0, 60, 100), (10, 60, 0), (20, 60, 100)]]))],
+            2,
+        );
+        assert!(out["out"].iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn weighted_sum_scales_each_input() {
+        let weights = Weights::new(vec![0.25, 0.75]);
+        let inputs = [("in[0]", constant(1.0)), ("in[1]", constant(1.0))];
+        let out = run::<Op>(OpType::WeightedSum(weights.clone()), 2, &inputs, 1);
+        assert!(out["out"].iter().all(|&x| (x - 1.0).abs() < 1e-6));
+
+        // missing weights default to 1.0
+        weights.set_all(vec![0.5]);
+        let out = run::<Op>(OpType::WeightedSum(weights), 2, &inputs, 1);
+        assert!(out["out"].iter().all(|&x| (x - 1.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn min_max_and_clamp_ops() {
+        let inputs = [("in[0]", constant(0.2)), ("in[1]", constant(0.8))];
+        let max = run::<Op>(OpType::Max, 2, &inputs, 1);
+        assert!(max["out"].iter().all(|&x| x == 0.8));
+        let min = run::<Op>(OpType::Min, 2, &inputs, 1);
+        assert!(min["out"].iter().all(|&x| x == 0.2));
+
+        let clamp = run::<Op>(
+            OpType::Clamp,
+            1,
+            &[
+                ("in[0]", constant(1.5)),
+                ("min", constant(0.0)),
+                ("max", constant(1.0)),
+            ],
+            1,
+        );
+        assert!(clamp["out"].iter().all(|&x| x == 1.0));
+    }
+
+    fn sine(freq: f32, amplitude: f32, num_blocks: usize) -> Vec<Buffer<f32>> {
+        (0..num_blocks)
+            .map(|block| {
+                (0..LEN)
+                    .map(|i| {
+                        let t = (block * LEN + i) as f32 / SAMPLE_RATE as f32;
+                        amplitude * (std::f32::consts::TAU * freq * t).sin()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn envelope_follower_attacks_fast_and_releases_slowly() {
+        let settings = EnvelopeFollowerSettings {
+            attack: 0.001,
+            release: 0.1,
+        };
+        // 0.1s of a loud sine, then silence
+        let burst_blocks = SAMPLE_RATE as usize / 10 / LEN;
+        let mut input = sine(440.0, 1.0, burst_blocks);
+        input.push(vec![0.0; LEN].into_boxed_slice());
+        let out = run::<EnvelopeFollower>(settings, 0, &[("in", input)], 4 * burst_blocks);
+        let out = &out["out"];
+
+        let burst_end = burst_blocks * LEN;
+        let ms = SAMPLE_RATE as usize / 1000;
+        assert!(out[10 * ms] > 0.8);
+        assert!(out[burst_end - 1] > 0.9);
+        // one release time later the level has fallen by about 1/e, and keeps falling
+        let released = out[burst_end + 100 * ms];
+        assert!(released > 0.25 && released < 0.45, "{}", released);
+        assert!(out.last().unwrap() < &0.1);
+    }
+
+    // magnitude of a single DFT bin, normalised so a full-scale sine at `freq` reads 0.5
+    fn magnitude(signal: &[f32], freq: f32) -> f32 {
+        let (re, im) = signal
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, &x)| {
+                let angle = std::f32::consts::TAU * freq * i as f32 / SAMPLE_RATE as f32;
+                (re + x * angle.cos(), im + x * angle.sin())
+            });
+        (re * re + im * im).sqrt() / signal.len() as f32
+    }
+
+    #[test]
+    fn ring_mod_makes_sidebands() {
+        let num_blocks = SAMPLE_RATE as usize / 10 / LEN + 1;
+        let out = run::<RingMod>(
+            Default::default(),
+            0,
+            &[("in", sine(440.0, 1.0, num_blocks))],
+            num_blocks,
+        );
+        // a tenth of a second puts every frequency here on a whole bin
+        let out = &out["out"][..SAMPLE_RATE as usize / 10];
+        assert!(magnitude(out, 340.0) > 0.2);
+        assert!(magnitude(out, 540.0) > 0.2);
+        assert!(magnitude(out, 440.0) < 0.01);
+    }
+
+    fn rms(signal: &[f32]) -> f32 {
+        (signal.iter().map(|x| x * x).sum::<f32>() / signal.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn reverb_tail_rings_on_after_an_impulse() {
+        let mut impulse = vec![0.0; LEN].into_boxed_slice();
+        impulse[0] = 1.0;
+        let settings = ReverbSettings {
+            mix: 1.0,
+            ..Default::default()
+        };
+        let num_blocks = SAMPLE_RATE as usize * 7 / 10 / LEN;
+        let out = run::<Reverb>(
+            settings,
+            0,
+            &[("in", vec![impulse, vec![0.0; LEN].into_boxed_slice()])],
+            num_blocks,
+        );
+        let out = &out["out"];
+        let ms = SAMPLE_RATE as usize / 1000;
+        let window = |start: usize| &out[start * ms..(start + 50) * ms];
+
+        // hundreds of milliseconds later the tail is still dense...
+        let late = window(300);
+        assert!(rms(late) > 1e-4);
+        assert!(late.iter().filter(|x| x.abs() > 1e-7).count() > late.len() * 9 / 10);
+        // ...and decaying
+        assert!(rms(window(100)) > rms(window(600)));
+    }
+
+    #[test]
+    fn chorus_detunes_less_as_depth_goes_to_zero() {
+        let num_blocks = SAMPLE_RATE as usize / 2 / LEN;
+        let input = sine(440.0, 1.0, num_blocks);
+        let dry: Vec<f32> = input.iter().flat_map(|b| b.iter().copied()).collect();
+        let run_chorus = |depth: f32| {
+            let settings = ChorusSettings {
+                rate_hz: 2.0,
+                depth,
+                mix: 0.5,
+            };
+            run::<Chorus>(settings, 0, &[("in", input.clone())], num_blocks)["out"].clone()
+        };
+
+        let wet = run_chorus(0.5);
+        let max_diff = dry
+            .iter()
+            .zip(wet.iter())
+            .fold(0.0f32, |acc, (a, b)| acc.max((a - b).abs()));
+        assert!(max_diff > 0.1);
+        let max_diff_from = |depth: f32| {
+            dry.iter()
+                .zip(run_chorus(depth).iter())
+                .fold(0.0f32, |acc, (a, b)| acc.max((a - b).abs()))
+        };
+        // no jump at zero: the difference from the dry signal shrinks smoothly with depth
+        assert!(max_diff_from(0.01) < max_diff_from(0.1));
+        // at most a 1.3 sample delay of a 440 Hz sine, half mixed in
+        assert!(max_diff_from(0.001) < 0.05);
+        assert!(max_diff_from(0.0) < 1e-6);
+    }
+
+    #[test]
+    fn dc_blocker_removes_offsets() {
+        let num_blocks = SAMPLE_RATE as usize / LEN;
+        let input = sine(100.0, 0.5, num_blocks)
+            .into_iter()
+            .map(|block| block.iter().map(|x| x + 0.5).collect())
+            .collect();
+        let out = run::<DcBlocker>((), 0, &[("in", input)], num_blocks);
+        // the last tenth of a second covers a whole number of cycles
+        let tail = &out["out"][out["out"].len() - SAMPLE_RATE as usize / 10..];
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+        assert!(mean.abs() < 0.01, "{}", mean);
+        assert!(peak(tail) > 0.4);
+    }
+
+    #[test]
+    fn slew_ramps_edges_at_their_own_rates() {
+        let settings = SlewSettings {
+            rise: SAMPLE_RATE as f32 / 100.0,
+            fall: SAMPLE_RATE as f32 / 50.0,
+        };
+        // high for four blocks, then low
+        let mut input = vec![vec![1.0; LEN].into_boxed_slice(); 4];
+        input.push(vec![0.0; LEN].into_boxed_slice());
+        let out = run::<Slew>(settings, 0, &[("in", input)], 8);
+        let out = &out["out"];
+
+        let ramping = |range: std::ops::Range<usize>| {
+            out[range].iter().filter(|&&x| x > 0.0 && x < 1.0).count()
+        };
+        let rise = ramping(0..4 * LEN);
+        let fall = ramping(4 * LEN..8 * LEN);
+        assert!((98..=100).contains(&rise), "{}", rise);
+        assert!((48..=50).contains(&fall), "{}", fall);
+    }
+
+    #[test]
+    fn sample_and_hold_latches_on_rising_edges() {
+        let ramp = (0..4)
+            .map(|block| (0..LEN).map(|i| (block * LEN + i) as f32).collect())
+            .collect();
+        let trigger = (0..LEN)
+            .map(|i| if i % 16 < 4 { 1.0 } else { 0.0 })
+            .collect();
+        let out = run::<SampleAndHold>(
+            Default::default(),
+            0,
+            &[("in", ramp), ("trigger", vec![trigger])],
+            4,
+        );
+        let expected: Vec<f32> = (0..4 * LEN).map(|i| (i / 16 * 16) as f32).collect();
+        assert_eq!(out["out"], expected);
+    }
+
+    #[test]
+    fn curved_attack_rises_faster_than_linear() {
+        let attack_midpoint = |curve: f32| {
+            let settings = EnvelopeSettings {
+                attack: 0.01,
+                curve,
+                ..Default::default()
+            };
+            let out = run_midi::<Envelope>(
+                settings,
+                0,
+                &[("in", constant(1.0))],
+                &[("in", notes(&[&[(0, 60, 100)]]))],
+                8,
+            );
+            out["out"][(SAMPLE_RATE as f32 * 0.005) as usize]
+        };
+        let linear = attack_midpoint(0.0);
+        let curved = attack_midpoint(5.0);
+        assert!((linear - 0.5).abs() < 0.01, "{}", linear);
+        assert!(curved > 0.9, "{}", curved);
+    }
+
+    #[test]
+    fn looping_envelope_repeats_while_held() {
+        // attack and decay add up to exactly one block
+        let settings = EnvelopeSettings {
+            attack: 20.0 / SAMPLE_RATE as f32,
+            decay: 44.0 / SAMPLE_RATE as f32,
+            sustain: 0.2,
+            mode: EnvelopeMode::LoopAD,
+            ..Default::default()
+        };
+        let out = run_midi::<Envelope>(
+            settings,
+            0,
+            &[("in", constant(1.0))],
+            &[("in", notes(&[&[(0, 60, 100)]]))],
+            8,
+        );
+        let out = &out["out"];
+        for i in LEN..7 * LEN {
+            assert!((out[i] - out[i + LEN]).abs() < 0.02, "{}", i);
+        }
+        let last_cycle = &out[7 * LEN..];
+        assert!(peak(last_cycle) > 0.9);
+        assert!(last_cycle.iter().any(|&x| x < 0.3));
+    }
+
+    #[test]
+    fn retrigger_from_current_level_is_click_free() {
+        let settings = EnvelopeSettings {
+            retrigger_mode: RetriggerMode::FromCurrent,
+            ..Default::default()
+        };
+        let out = run_midi::<Envelope>(
+            settings,
+            0,
+            &[("in", constant(1.0))],
+            // the second note-on lands partway through the release
+            &[(
+                "in",
+                notes(&[&[(0, 60, 100)], &[], &[(0, 60, 0)], &[], &[(0, 60, 100)]]),
+            )],
+            8,
+        );
+        let out = &out["out"];
+        assert!(out[4 * LEN - 1] > 0.1);
+        let largest_step = out
+            .windows(2)
+            .fold(0.0f32, |acc, pair| acc.max((pair[1] - pair[0]).abs()));
+        assert!(largest_step < 0.01, "{}", largest_step);
+    }
+
+    #[test]
+    fn compressor_reduces_loud_input_then_recovers() {
+        let loud_blocks = SAMPLE_RATE as usize / 5 / LEN;
+        let quiet_blocks = SAMPLE_RATE as usize / 2 / LEN;
+        let mut input = sine(1000.0, 1.0, loud_blocks);
+        input.extend(sine(1000.0, 0.25, quiet_blocks));
+        let out = run::<Compressor>(
+            Default::default(),
+            0,
+            &[("in", input)],
+            loud_blocks + quiet_blocks,
+        );
+        let out = &out["out"];
+
+        // a 4:1 ratio squeezes the 6dB over the 0.5 threshold down to about 1.5dB
+        let loud_tail = &out[(loud_blocks - 10) * LEN..loud_blocks * LEN];
+        assert!(
+            peak(loud_tail) > 0.5 && peak(loud_tail) < 0.7,
+            "{}",
+            peak(loud_tail)
+        );
+        // well after the release time, the quiet signal passes through untouched
+        let quiet_tail = &out[out.len() - 10 * LEN..];
+        assert!(
+            (peak(quiet_tail) - 0.25).abs() < 0.01,
+            "{}",
+            peak(quiet_tail)
+        );
+    }
+
+    #[test]
+    fn default_tables_produce_clean_output() {
+        let largest_step = |signal: &[f32]| {
+            signal
+                .windows(2)
+                .fold(0.0f32, |acc, pair| acc.max((pair[1] - pair[0]).abs()))
+        };
+        // a 440Hz sine moves by at most 2pi * 440 / SAMPLE_RATE per sample
+        let sine = run::<Oscillator>(OscillatorSettings::sine_default().into(), 0, &[], 8);
+        assert!(peak(&sine["out"]) <= 1.0);
+        assert!(largest_step(&sine["out"]) < 0.065);
+
+        // the default square is band-limited, so it passes through values between the rails
+        let square = run::<Oscillator>(OscillatorSettings::square_default().into(), 0, &[], 8);
+        assert!(peak(&square["out"]) <= 1.0);
+        assert!(square["out"].iter().any(|x| x.abs() < 0.5));
+    }
+
+    #[test]
+    fn gate_trigger_follows_square_wave_edges() {
+        let settings = EnvelopeSettings {
+            attack: 0.0001,
+            release: 0.0001,
+            trigger: EnvelopeTrigger::Gate,
+            ..Default::default()
+        };
+        let gate = (0..LEN)
+            .map(|i| if i < LEN / 2 { 1.0 } else { 0.0 })
+            .collect();
+        let out = run::<Envelope>(
+            settings,
+            0,
+            &[("in", constant(1.0)), ("gate", vec![gate])],
+            4,
+        );
+        for block in 0..4 {
+            let start = block * LEN;
+            assert_eq!(out["gate"][start], 1.0);
+            assert_eq!(out["gate"][start + LEN / 2], 0.0);
+            assert!(out["out"][start + 20] > 0.5);
+            assert_eq!(out["out"][start + LEN - 4], 0.0);
+        }
+    }
+
+    #[test]
+    fn dry_wet_mix_endpoints() {
+        let mix = |mix: f32| {
+            let settings = DryWetSettings { mix, dry_delay: 0 };
+            let inputs = [("dry", constant(1.0)), ("wet", constant(-1.0))];
+            run::<DryWet>(settings, 0, &inputs, 1)["out"].clone()
+        };
+        assert!(mix(0.0).iter().all(|&x| x == 1.0));
+        assert!(mix(1.0).iter().all(|&x| x == -1.0));
+        assert!(mix(0.5).iter().all(|&x| x == 0.0));
+    }
+
+    // sub-sample positions where the signal crosses zero going up
+    fn rising_crossings(signal: &[f32]) -> Vec<f32> {
+        signal
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+            .map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+            .collect()
+    }
+
+    #[test]
+    fn pitch_shift_glides_smoothly() {
+        // one octave up over a fifth of a second, changing every sample
+        let num_blocks = SAMPLE_RATE as usize / 5 / LEN;
+        let total = (num_blocks * LEN) as f32;
+        let glide = (0..num_blocks)
+            .map(|block| {
+                (0..LEN)
+                    .map(|i| 1.0 + (block * LEN + i) as f32 / total)
+                    .collect()
+            })
+            .collect();
+        let out = run::<Oscillator>(
+            OscillatorSettings::sine_default().into(),
+            0,
+            &[("pitch_shift", glide)],
+            num_blocks,
+        );
+        let periods: Vec<f32> = rising_crossings(&out["out"])
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect();
+        assert!(periods[0] > 90.0 && *periods.last().unwrap() < 55.0);
+        // every cycle is a little shorter than the last, with no flat steps in between
+        for pair in periods.windows(2) {
+            assert!(pair[1] < pair[0] - 0.1, "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn ping_pong_echoes_alternate_sides() {
+        let mut impulse = vec![0.0; LEN].into_boxed_slice();
+        impulse[0] = 1.0;
+        let settings = PingPongDelaySettings {
+            max_delay: 0.01,
+            delay_time: 10.0 / SAMPLE_RATE as f32,
+            feedback: 0.5,
+            mix: 1.0,
+        };
+        let out = run::<PingPongDelay>(
+            settings,
+            0,
+            &[("in", vec![impulse, vec![0.0; LEN].into_boxed_slice()])],
+            1,
+        );
+        let (left, right) = (&out["out_left"], &out["out_right"]);
+        let echoes: Vec<_> = (1..4).map(|k| (left[10 * k], right[10 * k])).collect();
+        let expected = [(1.0, 0.0), (0.0, 0.5), (0.25, 0.0)];
+        for ((l, r), (expected_l, expected_r)) in echoes.iter().zip(expected.iter()) {
+            assert!((l - expected_l).abs() < 1e-3 && (r - expected_r).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn note_off_velocity_scales_release() {
+        let settings = EnvelopeSettings {
+            release: 0.01,
+            release_velocity_sensitivity: 1.0,
+            ..Default::default()
+        };
+        let level_after_release = |vel: u8| {
+            let note_off = MidiEvent::Midi {
+                channel: u4::from(0),
+                message: midly::MidiMessage::NoteOff {
+                    key: u7::from(60),
+                    vel: u7::from(vel),
+                },
+            };
+            let mut midi = notes(&[&[(0, 60, 100)], &[], &[], &[], &[]]);
+            midi[4][0].push(note_off);
+            let out = run_midi::<Envelope>(
+                settings.clone(),
+                0,
+                &[("in", constant(1.0))],
+                &[("in", midi)],
+                10,
+            );
+            // 300 samples is past the shortened release but inside the lengthened one
+            out["out"][4 * LEN + 300]
+        };
+        assert_eq!(level_after_release(127), 0.0);
+        assert!(level_after_release(0) > 0.05);
+    }
+
+    #[test]
+    fn all_pass_keeps_magnitude_and_shifts_phase() {
+        let settings = AllPassSettings {
+            max_delay: 0.01,
+            // a whole number of samples, so the delay line doesn't interpolate
+            delay_time: 44.0 / SAMPLE_RATE as f32,
+            gain: 0.5,
+        };
+        let num_blocks = SAMPLE_RATE as usize / 5 / LEN;
+        let tail = SAMPLE_RATE as usize / 10;
+        let phase_cosines: Vec<f32> = [200.0, 1000.0, 5000.0]
+            .iter()
+            .map(|&freq| {
+                let input = sine(freq, 1.0, num_blocks);
+                let dry: Vec<f32> = input.iter().flat_map(|b| b.iter().copied()).collect();
+                let out = run::<AllPass>(settings.clone(), 0, &[("in", input)], num_blocks);
+                let (dry, wet) = (&dry[dry.len() - tail..], &out["out"][dry.len() - tail..]);
+
+                assert!((rms(wet) / rms(dry) - 1.0).abs() < 0.02, "{}", freq);
+                // a unit sine correlates with a shifted copy of itself by cos(shift) / 2
+                2.0 * dry.iter().zip(wet.iter()).map(|(a, b)| a * b).sum::<f32>() / tail as f32
+            })
+            .collect();
+        let spread = phase_cosines.iter().fold(f32::MIN, |acc, &x| acc.max(x))
+            - phase_cosines.iter().fold(f32::MAX, |acc, &x| acc.min(x));
+        assert!(spread > 0.2, "{:?}", phase_cosines);
+    }
+
+    #[test]
+    fn leveler_converges_to_target_rms() {
+        let settings = LevelerSettings {
+            target_level: 0.25,
+            response_time: 0.05,
+            ..Default::default()
+        };
+        // the running mean square takes a few response times to settle after the drop
+        let loud_blocks = SAMPLE_RATE as usize * 3 / 10 / LEN;
+        let quiet_blocks = 2 * loud_blocks;
+        let mut input = sine(1000.0, 1.0, loud_blocks);
+        input.extend(sine(1000.0, 0.1, quiet_blocks));
+        let out = run::<Leveler>(settings, 0, &[("in", input)], loud_blocks + quiet_blocks);
+        let out = &out["out"];
+
+        let window = SAMPLE_RATE as usize / 20;
+        let loud_tail = &out[loud_blocks * LEN - window..loud_blocks * LEN];
+        let quiet_tail = &out[out.len() - window..];
+        assert!((rms(loud_tail) - 0.25).abs() < 0.025, "{}", rms(loud_tail));
+        assert!(
+            (rms(quiet_tail) - 0.25).abs() < 0.025,
+            "{}",
+            rms(quiet_tail)
+        );
+    }
+
+    #[test]
+    fn decay_falls_monotonically_to_sustain() {
+        let settings = EnvelopeSettings {
+            attack: 0.001,
+            decay: 0.01,
+            sustain: 0.3,
+            ..Default::default()
+        };
+        let out = run_midi::<Envelope>(
+            settings,
+            0,
+            &[("in", constant(1.0))],
+            &[("in", notes(&[&[(0, 60, 100)]]))],
+            16,
+        );
+        let out = &out["out"];
+        let decay_start = (0..out.len())
+            .max_by(|&a, &b| out[a].partial_cmp(&out[b]).unwrap())
+            .unwrap();
+        let decay = &out[decay_start..];
+
+        assert!((decay[0] - 1.0).abs() < 0.01);
+        assert_eq!(*decay.last().unwrap(), 0.3);
+        assert!(decay.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(decay.iter().all(|&x| (0.3..=1.0).contains(&x)));
+    }
+
+    #[test]
+    fn control_interpolator_ramps_between_blocks() {
+        let steps = (0..4)
+            .map(|k| vec![k as f32; LEN].into_boxed_slice())
+            .collect();
+        let out = run::<ControlInterpolator>((), 0, &[("in", steps)], 4);
+        let out = &out["out"];
+        assert!(out[..LEN].iter().all(|&x| x == 0.0));
+        for k in 1..4 {
+            let block = &out[k * LEN..(k + 1) * LEN];
+            assert!((block[0] - (k as f32 - 1.0 + 1.0 / LEN as f32)).abs() < 1e-5);
+            assert!(block
+                .windows(2)
+                .all(|pair| (pair[1] - pair[0] - 1.0 / LEN as f32).abs() < 1e-5));
+            assert!((block[LEN - 1] - k as f32).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn random_lfo_steps_at_its_rate_and_smooths() {
+        let largest_step = |signal: &[f32]| {
+            signal
+                .windows(2)
+                .fold(0.0f32, |acc, pair| acc.max((pair[1] - pair[0]).abs()))
+        };
+        let settings = RandomLfoSettings {
+            rate: 100.0,
+            ..Default::default()
+        };
+        // half a second holds 50 values after the first
+        let num_blocks = SAMPLE_RATE as usize / 2 / LEN + 1;
+        let stepped = run::<RandomLfo>(settings.clone(), 0, &[], num_blocks);
+        let stepped = &stepped["out"];
+        let changes = stepped.windows(2).filter(|pair| pair[0] != pair[1]).count();
+        assert!((49..=50).contains(&changes), "{}", changes);
+
+        let smoothed = run::<RandomLfo>(
+            RandomLfoSettings {
+                smoothing: 0.002,
+                ..settings
+            },
+            0,
+            &[],
+            num_blocks,
+        );
+        assert!(largest_step(&smoothed["out"]) < largest_step(stepped) / 5.0);
+    }
+
+    #[test]
+    fn ducker_pumps_with_key_pulses() {
+        let settings = DuckerSettings {
+            attack: 0.0005,
+            release: 0.002,
+            ..Default::default()
+        };
+        // the key is high for the first two blocks of every eight
+        let key = (0..16)
+            .map(|block| vec![if block % 8 < 2 { 1.0 } else { 0.0 }; LEN].into_boxed_slice())
+            .collect();
+        let out = run::<Ducker>(settings, 0, &[("in", constant(1.0)), ("key", key)], 16);
+        let out = &out["out"];
+        for cycle in 0..2 {
+            let start = cycle * 8 * LEN;
+            let ducked = out[start + 2 * LEN - 1];
+            let recovered = out[start + 8 * LEN - 1];
+            assert!((ducked - 0.25).abs() < 0.02, "{}", ducked);
+            assert!(recovered > 0.98, "{}", recovered);
+        }
+    }
+
+    #[test]
+    fn oversampling_reduces_aliasing() {
+        // the 7th harmonic of a clipped 5kHz sine folds back down to 9.1kHz
+        let alias = |oversampling: Oversampling| {
+            let settings = ShaperSettings {
+                kind: ShaperKind::HardClip,
+                oversampling,
+            };
+            let num_blocks = SAMPLE_RATE as usize / 10 / LEN + 2;
+            let out = run::<Waveshaper>(
+                settings,
+                0,
+                &[
+                    ("in", sine(5000.0, 1.0, num_blocks)),
+                    ("drive", constant(10.0)),
+                ],
+                num_blocks,
+            );
+            magnitude(&out["out"][LEN..LEN + SAMPLE_RATE as usize / 10], 9100.0)
+        };
+        let plain = alias(Oversampling::None);
+        let oversampled = alias(Oversampling::X4);
+        assert!(plain > 0.01);
+        assert!(oversampled < plain / 2.0, "{} {}", oversampled, plain);
+    }
+
+    #[test]
+    fn divide_and_reciprocal_ops() {
+        let inputs = [
+            ("in[0]", constant(6.0)),
+            ("in[1]", constant(2.0)),
+            ("in[2]", constant(3.0)),
+        ];
+        let out = run::<Op>(OpType::Divide, 3, &inputs, 1);
+        assert!(out["out"].iter().all(|&x| x == 1.0));
+
+        let inputs = [("in[0]", constant(6.0)), ("in[1]", constant(0.0))];
+        let out = run::<Op>(OpType::Divide, 2, &inputs, 1);
+        assert!(out["out"].iter().all(|&x| x == 0.0));
+
+        let out = run::<Op>(OpType::Reciprocal, 1, &[("in[0]", constant(4.0))], 1);
+        assert!(out["out"].iter().all(|&x| x == 0.25));
+    }
+
+    #[test]
+    fn ladder_self_oscillates_at_cutoff() {
+        let settings = LadderFilterSettings {
+            cutoff: 1000.0,
+            resonance: 4.5,
+        };
+        let num_blocks = SAMPLE_RATE as usize / LEN;
+        let out = run::<LadderFilter>(settings, 0, &[("in", constant(0.0))], num_blocks);
+        let tail = &out["out"][out["out"].len() - SAMPLE_RATE as usize / 10..];
+        assert!(rms(tail) > 0.01, "{}", rms(tail));
+
+        let crossings = rising_crossings(tail);
+        let period = (crossings.last().unwrap() - crossings[0]) / (crossings.len() - 1) as f32;
+        let freq = SAMPLE_RATE as f32 / period;
+        assert!((freq - 1000.0).abs() < 200.0, "{}", freq);
+    }
+
+    #[test]
+    fn delay_repeats_an_impulse() {
+        let mut impulse = vec![0.0; LEN].into_boxed_slice();
+        impulse[0] = 1.0;
+        let settings = DelaySettings {
+            max_delay_secs: 0.01,
+        };
+        let out = run::<Delay>(
+            settings,
+            0,
+            &[
+                ("in", vec![impulse, vec![0.0; LEN].into_boxed_slice()]),
+                ("delay_time", constant(100.0 / SAMPLE_RATE as f32)),
+                ("mix", constant(1.0)),
+            ],
+            4,
+        );
+        let out = &out["out"];
+        assert!((out[100] - 1.0).abs() < 1e-3);
+        for (i, x) in out.iter().enumerate() {
+            if !(99..=101).contains(&i) {
+                assert!(x.abs() < 1e-6, "{}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn pink_noise_is_darker_than_white() {
+        let num_blocks = SAMPLE_RATE as usize / LEN;
+        let noise = |kind: NoiseKind| {
+            let settings = NoiseSettings { kind, seed: 1 };
+            run::<Noise>(settings, 0, &[], num_blocks)["out"].clone()
+        };
+        // high frequencies dominate the sample-to-sample differences
+        let brightness = |signal: &[f32]| {
+            let diffs: Vec<f32> = signal.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            rms(&diffs) / rms(signal)
+        };
+
+        let white = noise(NoiseKind::White);
+        let mean = white.iter().sum::<f32>() / white.len() as f32;
+        assert!(mean.abs() < 0.02, "{}", mean);
+        let pink = noise(NoiseKind::Pink);
+        assert!(brightness(&pink) < brightness(&white) / 2.0);
+    }
+
+    #[test]
+    fn one_hertz_lfo_cycles_once_per_second() {
+        let settings = LfoSettings {
+            waveform: LfoWaveform::Sine,
+            rate_hz: 1.0,
+            bipolar: true,
+        };
+        let num_blocks = SAMPLE_RATE as usize / LEN + 1;
+        let out = run::<Lfo>(settings, 0, &[], num_blocks);
+        let out = &out["out"];
+        let quarter = SAMPLE_RATE as usize / 4;
+        let expected = [0.0, 1.0, 0.0, -1.0, 0.0];
+        // the phase is accumulated in f32, so allow it to drift by about a degree
+        for (k, expected) in expected.iter().enumerate() {
+            assert!((out[k * quarter] - expected).abs() < 0.02, "{}", k);
+        }
+    }
+
+    #[test]
+    fn sampler_plays_source_at_root_pitch() {
+        let source: Vec<f32> = (0..100).map(|i| i as f32 / 100.0).collect();
+        let path = std::env::temp_dir().join("rustsynth_sampler_plays_source.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for &sample in source.iter() {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let settings = SamplerSettings {
+            path: path.clone(),
+            root_note: 60,
+            loop_mode: LoopMode::OneShot,
+        };
+        let out = run_midi::<Sampler>(settings, 0, &[], &[("in", notes(&[&[(0, 60, 127)]]))], 2);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(&out["out"][..100], &source[..]);
+        assert!(out["out"][100..].iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn plucked_string_decays() {
+        let settings = PluckedStringSettings { damping: 1.0 };
+        let num_blocks = SAMPLE_RATE as usize / 2 / LEN;
+        let out = run_midi::<PluckedString>(
+            settings,
+            0,
+            &[],
+            &[("in", notes(&[&[(0, 69, 127)]]))],
+            num_blocks,
+        );
+        let levels: Vec<f32> = out["out"]
+            .chunks(SAMPLE_RATE as usize / 20)
+            .map(rms)
+            .collect();
+        assert!(levels[0] > 0.1);
+        assert!(
+            levels.windows(2).all(|pair| pair[1] < pair[0]),
+            "{:?}",
+            levels
+        );
+    }
+
+    #[test]
+    fn tanh_shaper_bounds_and_adds_odd_harmonics() {
+        let settings = ShaperSettings {
+            kind: ShaperKind::Tanh,
+            oversampling: Oversampling::None,
+        };
+        let num_blocks = SAMPLE_RATE as usize / 10 / LEN + 1;
+        let out = run::<Waveshaper>(
+            settings,
+            0,
+            &[("in", sine(1000.0, 2.0, num_blocks))],
+            num_blocks,
+        );
+        let out = &out["out"][..SAMPLE_RATE as usize / 10];
+        assert!(peak(out) <= 1.0);
+        // a symmetric curve only adds odd harmonics
+        assert!(magnitude(out, 3000.0) > 0.01);
+        assert!(magnitude(out, 2000.0) < 1e-3);
+    }
+
+    #[test]
+    fn two_bit_crush_leaves_four_levels() {
+        let settings = BitcrusherSettings {
+            bits: 2.0,
+            downsample: 1.0,
+        };
+        let ramp = (0..4)
+            .map(|block| {
+                (0..LEN)
+                    .map(|i| (block * LEN + i) as f32 / (2 * LEN) as f32 - 1.0)
+                    .collect()
+            })
+            .collect();
+        let out = run::<Bitcrusher>(settings, 0, &[("in", ramp)], 4);
+
+        let levels = [-1.0, -1.0 / 3.0, 1.0 / 3.0, 1.0];
+        let level_of = |x: f32| levels.iter().position(|level| (x - level).abs() < 1e-6);
+        assert!(out["out"].iter().all(|&x| level_of(x).is_some()));
+        for level in 0..4 {
+            assert!(out["out"].iter().any(|&x| level_of(x) == Some(level)));
+        }
+    }
+
+    #[test]
+    fn lane_ops_cover_partial_lanes() {
+        // an odd block length leaves a partial lane at the end of every buffer with `simd` on
+        let len = 61;
+        let inputs: Vec<(String, Vec<Buffer<f32>>)> = (0..16)
+            .map(|i| {
+                let block = (0..len).map(|j| (i + j) as f32 * 0.01).collect();
+                (format!("in[{}]", i), vec![block])
+            })
+            .collect();
+        let inputs: Vec<(&str, Vec<Buffer<f32>>)> = inputs
+            .iter()
+            .map(|(name, bufs)| (name.as_str(), bufs.clone()))
+            .collect();
+        let run_op = |op: OpType| {
+            testing::run_module::<Op>(op, 16, SAMPLE_RATE, len, &inputs, &[], 1)
+                .unwrap()
+                .signal["out"][0]
+                .clone()
+        };
+
+        let sum = run_op(OpType::Add);
+        let negated = run_op(OpType::Negate);
+        let product = run_op(OpType::Multiply);
+        for j in 0..len {
+            let values = (0..16).map(|i| (i + j) as f32 * 0.01);
+            let expected_sum: f32 = values.clone().sum();
+            let expected_product: f32 = values.product();
+            assert!((sum[j] - expected_sum).abs() < 1e-4);
+            assert!((negated[j] + expected_sum).abs() < 1e-4);
+            assert!((product[j] - expected_product).abs() <= expected_product.abs() * 1e-5);
+        }
+    }
+
+    #[test]
+    fn freq_mod_makes_fm_sidebands() {
+        // a 100Hz modulator swinging the 440Hz carrier by 100Hz is FM with an index of 1
+        let num_blocks = SAMPLE_RATE as usize / 10 / LEN + 1;
+        let out = run::<Oscillator>(
+            OscillatorSettings::sine_default().into(),
+            0,
+            &[("freq_mod", sine(100.0, 100.0, num_blocks))],
+            num_blocks,
+        );
+        let out = &out["out"][..SAMPLE_RATE as usize / 10];
+        // Bessel function weights J0(1), J1(1) and J2(1), halved by `magnitude`
+        let bands = [
+            (440.0, 0.383),
+            (340.0, 0.220),
+            (540.0, 0.220),
+            (240.0, 0.057),
+        ];
+        for &(freq, expected) in bands.iter() {
+            let level = magnitude(out, freq);
+            assert!((level - expected).abs() < 0.02, "{} {}", freq, level);
+        }
+    }
+
+    #[test]
+    fn quarter_pulse_is_high_a_quarter_of_the_time() {
+        // a whole number of 441Hz periods
+        let num_blocks = SAMPLE_RATE as usize / 10 / LEN + 1;
+        let out = run::<Oscillator>(
+            OscillatorSettings::Pulse(0.25).into(),
+            0,
+            &[("pitch_shift", constant(441.0 / 440.0))],
+            num_blocks,
+        );
+        let out = &out["out"][..SAMPLE_RATE as usize / 10];
+        let high = out.iter().filter(|&&x| x > 0.0).count() as f32 / out.len() as f32;
+        assert!((high - 0.25).abs() < 0.01, "{}", high);
+        // the offset keeps the average at zero
+        let mean = out.iter().sum::<f32>() / out.len() as f32;
+        assert!(mean.abs() < 0.02, "{}", mean);
+    }
+
+    #[test]
+    fn unison_voices_spread_the_spectrum() {
+        // the outermost voices land on 430Hz and 450Hz
+        let detune_cents = 1200.0 * (450.0f32 / 440.0).log2();
+        let render = |voices: usize| {
+            let config = OscillatorConfig {
+                waveform: OscillatorSettings::sine_default(),
+                unison: UnisonSettings {
+                    voices,
+                    detune_cents,
+                    spread: 0.0,
+                },
+            };
+            let num_blocks = SAMPLE_RATE as usize / LEN + 1;
+            let out = run::<Oscillator>(config, 0, &[], num_blocks);
+            out["out"][..SAMPLE_RATE as usize].to_vec()
+        };
+
+        let single = render(1);
+        assert!(magnitude(&single, 450.0) < 0.01);
+        let unison = render(7);
+        assert!(magnitude(&unison, 450.0) > 0.05);
+        assert!(magnitude(&unison, 440.0) > 0.05);
+        assert!(magnitude(&unison, 440.0) < 0.2);
+    }
+
+    #[test]
+    fn state_variable_notch_removes_the_cutoff() {
+        let settings = StateVariableFilterSettings {
+            cutoff: 1000.0,
+            q: 0.707,
+        };
+        let num_blocks = SAMPLE_RATE as usize / 5 / LEN;
+        let tail = SAMPLE_RATE as usize / 10;
+        let level = |freq: f32| {
+            let out = run::<StateVariableFilter>(
+                settings.clone(),
+                0,
+                &[("in", sine(freq, 1.0, num_blocks))],
+                num_blocks,
+            );
+            rms(&out["notch"][out["notch"].len() - tail..])
+        };
+        assert!(level(1000.0) < 0.05);
+        assert!(level(100.0) > 0.6);
+        assert!(level(10000.0) > 0.6);
+    }
+
+    #[test]
+    fn centred_pan_keeps_equal_power() {
+        let out = run::<Panner>(0.0, 0, &[("in", constant(1.0))], 1);
+        let (left, right) = (out["out_left"][0], out["out_right"][0]);
+        assert!((left - right).abs() < 1e-6);
+        assert!((left * left + right * right - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn weighted_sum_keeps_its_snapshot_while_weights_are_locked() {
+        let mut host = crate::host::Host::new(SAMPLE_RATE, LEN).unwrap();
+        let weights = Weights::new(vec![0.25]);
+        let sum = host
+            .create_variadic_module::<Op>("sum", OpType::WeightedSum(weights.clone()), 1)
+            .unwrap();
+        let sum_in = host.variadic_buf::<In<f32>>(sum, "in").unwrap();
+        host.link_value(1.0, sum_in.at(0).unwrap()).unwrap();
+        let peek = |host: &crate::host::Host| {
+            host.peek_output::<f32>(host.buf(sum, "out").unwrap())
+                .unwrap()[0]
+        };
+
+        host.step(1);
+        assert_eq!(peek(&host), 0.25);
+        // an edit in progress doesn't block the block, which keeps the last weights
+        {
+            let _editing = weights.0.lock().unwrap();
+            host.step(1);
+        }
+        assert_eq!(peek(&host), 0.25);
+        weights.set(0, 0.5);
+        host.step(1);
+        assert_eq!(peek(&host), 0.5);
+    }
+
+    #[test]
+    fn super_oscillator_pulse_width_sets_the_duty_cycle() {
+        let num_blocks = SAMPLE_RATE as usize / 10 / LEN;
+        for &width in [0.25, 0.5, 0.75].iter() {
+            let settings = SuperOscillatorSettings {
+                waveform: SuperWaveform::Pulse,
+                sync_ratio: 1.0,
+                pulse_width: width,
+            };
+            let out = run::<SuperOscillator>(settings, 0, &[], num_blocks);
+            let signal = &out["out"];
+            let high = signal.iter().filter(|&&x| x > 0.0).count() as f32 / signal.len() as f32;
+            assert!((high - width).abs() < 0.01, "{}: {}", width, high);
+            let mean = signal.iter().sum::<f32>() / signal.len() as f32;
+            assert!(
+                (mean - (2.0 * width - 1.0)).abs() < 0.02,
+                "{}: {}",
+                width,
+                mean
+            );
+        }
+    }
+
+    #[test]
+    fn super_oscillator_saw_keeps_aliases_down_at_high_pitch() {
+        // 7040 Hz: the fourth and fifth harmonics fold back to 15940 and 8900 Hz
+        let settings = SuperOscillatorSettings {
+            waveform: SuperWaveform::Saw,
+            sync_ratio: 1.0,
+            pulse_width: 0.5,
+        };
+        let num_blocks = SAMPLE_RATE as usize / 10 / LEN;
+        let out = run_midi::<SuperOscillator>(
+            settings,
+            0,
+            &[],
+            &[("in", notes(&[&[(0, 117, 100)]]))],
+            num_blocks,
+        );
+        let signal = &out["out"];
+        let fundamental = magnitude(signal, 7040.0);
+        // a naive saw's harmonics fall off as 1/n, so these would read a quarter and a fifth
+        for &alias in [15940.0, 8900.0].iter() {
+            let ratio = magnitude(signal, alias) / fundamental;
+            assert!(ratio < 0.1, "{} Hz: {}", alias, ratio);
+        }
+    }
+
+    #[test]
+    fn hard_sync_locks_the_slave_to_the_master_period() {
+        let settings = SuperOscillatorSettings {
+            waveform: SuperWaveform::Saw,
+            sync_ratio: 1.5,
+            pulse_width: 0.5,
+        };
+        // 441 Hz repeats every 100 samples, while the free slave alone would repeat every 66.7
+        let num_blocks = 20;
+        let out = run::<SuperOscillator>(settings, 0, &[("freq_mod", constant(1.0))], num_blocks);
+        let signal = &out["out"];
+        let max_diff = |period: usize| {
+            (200..signal.len() - period)
+                .map(|i| (signal[i] - signal[i + period]).abs())
+                .fold(0.0f32, f32::max)
+        };
+        assert!(max_diff(100) < 0.01, "{}", max_diff(100));
+        assert!(max_diff(67) > 0.5);
+    }
+
+    #[test]
+    fn state_variable_filter_stays_bounded_under_a_cutoff_sweep() {
+        // a resonant sweep up to the clamp and back down, twice a second, over noise
+        let num_blocks = SAMPLE_RATE as usize / LEN;
+        let mut seed = 1u32;
+        let mut noise = || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        let input: Vec<Buffer<f32>> = (0..num_blocks)
+            .map(|_| (0..LEN).map(|_| noise()).collect())
+            .collect();
+        let cutoff: Vec<Buffer<f32>> = (0..num_blocks)
+            .map(|block| {
+                (0..LEN)
+                    .map(|i| {
+                        let t = ((block * LEN + i) as f32 / SAMPLE_RATE as f32 * 2.0).fract();
+                        20.0 + 20000.0 * (1.0 - (2.0 * t - 1.0).abs())
+                    })
+                    .collect()
+            })
+            .collect();
+        for &q in [0.5, 4.0, 20.0].iter() {
+            let settings = StateVariableFilterSettings { cutoff: 1000.0, q };
+            let out = run::<StateVariableFilter>(
+                settings,
+                0,
+                &[("in", input.clone()), ("cutoff", cutoff.clone())],
+                num_blocks,
+            );
+            for name in ["lp", "hp", "bp", "notch"].iter() {
+                assert!(out[*name].iter().all(|x| x.is_finite()));
+                let peak = peak(&out[*name]);
+                assert!(peak < 2.0 * q.max(1.0), "{} q={}: {}", name, q, peak);
+            }
+        }
+    }
+
+    #[test]
+    fn phase_mod_is_in_cycles_for_every_table() {
+        // a quarter cycle turns the sine into a cosine, whatever the table size
+        for &table_len in [64, 1024].iter() {
+            let out = run::<Oscillator>(
+                OscillatorSettings::Sine(table_len).into(),
+                0,
+                &[("phase_mod", constant(0.25))],
+                4,
+            );
+            for (i, &x) in out["out"].iter().enumerate() {
+                let expected =
+                    (std::f32::consts::TAU * 440.0 * i as f32 / SAMPLE_RATE as f32).cos();
+                assert!((x - expected).abs() < 0.01, "{} {}: {}", table_len, i, x);
+            }
+        }
+
+        // and half a cycle inverts a square pulse
+        let pulse = |phase_mod: f32| {
+            run::<Oscillator>(
+                OscillatorSettings::Pulse(0.5).into(),
+                0,
+                &[("phase_mod", constant(phase_mod))],
+                4,
+            )["out"]
+                .clone()
+        };
+        let inverted = pulse(0.5);
+        let differing = pulse(0.0)
+            .iter()
+            .zip(inverted.iter())
+            .filter(|(a, b)| (*a + *b).abs() > 1e-6)
+            .count();
+        // only the samples landing exactly on an edge may disagree
+        assert!(differing <= 4, "{}", differing);
+    }
+
+    #[test]
+    fn pulse_phase_mod_moves_edges_by_a_fraction_of_the_period() {
+        let rising_edges = |phase_mod: f32| {
+            let out = run::<Oscillator>(
+                OscillatorSettings::Pulse(0.25).into(),
+                0,
+                &[("phase_mod", constant(phase_mod))],
+                8,
+            );
+            out["out"]
+                .windows(2)
+                .enumerate()
+                .filter(|(_, w)| w[1] > w[0])
+                .map(|(i, _)| i as f32)
+                .collect::<Vec<_>>()
+        };
+        let period = SAMPLE_RATE as f32 / 440.0;
+        let plain = rising_edges(0.0);
+        let shifted = rising_edges(0.25);
+        // a quarter cycle ahead reaches each edge a quarter period sooner
+        assert_eq!(plain.len(), shifted.len());
+        for (a, b) in plain.iter().zip(shifted.iter()) {
+            assert!((a - b - period * 0.25).abs() <= 1.0, "{} {}", a, b);
+        }
+    }
+
+    #[test]
+    fn band_limited_square_drops_harmonics_above_nyquist() {
+        // at 3520Hz the 7th harmonic would sit at 24640Hz and fold back to 19460Hz
+        let num_blocks = SAMPLE_RATE as usize / 10 / LEN + 1;
+        let out = run::<Oscillator>(
+            OscillatorSettings::square_default().into(),
+            0,
+            &[("pitch_shift", constant(8.0))],
+            num_blocks,
+        );
+        let out = &out["out"][..SAMPLE_RATE as usize / 10];
+        let fundamental = magnitude(out, 3520.0);
+        assert!(fundamental > 0.3, "{}", fundamental);
+        // the harmonics that fit are kept
+        assert!(magnitude(out, 3.0 * 3520.0) > 0.05 * fundamental);
+        let alias = magnitude(out, 19460.0);
+        assert!(alias < 0.01 * fundamental, "{}", alias / fundamental);
+    }
+
+    #[test]
+    fn oversampling_reduces_compressor_aliasing() {
+        // with instant attack and release the hard knee clips a 5kHz sine, folding its 7th
+        // harmonic back down to 9.1kHz
+        let alias = |oversampling: Oversampling| {
+            let settings = CompressorSettings {
+                threshold: 0.1,
+                ratio: 20.0,
+                attack: 0.0,
+                release: 0.0,
+                oversampling,
+                ..Default::default()
+            };
+            let num_blocks = SAMPLE_RATE as usize / 10 / LEN + 2;
+            let out = run::<Compressor>(
+                settings,
+                0,
+                &[("in", sine(5000.0, 1.0, num_blocks))],
+                num_blocks,
+            );
+            magnitude(&out["out"][LEN..LEN + SAMPLE_RATE as usize / 10], 9100.0)
+        };
+        let plain = alias(Oversampling::None);
+        let oversampled = alias(Oversampling::X4);
+        assert!(plain > 0.005, "{}", plain);
+        assert!(oversampled < plain / 2.0, "{} {}", oversampled, plain);
+    }
 }
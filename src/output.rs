@@ -1,13 +1,16 @@
-use crate::{
-    constants::*,
-    host::{
-        Buffer, BufferHandle, BuiltModuleDescriptor, In, Module, ModuleDescriptor, ModuleSettings,
-    },
+use crate::host::{
+    BufferHandle, BuiltModuleDescriptor, In, Module, ModuleDescriptor, ModuleSettings,
 };
 
 use std::{
     convert::Infallible,
-    sync::{Arc, Condvar, Mutex},
+    fs::OpenOptions,
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
 use rodio::Source;
@@ -29,50 +32,135 @@ impl DoubleBufferName {
 
 struct AudioOutputState {
     index: usize,
+    channel: u16,
     now_reading: DoubleBufferName,
     can_write: bool,
     out_of_samples: bool,
+    started: bool,
+    capture: Option<Vec<f32>>,
+    // drop blocks instead of waiting for the device, for stepping without playback
+    discard: bool,
+    recorder: Option<Arc<Recorder>>,
+    wait_time: Duration,
 }
 
 struct AudioOutputInner {
+    channels: u16,
+    channel_range: Range<u16>,
     state: Mutex<AudioOutputState>,
     can_write_condvar: Condvar,
-    buffer_a: Mutex<[f32; BUFFER_LEN]>,
-    buffer_b: Mutex<[f32; BUFFER_LEN]>,
+    sample_rate: u32,
+    buffer_len: usize,
+    buffer_a: Mutex<Vec<Frame>>,
+    buffer_b: Mutex<Vec<Frame>>,
 }
 
+type Frame = [f32; 2];
+
 #[derive(Clone)]
 pub(crate) struct AudioOutput(Arc<AudioOutputInner>);
 
 impl AudioOutput {
-    pub fn new() -> Self {
+    pub fn new(sample_rate: u32, buffer_len: usize) -> Self {
+        Self::with_channels(0..2, sample_rate, buffer_len)
+    }
+
+    pub fn with_channels(channel_range: Range<u16>, sample_rate: u32, buffer_len: usize) -> Self {
         Self(Arc::new(AudioOutputInner {
+            // a range starting at channel 0 still opens two channels, since rodio copies a mono
+            // source to every device channel
+            channels: channel_range.end.max(2),
+            channel_range,
             state: Mutex::new(AudioOutputState {
                 index: 0,
+                channel: 0,
                 now_reading: DoubleBufferName::BufferB,
                 can_write: true,
                 out_of_samples: true,
+                started: false,
+                capture: None,
+                discard: false,
+                recorder: None,
+                wait_time: Duration::default(),
             }),
             can_write_condvar: Condvar::new(),
-            buffer_a: Mutex::new([0.0; BUFFER_LEN]),
-            buffer_b: Mutex::new([0.0; BUFFER_LEN]),
+            sample_rate,
+            buffer_len,
+            buffer_a: Mutex::new(vec![[0.0; 2]; buffer_len]),
+            buffer_b: Mutex::new(vec![[0.0; 2]; buffer_len]),
         }))
     }
 
-    pub fn write(&self, data: &Buffer<f32>) {
+    pub fn write(&self, left: &[f32], right: &[f32]) {
         let write_buffer_name = {
             let mut state = self.0.state.lock().unwrap();
+            if let Some(recorder) = state.recorder.as_ref() {
+                recorder.add(&self.0.channel_range, left, right);
+            }
+            if let Some(capture) = state.capture.as_mut() {
+                for (&l, &r) in left.iter().zip(right.iter()) {
+                    capture.push(l);
+                    capture.push(r);
+                }
+                return;
+            }
+            if state.discard {
+                return;
+            }
+            let wait_start = Instant::now();
             while !state.can_write {
                 state = self.0.can_write_condvar.wait(state).unwrap();
             }
+            state.wait_time += wait_start.elapsed();
             state.out_of_samples = false;
             state.can_write = false;
             state.now_reading.next()
         };
-        *self.get_buffer(write_buffer_name).try_lock().unwrap() = *data;
+        let mut buffer = self.get_buffer(write_buffer_name).try_lock().unwrap();
+        for ((frame, &l), &r) in buffer.iter_mut().zip(left.iter()).zip(right.iter()) {
+            *frame = [l, r];
+        }
+    }
+
+    pub fn begin_capture(&self, capacity: usize) {
+        self.0.state.lock().unwrap().capture = Some(Vec::with_capacity(capacity));
+    }
+
+    pub fn set_discard(&self, discard: bool) {
+        self.0.state.lock().unwrap().discard = discard;
+    }
+
+    pub fn end_capture(&self) -> Vec<f32> {
+        self.0
+            .state
+            .lock()
+            .unwrap()
+            .capture
+            .take()
+            .unwrap_or_default()
+    }
+
+    pub fn channel_range(&self) -> Range<u16> {
+        self.0.channel_range.clone()
+    }
+
+    pub fn set_recorder(&self, recorder: Option<Arc<Recorder>>) {
+        self.0.state.lock().unwrap().recorder = recorder;
     }
 
-    fn get_buffer(&self, name: DoubleBufferName) -> &Mutex<[f32; BUFFER_LEN]> {
+    pub fn take_wait_time(&self) -> Duration {
+        std::mem::take(&mut self.0.state.lock().unwrap().wait_time)
+    }
+
+    fn set_started(&self, started: bool) {
+        self.0.state.lock().unwrap().started = started;
+    }
+
+    fn is_started(&self) -> bool {
+        self.0.state.lock().unwrap().started
+    }
+
+    fn get_buffer(&self, name: DoubleBufferName) -> &Mutex<Vec<Frame>> {
         match name {
             DoubleBufferName::BufferA => &self.0.buffer_a,
             DoubleBufferName::BufferB => &self.0.buffer_b,
@@ -89,10 +177,22 @@ impl Iterator for AudioOutput {
             return Some(0.0);
         }
 
-        let out = self.get_buffer(state.now_reading).try_lock().unwrap()[state.index];
+        let out = if state.started && self.0.channel_range.contains(&state.channel) {
+            // channels past the second in a wide range repeat the right channel
+            let side = ((state.channel - self.0.channel_range.start) as usize).min(1);
+            self.get_buffer(state.now_reading).try_lock().unwrap()[state.index][side]
+        } else {
+            0.0
+        };
+
+        state.channel += 1;
+        if state.channel < self.0.channels {
+            return Some(out);
+        }
+        state.channel = 0;
 
         state.index += 1;
-        if state.index >= BUFFER_LEN {
+        if state.index >= self.0.buffer_len {
             state.index = 0;
             if state.can_write {
                 state.out_of_samples = true;
@@ -107,43 +207,333 @@ impl Iterator for AudioOutput {
     }
 }
 
+// Adds one stereo frame into the channels of an interleaved frame covered by `channel_range`,
+// laid out the same way the device sees it. Channels past the end of `frame` are dropped.
+pub(crate) fn place_frame(frame: &mut [f32], channel_range: &Range<u16>, left: f32, right: f32) {
+    for channel in channel_range.clone() {
+        if let Some(sample) = frame.get_mut(channel as usize) {
+            *sample += if channel == channel_range.start {
+                left
+            } else {
+                right
+            };
+        }
+    }
+}
+
+struct RecorderInner {
+    channels: usize,
+    // interleaved block with every output written into its own channels
+    mix: Vec<f32>,
+    blocks: mpsc::Sender<Vec<f32>>,
+    free_blocks: mpsc::Receiver<Vec<f32>>,
+}
+
+// Places the blocks written by every output into their channels and hands one interleaved block per host block to
+// a writer thread. Written blocks come back through `free_blocks` to be reused, so recording
+// doesn't allocate on the audio thread once the pool is warm.
+pub(crate) struct Recorder {
+    inner: Mutex<RecorderInner>,
+}
+
+const RECORDER_POOL_SIZE: usize = 8;
+
+impl Recorder {
+    pub fn start(
+        path: &Path,
+        channels: u16,
+        sample_rate: u32,
+        buffer_len: usize,
+    ) -> hound::Result<(Arc<Self>, JoinHandle<hound::Result<()>>)> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        let (blocks_tx, blocks_rx) = mpsc::channel::<Vec<f32>>();
+        let (free_tx, free_rx) = mpsc::channel::<Vec<f32>>();
+        for _ in 0..RECORDER_POOL_SIZE {
+            let _ = free_tx.send(Vec::with_capacity(buffer_len * channels as usize));
+        }
+        let recorder = Arc::new(Self {
+            inner: Mutex::new(RecorderInner {
+                channels: channels as usize,
+                mix: vec![0.0; buffer_len * channels as usize],
+                blocks: blocks_tx,
+                free_blocks: free_rx,
+            }),
+        });
+        let handle = std::thread::spawn(move || {
+            for block in blocks_rx {
+                for &sample in block.iter() {
+                    writer.write_sample(sample)?;
+                }
+                let _ = free_tx.send(block);
+            }
+            writer.finalize()
+        });
+        Ok((recorder, handle))
+    }
+
+    fn add(&self, channel_range: &Range<u16>, left: &[f32], right: &[f32]) {
+        let mut inner = self.inner.lock().unwrap();
+        let channels = inner.channels;
+        for (frame, (&l, &r)) in inner
+            .mix
+            .chunks_exact_mut(channels)
+            .zip(left.iter().zip(right.iter()))
+        {
+            place_frame(frame, channel_range, l, r);
+        }
+    }
+
+    // sends the mixed block to the writer and clears it for the next one
+    pub fn flush(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let mut block = inner
+            .free_blocks
+            .try_recv()
+            .unwrap_or_else(|_| Vec::with_capacity(inner.mix.len()));
+        block.clear();
+        block.extend_from_slice(&inner.mix);
+        for sample in inner.mix.iter_mut() {
+            *sample = 0.0;
+        }
+        // the writer thread only hangs up after an I/O error, which stop_recording reports
+        let _ = inner.blocks.send(block);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum WavBitDepth {
+    Int16,
+    Float32,
+}
+
+#[derive(Clone)]
+pub struct WavSettings {
+    pub bit_depth: WavBitDepth,
+    pub normalize: Option<f32>,
+    pub dither: bool,
+    pub loop_points: Option<(u32, u32)>,
+}
+
+impl Default for WavSettings {
+    fn default() -> Self {
+        Self {
+            bit_depth: WavBitDepth::Float32,
+            normalize: None,
+            dither: true,
+            loop_points: None,
+        }
+    }
+}
+
+pub fn normalize(samples: &mut [f32], target_db: f32) {
+    let peak = samples.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+    if peak > 0.0 {
+        let gain = 10.0f32.powf(target_db / 20.0) / peak;
+        for sample in samples.iter_mut() {
+            *sample *= gain;
+        }
+    }
+}
+
+pub fn write_wav(
+    path: &Path,
+    samples: &[f32],
+    channels: u16,
+    sample_rate: u32,
+    settings: &WavSettings,
+) -> hound::Result<()> {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: match settings.bit_depth {
+            WavBitDepth::Int16 => 16,
+            WavBitDepth::Float32 => 32,
+        },
+        sample_format: match settings.bit_depth {
+            WavBitDepth::Int16 => hound::SampleFormat::Int,
+            WavBitDepth::Float32 => hound::SampleFormat::Float,
+        },
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    let mut rng_state = 0x2545_f491u32;
+    let mut next_uniform = || {
+        rng_state ^= rng_state << 13;
+        rng_state ^= rng_state >> 17;
+        rng_state ^= rng_state << 5;
+        rng_state as f32 / u32::MAX as f32
+    };
+    for &sample in samples {
+        match settings.bit_depth {
+            WavBitDepth::Int16 => {
+                let dither = if settings.dither {
+                    next_uniform() + next_uniform() - 1.0
+                } else {
+                    0.0
+                };
+                let scaled = sample.clamp(-1.0, 1.0) * i16::MAX as f32 + dither;
+                writer
+                    .write_sample(scaled.round().max(i16::MIN as f32).min(i16::MAX as f32) as i16)?
+            }
+            WavBitDepth::Float32 => writer.write_sample(sample)?,
+        }
+    }
+    writer.finalize()?;
+    if let Some((start, end)) = settings.loop_points {
+        write_smpl_chunk(path, sample_rate, start, end)?;
+    }
+    Ok(())
+}
+
+fn write_smpl_chunk(
+    path: &Path,
+    sample_rate: u32,
+    loop_start: u32,
+    loop_end: u32,
+) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let fields: [u32; 15] = [
+        0,                           // manufacturer
+        0,                           // product
+        1_000_000_000 / sample_rate, // sample period (ns)
+        60,                          // MIDI unity note
+        0,                           // MIDI pitch fraction
+        0,                           // SMPTE format
+        0,                           // SMPTE offset
+        1,                           // number of loops
+        0,                           // sampler data length
+        0,                           // loop cue point id
+        0,                           // loop type (forward)
+        loop_start,
+        loop_end,
+        0, // loop fraction
+        0, // loop play count (infinite)
+    ];
+    let mut chunk = Vec::with_capacity(8 + fields.len() * 4);
+    chunk.extend_from_slice(b"smpl");
+    chunk.extend_from_slice(&(fields.len() as u32 * 4).to_le_bytes());
+    for field in fields.iter() {
+        chunk.extend_from_slice(&field.to_le_bytes());
+    }
+    file.seek(SeekFrom::End(0))?;
+    file.write_all(&chunk)?;
+
+    let mut riff_len = [0u8; 4];
+    file.seek(SeekFrom::Start(4))?;
+    file.read_exact(&mut riff_len)?;
+    let riff_len = u32::from_le_bytes(riff_len) + chunk.len() as u32;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_len.to_le_bytes())
+}
+
+#[derive(Clone)]
+pub struct Transport(Vec<AudioOutput>);
+
+impl Transport {
+    pub(crate) fn new(outputs: Vec<AudioOutput>) -> Self {
+        Self(outputs)
+    }
+
+    pub fn start(&self) {
+        for output in self.0.iter() {
+            output.set_started(true);
+        }
+    }
+
+    pub fn pause(&self) {
+        for output in self.0.iter() {
+            output.set_started(false);
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.0.iter().all(|output| output.is_started())
+    }
+}
+
 impl Source for AudioOutput {
     fn current_frame_len(&self) -> Option<usize> {
-        return None;
+        None
     }
 
     fn channels(&self) -> u16 {
-        return 1;
+        self.0.channels
     }
 
     fn sample_rate(&self) -> u32 {
-        return SAMPLE_RATE;
+        self.0.sample_rate
     }
 
     fn total_duration(&self) -> Option<std::time::Duration> {
-        return None;
+        None
     }
 }
 
 pub(crate) struct AudioOutputModule {
     signal_in: BufferHandle<In<f32>>,
+    left_in: BufferHandle<In<f32>>,
+    right_in: BufferHandle<In<f32>>,
+    gain_in: BufferHandle<In<f32>>,
+    threshold_in: BufferHandle<In<f32>>,
     output: AudioOutput,
+    // limited output, sized once at init so the audio thread never allocates
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+#[derive(Clone)]
+pub(crate) struct AudioOutputSettings {
+    pub output: AudioOutput,
+    pub limiter_threshold: f32,
+}
+
+impl From<AudioOutput> for AudioOutputSettings {
+    fn from(output: AudioOutput) -> Self {
+        Self {
+            output,
+            limiter_threshold: 0.9,
+        }
+    }
+}
+
+fn soft_limit(x: f32, threshold: f32) -> f32 {
+    let threshold = threshold.clamp(0.0, 1.0);
+    let magnitude = x.abs();
+    if magnitude <= threshold {
+        x
+    } else if threshold >= 1.0 {
+        x.signum()
+    } else {
+        let knee = 1.0 - threshold;
+        x.signum() * (threshold + knee * ((magnitude - threshold) / knee).tanh())
+    }
 }
 
 impl ModuleSettings for AudioOutputModule {
-    type Settings = AudioOutput;
+    type Settings = AudioOutputSettings;
     type Error = Infallible;
 }
 
 impl Module for AudioOutputModule {
     fn init(
         mut desc: ModuleDescriptor,
-        output: AudioOutput,
+        settings: AudioOutputSettings,
         _: usize,
     ) -> Result<BuiltModuleDescriptor<Self>, Infallible> {
         let module = Self {
             signal_in: desc.with_buf_in::<f32>("in"),
-            output,
+            left_in: desc.with_buf_in::<f32>("in_left"),
+            right_in: desc.with_buf_in::<f32>("in_right"),
+            gain_in: desc.with_buf_in_default::<f32>("gain", 1.0),
+            threshold_in: desc.with_buf_in_default::<f32>("threshold", settings.limiter_threshold),
+            output: settings.output,
+            left: vec![0.0; desc.buffer_len()],
+            right: vec![0.0; desc.buffer_len()],
         };
         Ok(desc.build(module))
     }
@@ -153,6 +543,167 @@ impl Module for AudioOutputModule {
         buffers_in: &crate::host::ModuleBuffersIn,
         _buffers_out: &mut crate::host::ModuleBuffersOut,
     ) {
-        self.output.write(buffers_in.get(self.signal_in));
+        let signal_in = buffers_in.get(self.signal_in);
+        let gain_in = buffers_in.get(self.gain_in);
+        let threshold_in = buffers_in.get(self.threshold_in);
+        let left_in = buffers_in.get(self.left_in);
+        let right_in = buffers_in.get(self.right_in);
+        for i in 0..self.left.len() {
+            self.left[i] = soft_limit((left_in[i] + signal_in[i]) * gain_in[i], threshold_in[i]);
+            self.right[i] = soft_limit((right_in[i] + signal_in[i]) * gain_in[i], threshold_in[i]);
+        }
+        self.output.write(&self.left, &self.right);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_silent_until_started() {
+        let output = AudioOutput::new(44100, 4);
+        let transport = Transport::new(vec![output.clone()]);
+        let mut reader = output.clone();
+
+        // the reader trails the writer by one block
+        output.write(&[0.5; 4], &[0.5; 4]);
+        reader.by_ref().take(8).for_each(drop);
+        output.write(&[0.5; 4], &[0.5; 4]);
+        assert!(!transport.is_started());
+        assert!(reader.by_ref().take(8).all(|x| x == 0.0));
+
+        transport.start();
+        output.write(&[0.5; 4], &[0.5; 4]);
+        assert!(transport.is_started());
+        assert!(reader.by_ref().take(8).all(|x| x == 0.5));
+    }
+
+    #[test]
+    fn output_only_fills_its_channels() {
+        let output = AudioOutput::with_channels(2..3, 44100, 4);
+        Transport::new(vec![output.clone()]).start();
+        let mut reader = output.clone();
+        assert_eq!(reader.channels(), 3);
+
+        // the reader trails the writer by one block
+        output.write(&[0.5; 4], &[0.25; 4]);
+        reader.by_ref().take(12).for_each(drop);
+        output.write(&[0.5; 4], &[0.25; 4]);
+        let frames = reader.take(12).collect::<Vec<_>>();
+        for frame in frames.chunks(3) {
+            assert_eq!(frame, &[0.0, 0.0, 0.5]);
+        }
+    }
+
+    #[test]
+    fn soft_limit_stays_under_full_scale() {
+        assert_eq!(soft_limit(0.5, 0.9), 0.5);
+        assert!(soft_limit(10.0, 0.9) <= 1.0);
+        assert!(soft_limit(0.95, 0.9) > 0.9);
+        assert_eq!(soft_limit(-3.0, 1.0), -1.0);
+    }
+
+    #[test]
+    fn normalize_hits_the_target_peak() {
+        let mut samples = vec![0.1, -0.25, 0.2];
+        normalize(&mut samples, -6.0);
+        let peak = samples.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+        assert!((peak - 10.0f32.powf(-6.0 / 20.0)).abs() < 1e-6);
+        assert!((samples[0] / samples[2] - 0.5).abs() < 1e-6);
+
+        let mut silence = vec![0.0; 4];
+        normalize(&mut silence, 0.0);
+        assert!(silence.iter().all(|&x| x == 0.0));
+    }
+
+    fn temp_wav(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rustsynth_{}.wav", name))
+    }
+
+    fn read_i16(path: &Path) -> Vec<i16> {
+        hound::WavReader::open(path)
+            .unwrap()
+            .into_samples::<i16>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn dither_keeps_quiet_signals_alive() {
+        // well under one 16-bit step, so it rounds away entirely without dither
+        let samples = vec![0.4 / i16::MAX as f32; 1000];
+        let path = temp_wav("dither_keeps_quiet_signals_alive");
+        let mut settings = WavSettings {
+            bit_depth: WavBitDepth::Int16,
+            dither: false,
+            ..Default::default()
+        };
+        write_wav(&path, &samples, 1, 44100, &settings).unwrap();
+        assert!(read_i16(&path).iter().all(|&x| x == 0));
+
+        settings.dither = true;
+        write_wav(&path, &samples, 1, 44100, &settings).unwrap();
+        let dithered = read_i16(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(dithered.iter().any(|&x| x != 0));
+        assert!(dithered.iter().all(|&x| x.abs() <= 2));
+    }
+
+    #[test]
+    fn loop_points_are_written_to_a_smpl_chunk() {
+        let path = temp_wav("loop_points_are_written_to_a_smpl_chunk");
+        let settings = WavSettings {
+            loop_points: Some((10, 90)),
+            ..Default::default()
+        };
+        write_wav(&path, &[0.25; 100], 1, 44100, &settings).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let samples = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(samples, vec![0.25; 100]);
+        let riff_len = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        assert_eq!(riff_len as usize, bytes.len() - 8);
+        let smpl = bytes.windows(4).position(|w| w == b"smpl").unwrap();
+        let field = |i: usize| {
+            let at = smpl + 8 + i * 4;
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        assert_eq!((field(7), field(11), field(12)), (1, 10, 90));
+    }
+
+    #[test]
+    fn recorder_round_trips_each_channel() {
+        let path = temp_wav("recorder_round_trips_each_channel");
+        let (recorder, handle) = Recorder::start(&path, 3, 44100, 16).unwrap();
+        let sine = |i: usize| (i as f32 * 0.3).sin() * 0.5;
+        let ramp = |i: usize| i as f32 / 64.0;
+        for block in 0..4 {
+            let indices = (block * 16..(block + 1) * 16).collect::<Vec<_>>();
+            let left = indices.iter().map(|&i| sine(i)).collect::<Vec<_>>();
+            let right = indices.iter().map(|&i| ramp(i)).collect::<Vec<_>>();
+            recorder.add(&(0..2), &left, &right);
+            recorder.add(&(2..3), &right, &left);
+            recorder.flush();
+        }
+        drop(recorder);
+        handle.join().unwrap().unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 3);
+        let samples = reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples.len(), 64 * 3);
+        for (i, frame) in samples.chunks(3).enumerate() {
+            assert_eq!(frame, &[sine(i), ramp(i), ramp(i)]);
+        }
     }
 }